@@ -0,0 +1,44 @@
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN normalised_email TEXT NULL;
+
+    -- Backfill `normalised_email` for historical entries.
+    -- This mirrors `SubscriberEmail::parse_with_policy` for the default
+    -- `email_policy.plus_addressing_domains`; IDN domains are only lowercased.
+    UPDATE subscriptions
+        SET normalised_email = CASE
+            WHEN lower(substring(trim(email) from '@([^@]*)$'))
+                IN ('gmail.com', 'googlemail.com', 'outlook.com', 'hotmail.com', 'fastmail.com')
+            THEN regexp_replace(substring(trim(email) from '^(.*)@[^@]*$'), '\+.*$', '')
+            ELSE substring(trim(email) from '^(.*)@[^@]*$')
+        END || '@' || lower(substring(trim(email) from '@([^@]*)$'));
+
+    -- Merge duplicates, keeping a confirmed subscription over a pending one
+    -- and the oldest subscription otherwise.
+    CREATE TEMPORARY TABLE subscription_merges ON COMMIT DROP AS
+        SELECT
+            id AS duplicate_id,
+            first_value(id) OVER (
+                PARTITION BY lower(normalised_email)
+                ORDER BY (status = 'confirmed') DESC, subscribed_at ASC, id ASC
+            ) AS keeper_id
+        FROM subscriptions;
+    DELETE FROM subscription_merges WHERE duplicate_id = keeper_id;
+
+    UPDATE subscription_tokens
+        SET subscriber_id = m.keeper_id
+        FROM subscription_merges m
+        WHERE subscriber_id = m.duplicate_id;
+    -- A confirmed duplicate implies a confirmed keeper, which already has
+    -- its own delivery tasks for the same issues.
+    DELETE FROM issue_delivery_queue q
+        USING subscription_merges m, subscriptions s
+        WHERE s.id = m.duplicate_id
+        AND q.subscriber_email = s.email;
+    DELETE FROM subscriptions s
+        USING subscription_merges m
+        WHERE s.id = m.duplicate_id;
+
+    ALTER TABLE subscriptions ALTER COLUMN normalised_email SET NOT NULL;
+    CREATE UNIQUE INDEX subscriptions_normalised_email_key
+        ON subscriptions (lower(normalised_email));
+COMMIT;
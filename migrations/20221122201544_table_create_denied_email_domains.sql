@@ -0,0 +1,6 @@
+CREATE TABLE denied_email_domains (
+   domain TEXT NOT NULL,
+   added_by uuid NOT NULL REFERENCES users (id),
+   added_at timestamptz NOT NULL,
+   PRIMARY KEY (domain)
+);
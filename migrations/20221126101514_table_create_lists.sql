@@ -0,0 +1,35 @@
+BEGIN;
+    CREATE TABLE lists (
+        list_id uuid NOT NULL,
+        slug TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        created_at timestamptz NOT NULL,
+        PRIMARY KEY (list_id)
+    );
+    -- Everything published so far went to a single implicit list.
+    INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+
+    CREATE TABLE list_memberships (
+        list_id uuid NOT NULL REFERENCES lists (list_id),
+        subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        status TEXT NOT NULL,
+        subscribed_at timestamptz NOT NULL,
+        PRIMARY KEY (list_id, subscriber_id)
+    );
+    INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        SELECT l.list_id, s.id, s.status, s.subscribed_at
+        FROM subscriptions s, lists l
+        WHERE l.slug = 'newsletter';
+
+    -- A confirmation token confirms the membership it was sent for.
+    ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE subscription_tokens
+        SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+    ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+    ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE newsletter_issues
+        SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+    ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
@@ -0,0 +1,24 @@
+BEGIN;
+    CREATE TABLE subscriber_tags (
+        subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        added_at timestamptz NOT NULL,
+        PRIMARY KEY (subscriber_id, tag)
+    );
+    CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+    -- Set when the email provider reports a hard bounce for the address.
+    ALTER TABLE subscriptions ADD COLUMN bounced_at timestamptz NULL;
+
+    CREATE TABLE segments (
+        segment_id uuid NOT NULL,
+        name TEXT NOT NULL UNIQUE,
+        expression TEXT NOT NULL,
+        created_at timestamptz NOT NULL,
+        updated_at timestamptz NOT NULL,
+        PRIMARY KEY (segment_id)
+    );
+
+    -- The segment an issue was sent to, as it read at publish time.
+    ALTER TABLE newsletter_issues ADD COLUMN segment_expression TEXT NULL;
+COMMIT;
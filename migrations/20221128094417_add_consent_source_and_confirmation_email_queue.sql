@@ -0,0 +1,12 @@
+BEGIN;
+    -- Where consent was collected for memberships that skipped the
+    -- confirmation email, e.g. "Mailchimp double opt-in, exported 2022-11-28".
+    ALTER TABLE list_memberships ADD COLUMN consent_source TEXT NULL;
+
+    CREATE TABLE confirmation_email_queue (
+        subscription_token TEXT NOT NULL
+            REFERENCES subscription_tokens (subscription_token) ON DELETE CASCADE,
+        enqueued_at timestamptz NOT NULL,
+        PRIMARY KEY (subscription_token)
+    );
+COMMIT;
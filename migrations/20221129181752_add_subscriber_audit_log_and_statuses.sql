@@ -0,0 +1,31 @@
+BEGIN;
+    CREATE TABLE subscriber_audit_log (
+        audit_log_id uuid NOT NULL,
+        -- Not a foreign key: entries outlive the subscribers they describe.
+        subscriber_id uuid NOT NULL,
+        subscriber_email TEXT NOT NULL,
+        action TEXT NOT NULL,
+        performed_by uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+        performed_at timestamptz NOT NULL,
+        PRIMARY KEY (audit_log_id)
+    );
+    CREATE INDEX subscriber_audit_log_subscriber_id_idx
+        ON subscriber_audit_log (subscriber_id, performed_at);
+
+    -- Subscribers who left every list they joined are unsubscribed, whatever
+    -- the status of their address.
+    CREATE VIEW subscriber_statuses AS
+    SELECT
+        s.id,
+        s.email,
+        s.name,
+        s.subscribed_at,
+        CASE
+            WHEN NOT EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.subscriber_id = s.id AND m.status <> 'unsubscribed'
+            ) THEN 'unsubscribed'
+            ELSE s.status
+        END AS status
+    FROM subscriptions s;
+COMMIT;
@@ -0,0 +1,15 @@
+BEGIN;
+    -- Drafts are neither published nor queued for delivery.
+    ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+    UPDATE newsletter_issues SET status = 'published';
+    ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+
+    -- The saved segment picked while writing a draft; it is resolved to
+    -- `segment_expression` when the draft is published.
+    ALTER TABLE newsletter_issues ADD COLUMN segment_name TEXT NULL;
+
+    ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NULL;
+    UPDATE newsletter_issues SET updated_at = now();
+    ALTER TABLE newsletter_issues ALTER COLUMN updated_at SET NOT NULL;
+COMMIT;
@@ -0,0 +1,12 @@
+BEGIN;
+    CREATE TABLE newsletter_layouts (
+        layout_id uuid NOT NULL,
+        name TEXT NOT NULL UNIQUE,
+        html_template TEXT NOT NULL,
+        text_template TEXT NOT NULL,
+        updated_at timestamptz NOT NULL,
+        PRIMARY KEY (layout_id)
+    );
+    ALTER TABLE newsletter_issues
+        ADD COLUMN layout_id uuid NULL REFERENCES newsletter_layouts (layout_id);
+COMMIT;
@@ -0,0 +1,10 @@
+BEGIN;
+    -- Published issues are readable online at /issues/{slug}.
+    ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+    UPDATE newsletter_issues
+    SET slug = trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g'))
+        || '-' || left(newsletter_issue_id::text, 8)
+    WHERE status = 'published';
+
+    ALTER TABLE newsletter_issues ADD COLUMN hidden_from_archive BOOLEAN NOT NULL DEFAULT false;
+COMMIT;
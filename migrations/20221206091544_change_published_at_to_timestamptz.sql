@@ -0,0 +1,3 @@
+-- published_at has always been set with now(), so it converts cleanly.
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
@@ -0,0 +1,10 @@
+BEGIN;
+    -- Matches in the title rank above matches in the content.
+    ALTER TABLE newsletter_issues ADD COLUMN search_vector tsvector
+        GENERATED ALWAYS AS (
+            setweight(to_tsvector('english', title), 'A')
+            || setweight(to_tsvector('english', text_content), 'B')
+        ) STORED;
+    CREATE INDEX newsletter_issues_search_vector_idx
+        ON newsletter_issues USING GIN (search_vector);
+COMMIT;
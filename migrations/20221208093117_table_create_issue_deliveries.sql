@@ -0,0 +1,29 @@
+BEGIN;
+    -- What happened to each delivery task once it left `issue_delivery_queue`.
+    CREATE TABLE issue_deliveries (
+        newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+        subscriber_email TEXT NOT NULL,
+        -- Either `delivered` or `failed`.
+        outcome TEXT NOT NULL,
+        error TEXT NULL,
+        attempted_at timestamptz NOT NULL,
+        PRIMARY KEY (newsletter_issue_id, subscriber_email)
+    );
+
+    CREATE VIEW issue_delivery_progress AS
+    SELECT
+        i.newsletter_issue_id,
+        (
+            SELECT count(*) FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = i.newsletter_issue_id
+        ) AS queued,
+        (
+            SELECT count(*) FROM issue_deliveries d
+            WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'delivered'
+        ) AS delivered,
+        (
+            SELECT count(*) FROM issue_deliveries d
+            WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'failed'
+        ) AS failed
+    FROM newsletter_issues i;
+COMMIT;
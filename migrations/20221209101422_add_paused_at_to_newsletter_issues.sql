@@ -0,0 +1,2 @@
+-- The worker leaves the delivery tasks of paused issues in the queue.
+ALTER TABLE newsletter_issues ADD COLUMN paused_at timestamptz NULL;
@@ -0,0 +1,6 @@
+BEGIN;
+    -- Issues can be sent to those who confirmed after they were published.
+    ALTER TABLE list_memberships ADD COLUMN confirmed_at timestamptz NULL;
+    -- The best guess for memberships confirmed so far.
+    UPDATE list_memberships SET confirmed_at = subscribed_at WHERE status = 'confirmed';
+COMMIT;
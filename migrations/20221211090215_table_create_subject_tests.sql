@@ -0,0 +1,68 @@
+BEGIN;
+    -- Subjects tried on a share of the audience of an issue, the best of
+    -- which is then sent to the rest.
+    CREATE TABLE subject_tests (
+        newsletter_issue_id uuid PRIMARY KEY REFERENCES newsletter_issues (newsletter_issue_id),
+        -- The percentage of the audience each variant is sent to.
+        variant_share SMALLINT NOT NULL CHECK (variant_share BETWEEN 1 AND 25),
+        -- Either `opens` or `clicks`.
+        metric TEXT NOT NULL,
+        decide_at timestamptz NOT NULL,
+        winning_variant SMALLINT NULL,
+        decided_at timestamptz NULL
+    );
+
+    -- Variant 0 is the title of the issue.
+    CREATE TABLE subject_variants (
+        newsletter_issue_id uuid NOT NULL REFERENCES subject_tests (newsletter_issue_id),
+        variant SMALLINT NOT NULL,
+        subject TEXT NOT NULL,
+        PRIMARY KEY (newsletter_issue_id, variant)
+    );
+
+    -- The variant each subscriber in the test share is sent. The rest of the
+    -- audience, left NULL, waits for the winner.
+    ALTER TABLE issue_delivery_queue ADD COLUMN variant SMALLINT NULL;
+    ALTER TABLE issue_deliveries ADD COLUMN variant SMALLINT NULL;
+
+    -- Opens and clicks of tested issues, once per subscriber.
+    CREATE TABLE issue_engagements (
+        newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+        subscriber_email TEXT NOT NULL,
+        -- Either `open` or `click`.
+        kind TEXT NOT NULL,
+        engaged_at timestamptz NOT NULL,
+        PRIMARY KEY (newsletter_issue_id, subscriber_email, kind)
+    );
+
+    CREATE VIEW subject_variant_results AS
+    SELECT
+        v.newsletter_issue_id,
+        v.variant,
+        v.subject,
+        (
+            SELECT count(*) FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = v.newsletter_issue_id AND q.variant = v.variant
+        ) + (
+            SELECT count(*) FROM issue_deliveries d
+            WHERE d.newsletter_issue_id = v.newsletter_issue_id AND d.variant = v.variant
+        ) AS assigned,
+        (
+            SELECT count(*) FROM issue_deliveries d
+            WHERE d.newsletter_issue_id = v.newsletter_issue_id
+            AND d.variant = v.variant AND d.outcome = 'delivered'
+        ) AS delivered,
+        (
+            SELECT count(*) FROM issue_deliveries d
+            JOIN issue_engagements e USING (newsletter_issue_id, subscriber_email)
+            WHERE d.newsletter_issue_id = v.newsletter_issue_id
+            AND d.variant = v.variant AND e.kind = 'open'
+        ) AS opens,
+        (
+            SELECT count(*) FROM issue_deliveries d
+            JOIN issue_engagements e USING (newsletter_issue_id, subscriber_email)
+            WHERE d.newsletter_issue_id = v.newsletter_issue_id
+            AND d.variant = v.variant AND e.kind = 'click'
+        ) AS clicks
+    FROM subject_variants v;
+COMMIT;
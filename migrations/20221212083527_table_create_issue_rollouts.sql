@@ -0,0 +1,22 @@
+BEGIN;
+    -- Issues sent to a growing share of their audience, checking between
+    -- stages that few enough emails failed.
+    CREATE TABLE issue_rollouts (
+        newsletter_issue_id uuid PRIMARY KEY REFERENCES newsletter_issues (newsletter_issue_id),
+        -- The percentage of the audience sent to by the end of each stage,
+        -- the last being 100.
+        stages SMALLINT[] NOT NULL,
+        -- The percentage of failed deliveries in a stage over which the issue
+        -- is paused.
+        max_failure_rate float8 NOT NULL,
+        current_stage SMALLINT NOT NULL DEFAULT 0,
+        -- Set when the current stage failed the check, until an admin
+        -- resumes the issue.
+        halted_at timestamptz NULL,
+        halted_failure_rate float8 NULL
+    );
+
+    -- The stage each delivery belongs to, for issues sent in stages.
+    ALTER TABLE issue_delivery_queue ADD COLUMN stage SMALLINT NULL;
+    ALTER TABLE issue_deliveries ADD COLUMN stage SMALLINT NULL;
+COMMIT;
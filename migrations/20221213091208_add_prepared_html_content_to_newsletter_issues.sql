@@ -0,0 +1,3 @@
+-- The HTML part of a scheduled issue as it will be sent, checked when it was
+-- scheduled. The authored one stays in `html_content` until it is published.
+ALTER TABLE newsletter_issues ADD COLUMN prepared_html_content TEXT NULL;
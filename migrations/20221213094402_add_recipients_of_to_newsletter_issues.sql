@@ -0,0 +1,4 @@
+-- The earlier issue whose recipients, and only them, get this one, e.g. to
+-- correct it. Overrides the list and segment.
+ALTER TABLE newsletter_issues
+    ADD COLUMN recipients_of uuid NULL REFERENCES newsletter_issues (newsletter_issue_id);
@@ -0,0 +1,7 @@
+-- The subject test picked with an issue, started once it is published. The
+-- alternatives to the title are kept as entered, blank ones included.
+ALTER TABLE newsletter_issues
+    ADD COLUMN test_subjects TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN test_share SMALLINT NULL,
+    ADD COLUMN test_wait_hours SMALLINT NULL,
+    ADD COLUMN test_metric TEXT NULL;
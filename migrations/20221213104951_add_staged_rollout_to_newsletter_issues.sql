@@ -0,0 +1,2 @@
+-- Whether the issue is sent in stages once published, see `issue_rollouts`.
+ALTER TABLE newsletter_issues ADD COLUMN staged_rollout BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,7 @@
+BEGIN;
+    -- When the email provider reported a delivery as a hard bounce.
+    ALTER TABLE issue_deliveries ADD COLUMN bounced_at timestamptz NULL;
+    -- How long to wait after the last email of a stage was sent before
+    -- checking it, for bounces to come in.
+    ALTER TABLE issue_rollouts ADD COLUMN settle_minutes INTEGER NOT NULL DEFAULT 0;
+COMMIT;
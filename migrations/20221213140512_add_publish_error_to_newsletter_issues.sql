@@ -0,0 +1,3 @@
+-- Why the scheduler could not publish a scheduled issue. Such issues are
+-- skipped until they are rescheduled or turned back into drafts.
+ALTER TABLE newsletter_issues ADD COLUMN publish_error TEXT NULL;
@@ -0,0 +1,27 @@
+//! Recompute the normalised email of subscriptions with non-ASCII addresses,
+//! merging the duplicates found. Run it once, after the migrations, from a
+//! single instance: it exits with an error if anything fails, leaving the
+//! subscriptions as they were.
+use zero2prod::configuration::get_configuration;
+use zero2prod::domain::EmailPolicy;
+use zero2prod::normalised_emails::backfill_normalised_emails;
+use zero2prod::startup::get_connection_pool;
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let subscriber = get_subscriber(
+        "backfill_normalised_emails".into(),
+        "info".into(),
+        std::io::stdout,
+    );
+    init_subscriber(subscriber);
+
+    let configuration = get_configuration().expect("Failed to read configuration.");
+    let connection_pool = get_connection_pool(&configuration.database);
+    // Only the normalisation rules apply: rows at denied domains are kept.
+    let normalisation_policy = EmailPolicy::new(configuration.email_policy.plus_addressing_domains);
+    let n_fixed = backfill_normalised_emails(&connection_pool, &normalisation_policy).await?;
+    tracing::info!(n_fixed, "The normalised emails are backfilled");
+    Ok(())
+}
@@ -8,13 +8,15 @@ use sqlx::postgres::PgSslMode;
 use sqlx::ConnectOptions;
 use std::env;
 
-use crate::domain::SubscriberEmail;
+use crate::domain::{EmailPolicy, SubscriberEmail};
 
 #[derive(Clone, serde::Deserialize)]
 pub struct Configuration {
     pub application: ApplicationConfiguration,
     pub database: DatabaseConfiguration,
     pub email_client: EmailClientConfiguration,
+    pub email_policy: EmailPolicyConfiguration,
+    pub rollout: RolloutConfiguration,
     pub redis_uri: Secret<String>,
 }
 
@@ -45,6 +47,45 @@ pub struct EmailClientConfiguration {
     pub authorization_token: Secret<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_milliseconds: u64,
+    // Whether the provider can deliver to non-ASCII addresses (RFC 6531).
+    pub supports_smtputf8: bool,
+    // Expected in the URL the provider posts bounces and other events to.
+    pub events_token: Secret<String>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct EmailPolicyConfiguration {
+    // Domains where `user+tag@domain` delivers to `user@domain`.
+    pub plus_addressing_domains: Vec<String>,
+    pub block_disposable_domains: bool,
+    pub denied_domains: Vec<String>,
+    // When non-empty, only these domains may subscribe.
+    pub allowed_domains: Vec<String>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct RolloutConfiguration {
+    // The percentage of the audience sent to by the end of each stage of a
+    // staged send.
+    pub stages: Vec<u8>,
+    // The percentage of failed or bounced deliveries in a stage over which
+    // the send is paused.
+    pub max_failure_rate: f64,
+    // How long to wait after the last email of a stage was sent before
+    // checking it, for the email provider to report bounces.
+    pub settle_minutes: u16,
+}
+
+impl RolloutConfiguration {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stages.last() != Some(&100) {
+            return Err("The last stage of a staged send must reach 100% of the audience.".into());
+        }
+        if self.stages.windows(2).any(|w| w[0] >= w[1]) || self.stages[0] == 0 {
+            return Err("The stages of a staged send must grow from more than 0%.".into());
+        }
+        Ok(())
+    }
 }
 
 pub fn get_configuration() -> Result<Configuration, config::ConfigError> {
@@ -83,7 +124,7 @@ impl DatabaseConfiguration {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -115,3 +156,22 @@ impl EmailClientConfiguration {
         )
     }
 }
+
+impl EmailPolicyConfiguration {
+    pub fn policy(self, transport_supports_smtputf8: bool) -> EmailPolicy {
+        let policy = EmailPolicy::new(self.plus_addressing_domains);
+        let policy = if self.block_disposable_domains {
+            policy.block_disposable_domains()
+        } else {
+            policy
+        };
+        let policy = if transport_supports_smtputf8 {
+            policy
+        } else {
+            policy.ascii_only()
+        };
+        policy
+            .deny_domains(self.denied_domains)
+            .allow_only_domains(self.allowed_domains)
+    }
+}
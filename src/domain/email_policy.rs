@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+
+const DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// Rules applied when parsing and normalising subscriber email addresses.
+#[derive(Debug, Clone, Default)]
+pub struct EmailPolicy {
+    plus_addressing_domains: HashSet<String>,
+    denied_domains: HashSet<String>,
+    // `None` accepts every domain that is not denied.
+    allowed_domains: Option<HashSet<String>>,
+    // Set when the email transport cannot deliver to non-ASCII addresses.
+    ascii_only: bool,
+}
+
+impl EmailPolicy {
+    pub fn new(plus_addressing_domains: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            plus_addressing_domains: plus_addressing_domains
+                .into_iter()
+                .map(|d| d.trim().to_lowercase())
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Reject addresses hosted by the bundled list of throwaway providers.
+    pub fn block_disposable_domains(mut self) -> Self {
+        self.denied_domains.extend(
+            DISPOSABLE_DOMAINS
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_string),
+        );
+        self
+    }
+
+    pub fn deny_domains(mut self, domains: impl IntoIterator<Item = String>) -> Self {
+        self.denied_domains
+            .extend(domains.into_iter().map(|d| d.trim().to_lowercase()));
+        self
+    }
+
+    /// Only accept addresses hosted by `domains`, e.g. for internal newsletters.
+    /// An empty list leaves the policy open to every domain.
+    pub fn allow_only_domains(mut self, domains: impl IntoIterator<Item = String>) -> Self {
+        let domains: HashSet<String> = domains
+            .into_iter()
+            .map(|d| d.trim().to_lowercase())
+            .collect();
+        self.allowed_domains = if domains.is_empty() {
+            None
+        } else {
+            Some(domains)
+        };
+        self
+    }
+
+    /// Reject non-ASCII local parts and Unicode domains, for transports that
+    /// do not support SMTPUTF8.
+    pub fn ascii_only(mut self) -> Self {
+        self.ascii_only = true;
+        self
+    }
+
+    pub fn allows_smtputf8(&self) -> bool {
+        !self.ascii_only
+    }
+
+    /// Whether `user+tag@domain` should be treated as the same mailbox as
+    /// `user@domain`. `domain` is expected in its normalised (ASCII) form.
+    pub fn canonicalises_plus_addressing(&self, domain: &str) -> bool {
+        self.plus_addressing_domains.contains(domain)
+    }
+
+    /// Check a normalised (ASCII) domain against the deny and allow lists.
+    /// Entries match the domain itself as well as any of its subdomains.
+    pub fn check_domain(&self, domain: &str) -> Result<(), String> {
+        let mut suffixes = domain_suffixes(domain);
+        if suffixes.any(|d| self.denied_domains.contains(d)) {
+            return Err(domain_rejection(domain));
+        }
+        if let Some(allowed_domains) = &self.allowed_domains {
+            if !domain_suffixes(domain).any(|d| allowed_domains.contains(d)) {
+                return Err(domain_rejection(domain));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The message returned to a subscriber whose domain is not accepted.
+pub fn domain_rejection(domain: &str) -> String {
+    format!("Email addresses at {} cannot be used to subscribe.", domain)
+}
+
+/// `a.b.example.com` yields `a.b.example.com`, `b.example.com`, `example.com`.
+pub fn domain_suffixes(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |d| d.split_once('.').map(|(_, rest)| rest))
+        .filter(|d| d.contains('.'))
+}
+
+/// Parse a bare domain, e.g. one entered by an admin, into its normalised
+/// (lowercase, punycode) form.
+pub fn parse_domain(s: &str) -> Result<String, String> {
+    let s = s.trim().trim_start_matches('@');
+    match idna::domain_to_ascii(s) {
+        Ok(domain) if domain.contains('.') && !domain.starts_with('.') => Ok(domain),
+        _ => Err(format!("{} is not a valid domain.", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{domain_suffixes, parse_domain, EmailPolicy};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn the_default_policy_accepts_every_domain() {
+        assert_ok!(EmailPolicy::default().check_domain("mailinator.com"));
+    }
+
+    #[test]
+    fn bundled_disposable_domains_are_rejected() {
+        let policy = EmailPolicy::default().block_disposable_domains();
+        assert_err!(policy.check_domain("mailinator.com"));
+        assert_err!(policy.check_domain("yopmail.com"));
+        assert_ok!(policy.check_domain("gmail.com"));
+    }
+
+    #[test]
+    fn subdomains_of_a_denied_domain_are_rejected() {
+        let policy = EmailPolicy::default().deny_domains(vec!["Example.com".to_string()]);
+        assert_err!(policy.check_domain("example.com"));
+        assert_err!(policy.check_domain("mail.example.com"));
+        assert_ok!(policy.check_domain("notexample.com"));
+    }
+
+    #[test]
+    fn allow_only_mode_rejects_other_domains() {
+        let policy = EmailPolicy::default().allow_only_domains(vec!["zero2prod.dev".to_string()]);
+        assert_ok!(policy.check_domain("zero2prod.dev"));
+        assert_ok!(policy.check_domain("team.zero2prod.dev"));
+        assert_err!(policy.check_domain("gmail.com"));
+    }
+
+    #[test]
+    fn denied_domains_win_over_allowed_domains() {
+        let policy = EmailPolicy::default()
+            .allow_only_domains(vec!["zero2prod.dev".to_string()])
+            .deny_domains(vec!["old.zero2prod.dev".to_string()]);
+        assert_err!(policy.check_domain("old.zero2prod.dev"));
+    }
+
+    #[test]
+    fn an_empty_allow_list_accepts_every_domain() {
+        let policy = EmailPolicy::default().allow_only_domains(vec![]);
+        assert_ok!(policy.check_domain("gmail.com"));
+    }
+
+    #[test]
+    fn domain_suffixes_stop_before_the_top_level_domain() {
+        let suffixes: Vec<_> = domain_suffixes("a.b.example.com").collect();
+        assert_eq!(
+            suffixes,
+            vec!["a.b.example.com", "b.example.com", "example.com"]
+        );
+    }
+
+    #[test]
+    fn domains_are_parsed_into_their_normalised_form() {
+        assert_eq!(parse_domain(" @Bücher.DE ").unwrap(), "xn--bcher-kva.de");
+        assert_err!(parse_domain("localhost"));
+        assert_err!(parse_domain(""));
+    }
+}
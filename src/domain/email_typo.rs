@@ -0,0 +1,128 @@
+// Popular mailbox providers we suggest when a domain looks like a typo of one.
+// Legitimate domains that are a small edit away from another entry (e.g.
+// `mail.com` and `gmail.com`) must be listed so they are never "corrected".
+const POPULAR_DOMAINS: &[&str] = &[
+    "aol.com",
+    "btinternet.com",
+    "comcast.net",
+    "email.com",
+    "fastmail.com",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "googlemail.com",
+    "hey.com",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.fr",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "verizon.net",
+    "web.de",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.fr",
+    "yandex.com",
+    "yandex.ru",
+    "ymail.com",
+    "zoho.com",
+];
+
+/// Suggest the popular domain `domain` was most likely meant to be, if it
+/// is a close misspelling of one (e.g. `gmial.com` -> `gmail.com`).
+/// `domain` is expected in its normalised (lowercase) form.
+pub fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if POPULAR_DOMAINS.contains(&domain) {
+        return None;
+    }
+    // A single slip only: two edits away from a popular domain there are
+    // real providers (`yahoo.de` for `yahoo.fr`, `outlook.de`, `gmx.at`).
+    POPULAR_DOMAINS
+        .iter()
+        .find(|candidate| edit_distance(domain, candidate) == 1)
+        .copied()
+}
+
+// Optimal string alignment distance: Levenshtein plus adjacent transpositions,
+// which covers the most common typing mistakes (`gmial`, `yhaoo`).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, suggest_domain};
+    use claim::{assert_none, assert_some_eq};
+
+    #[test]
+    fn transpositions_count_as_a_single_edit() {
+        assert_eq!(edit_distance("gmial.com", "gmail.com"), 1);
+    }
+
+    #[test]
+    fn common_typos_are_corrected() {
+        assert_some_eq!(suggest_domain("gmial.com"), "gmail.com");
+        assert_some_eq!(suggest_domain("yaho.com"), "yahoo.com");
+        assert_some_eq!(suggest_domain("gmail.con"), "gmail.com");
+        assert_some_eq!(suggest_domain("hotmial.com"), "hotmail.com");
+    }
+
+    #[test]
+    fn popular_domains_are_left_alone() {
+        assert_none!(suggest_domain("gmail.com"));
+        assert_none!(suggest_domain("mail.com"));
+        assert_none!(suggest_domain("gmx.com"));
+    }
+
+    #[test]
+    fn real_domains_close_to_a_popular_one_are_left_alone() {
+        for domain in [
+            "email.com",
+            "yahoo.de",
+            "yahoo.ca",
+            "yahoo.es",
+            "hotmail.de",
+            "hotmail.it",
+            "outlook.de",
+            "gmx.at",
+            "gmx.net",
+            "aim.com",
+            "yandex.ua",
+        ] {
+            assert_none!(suggest_domain(domain), "{} was corrected", domain);
+        }
+    }
+
+    #[test]
+    fn unrelated_domains_are_left_alone() {
+        assert_none!(suggest_domain("zero2prod.dev"));
+        assert_none!(suggest_domain("example.com"));
+    }
+}
@@ -0,0 +1,59 @@
+/// The identifier a mailing list is addressed by, e.g. on `POST /subscriptions`.
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+/// The list that existing subscribers and issues were migrated to.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_valid = !s.is_empty()
+            && s.len() <= 64
+            && !s.starts_with('-')
+            && !s.ends_with('-')
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!(
+                "{} is not a valid list slug: use lowercase letters, digits and dashes.",
+                s
+            ))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_slugs_with_dashes_are_valid() {
+        assert_ok!(ListSlug::parse("product-updates-2023".to_string()));
+    }
+
+    #[test]
+    fn empty_slugs_are_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn slugs_longer_than_64_characters_are_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn uppercase_letters_spaces_and_symbols_are_rejected() {
+        for slug in ["News", "product updates", "news/letter", "-news", "news-"] {
+            assert_err!(ListSlug::parse(slug.to_string()), "{}", slug);
+        }
+    }
+}
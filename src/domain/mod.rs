@@ -1,7 +1,17 @@
+mod email_policy;
+mod email_typo;
+mod list_slug;
 mod new_subscriber;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 
+pub use email_policy::{domain_rejection, domain_suffixes, parse_domain, EmailPolicy};
+pub use email_typo::suggest_domain;
+pub use list_slug::{ListSlug, DEFAULT_LIST_SLUG};
 pub use new_subscriber::NewSubscriber;
+pub use subscriber_attributes::SubscriberAttributes;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
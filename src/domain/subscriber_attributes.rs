@@ -0,0 +1,127 @@
+use serde_json::{Map, Value};
+
+const MAX_ATTRIBUTES: usize = 50;
+const MAX_KEY_LENGTH: usize = 64;
+const MAX_VALUE_LENGTH: usize = 1024;
+
+/// Custom per-subscriber data, available to newsletter merge tags as
+/// `{{ attributes.<key> }}`.
+#[derive(Debug, Default, Clone)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn parse(value: Value) -> Result<SubscriberAttributes, String> {
+        let attributes = match value {
+            Value::Null => return Ok(Self::default()),
+            Value::Object(attributes) => attributes,
+            _ => return Err("Subscriber attributes must be a JSON object.".into()),
+        };
+        if attributes.len() > MAX_ATTRIBUTES {
+            return Err(format!(
+                "A subscriber cannot have more than {} attributes.",
+                MAX_ATTRIBUTES
+            ));
+        }
+        for (key, value) in &attributes {
+            let is_valid_key = !key.is_empty()
+                && key.len() <= MAX_KEY_LENGTH
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_valid_key {
+                return Err(format!(
+                    "{} is not a valid attribute name: use up to {} letters, digits or underscores.",
+                    key, MAX_KEY_LENGTH
+                ));
+            }
+            match value {
+                Value::String(s) if s.chars().count() > MAX_VALUE_LENGTH => {
+                    return Err(format!(
+                        "The value of attribute {} is longer than {} characters.",
+                        key, MAX_VALUE_LENGTH
+                    ));
+                }
+                Value::Array(_) | Value::Object(_) => {
+                    return Err(format!(
+                        "The value of attribute {} must be a string, number, boolean or null.",
+                        key
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(Self(attributes))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+}
+
+impl AsRef<Map<String, Value>> for SubscriberAttributes {
+    fn as_ref(&self) -> &Map<String, Value> {
+        &self.0
+    }
+}
+
+impl From<SubscriberAttributes> for Value {
+    fn from(attributes: SubscriberAttributes) -> Self {
+        Value::Object(attributes.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberAttributes;
+    use claim::{assert_err, assert_ok};
+    use serde_json::json;
+
+    #[test]
+    fn null_is_parsed_as_no_attributes() {
+        let attributes = SubscriberAttributes::parse(json!(null)).unwrap();
+        assert!(attributes.as_ref().is_empty());
+    }
+
+    #[test]
+    fn scalar_values_are_accepted() {
+        assert_ok!(SubscriberAttributes::parse(json!({
+            "company": "Acme",
+            "seats": 12,
+            "beta": true,
+            "plan": null,
+        })));
+    }
+
+    #[test]
+    fn non_object_attributes_are_rejected() {
+        assert_err!(SubscriberAttributes::parse(json!(["company"])));
+        assert_err!(SubscriberAttributes::parse(json!("company")));
+    }
+
+    #[test]
+    fn nested_values_are_rejected() {
+        assert_err!(SubscriberAttributes::parse(
+            json!({ "company": { "name": "Acme" } })
+        ));
+        assert_err!(SubscriberAttributes::parse(json!({ "tags": ["a", "b"] })));
+    }
+
+    #[test]
+    fn keys_that_cannot_be_used_in_a_merge_tag_are_rejected() {
+        for key in ["", "first name", "company.name", "{{", &"a".repeat(65)] {
+            assert_err!(SubscriberAttributes::parse(json!({ key: "x" })));
+        }
+    }
+
+    #[test]
+    fn too_many_attributes_are_rejected() {
+        let attributes: serde_json::Map<_, _> =
+            (0..51).map(|i| (format!("key_{}", i), json!(i))).collect();
+        assert_err!(SubscriberAttributes::parse(attributes.into()));
+    }
+
+    #[test]
+    fn overly_long_values_are_rejected() {
+        assert_err!(SubscriberAttributes::parse(
+            json!({ "company": "a".repeat(1025) })
+        ));
+    }
+}
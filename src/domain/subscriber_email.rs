@@ -1,21 +1,93 @@
+use crate::domain::{suggest_domain, EmailPolicy};
+use unicode_normalization::UnicodeNormalization;
 use validator::validate_email;
 
 #[derive(Debug)]
-pub struct SubscriberEmail(String);
+pub struct SubscriberEmail {
+    // The address as the subscriber typed it, minus surrounding whitespace.
+    display: String,
+    // The form used to detect duplicate subscriptions.
+    normalised: String,
+}
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
+        Self::parse_with_policy(s, &EmailPolicy::default())
+    }
+
+    pub fn parse_with_policy(s: String, policy: &EmailPolicy) -> Result<SubscriberEmail, String> {
+        let invalid = || format!("{} is not a valid subscriber email.", s);
+
+        let display = s.trim();
+        if !display.is_ascii() && !policy.allows_smtputf8() {
+            return Err(format!(
+                "{} contains non-ASCII characters, which our email provider cannot deliver to.",
+                s
+            ));
+        }
+        let (local_part, domain) = display.rsplit_once('@').ok_or_else(invalid)?;
+        if !validate_email(format!("{}@{}", ascii_skeleton(local_part)?, domain)) {
+            return Err(invalid());
+        }
+        // `domain_to_ascii` lowercases the domain and converts IDNs to punycode.
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        policy.check_domain(&domain)?;
+        let local_part = if policy.canonicalises_plus_addressing(&domain) {
+            local_part.split('+').next().unwrap_or_default()
         } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+            local_part
+        };
+        if local_part.is_empty() {
+            return Err(invalid());
         }
+        // The same non-ASCII local part can be typed in composed or decomposed form.
+        let local_part: String = local_part.nfc().collect();
+
+        Ok(Self {
+            display: display.to_string(),
+            normalised: format!("{}@{}", local_part, domain),
+        })
     }
+
+    pub fn normalised(&self) -> &str {
+        &self.normalised
+    }
+
+    /// The normalised (lowercase, punycode) domain of the address.
+    pub fn domain(&self) -> &str {
+        self.normalised
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
+
+    /// The address with its domain corrected, if the domain looks like a
+    /// misspelling of a popular provider.
+    pub fn suggested_correction(&self) -> Option<String> {
+        let (local_part, _) = self.display.rsplit_once('@')?;
+        suggest_domain(self.domain()).map(|domain| format!("{}@{}", local_part, domain))
+    }
+}
+
+// `validate_email` only understands ASCII local parts. RFC 6531 (SMTPUTF8)
+// allows any non-ASCII character wherever an ASCII `atext` character is
+// allowed, so we validate the local part with those characters substituted.
+fn ascii_skeleton(local_part: &str) -> Result<String, String> {
+    local_part
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii() => Ok(c),
+            c if c.is_whitespace() || c.is_control() => {
+                Err(format!("{} is not a valid subscriber email.", local_part))
+            }
+            _ => Ok('a'),
+        })
+        .collect()
 }
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.display
     }
 }
 
@@ -23,16 +95,18 @@ impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // We just forward to the Display implementation of
         // the wrapped String.
-        self.0.fmt(f)
+        self.display.fmt(f)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
-    use claim::assert_err;
+    use crate::domain::EmailPolicy;
+    use claim::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
+    use quickcheck::Arbitrary;
 
     #[test]
     fn empty_string_is_rejected() {
@@ -52,6 +126,75 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = SubscriberEmail::parse("  ursula@domain.com \n".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+        assert_eq!(email.normalised(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn the_domain_is_lowercased_but_the_display_form_is_kept() {
+        let email = SubscriberEmail::parse("Ursula@Domain.COM".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula@Domain.COM");
+        assert_eq!(email.normalised(), "Ursula@domain.com");
+    }
+
+    #[test]
+    fn idn_domains_are_converted_to_punycode() {
+        let email = SubscriberEmail::parse("ursula@bücher.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@bücher.de");
+        assert_eq!(email.normalised(), "ursula@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn plus_addressing_is_kept_for_domains_outside_the_policy() {
+        let policy = EmailPolicy::new(vec!["gmail.com".to_string()]);
+        let email =
+            SubscriberEmail::parse_with_policy("ursula+news@domain.com".to_string(), &policy)
+                .unwrap();
+        assert_eq!(email.normalised(), "ursula+news@domain.com");
+    }
+
+    #[test]
+    fn plus_addressing_is_canonicalised_for_domains_in_the_policy() {
+        let policy = EmailPolicy::new(vec!["gmail.com".to_string()]);
+        let email =
+            SubscriberEmail::parse_with_policy("ursula+news@GMail.com".to_string(), &policy)
+                .unwrap();
+        assert_eq!(email.as_ref(), "ursula+news@GMail.com");
+        assert_eq!(email.normalised(), "ursula@gmail.com");
+    }
+
+    #[test]
+    fn an_empty_local_part_after_canonicalisation_is_rejected() {
+        let policy = EmailPolicy::new(vec!["gmail.com".to_string()]);
+        assert_err!(SubscriberEmail::parse_with_policy(
+            "+news@gmail.com".to_string(),
+            &policy
+        ));
+        assert_ok!(SubscriberEmail::parse("+news@gmail.com".to_string()));
+    }
+
+    #[test]
+    fn addresses_at_a_denied_domain_are_rejected() {
+        let policy = EmailPolicy::default().deny_domains(vec!["domain.com".to_string()]);
+        let outcome = SubscriberEmail::parse_with_policy("ursula@Domain.com".to_string(), &policy);
+        assert_eq!(
+            outcome.unwrap_err(),
+            "Email addresses at domain.com cannot be used to subscribe."
+        );
+    }
+
+    #[test]
+    fn a_misspelt_domain_gets_a_suggestion_that_keeps_the_local_part() {
+        let email = SubscriberEmail::parse("Ursula@GMIAL.com".to_string()).unwrap();
+        assert_eq!(
+            email.suggested_correction(),
+            Some("Ursula@gmail.com".to_string())
+        );
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
@@ -66,4 +209,123 @@ mod tests {
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_ascii_emails_are_parsed_without_smtputf8(valid_email: ValidEmailFixture) -> bool {
+        SubscriberEmail::parse_with_policy(valid_email.0, &EmailPolicy::default().ascii_only())
+            .is_ok()
+    }
+
+    #[test]
+    fn a_non_ascii_local_part_is_rejected_without_smtputf8() {
+        let policy = EmailPolicy::default().ascii_only();
+        let outcome = SubscriberEmail::parse_with_policy("ürsula@domain.com".to_string(), &policy);
+        assert_eq!(
+            outcome.unwrap_err(),
+            "ürsula@domain.com contains non-ASCII characters, \
+            which our email provider cannot deliver to."
+        );
+    }
+
+    #[test]
+    fn a_unicode_domain_is_rejected_without_smtputf8() {
+        let policy = EmailPolicy::default().ascii_only();
+        assert_err!(SubscriberEmail::parse_with_policy(
+            "ursula@bücher.de".to_string(),
+            &policy
+        ));
+    }
+
+    #[test]
+    fn non_ascii_whitespace_in_the_local_part_is_rejected() {
+        assert_err!(SubscriberEmail::parse(
+            "ursula\u{00A0}le@domain.com".to_string()
+        ));
+        assert_err!(SubscriberEmail::parse(
+            "ursula\u{2028}@domain.com".to_string()
+        ));
+    }
+
+    #[test]
+    fn composed_and_decomposed_local_parts_normalise_to_the_same_address() {
+        let composed = SubscriberEmail::parse("jos\u{00E9}@domain.com".to_string()).unwrap();
+        let decomposed = SubscriberEmail::parse("jose\u{0301}@domain.com".to_string()).unwrap();
+        assert_eq!(composed.normalised(), decomposed.normalised());
+        assert_ne!(composed.as_ref(), decomposed.as_ref());
+    }
+
+    const UNICODE_LOCAL_CHARACTERS: &[char] = &[
+        'a', 'z', '0', '9', '.', '_', '-', '+', 'à', 'é', 'ü', 'ß', 'ñ', 'ø', 'д', 'я', 'ω', 'λ',
+        '漢', '字', 'ア', '한', 'ا', 'ह', '😀',
+    ];
+    const UNICODE_DOMAIN_LABELS: &[&str] = &[
+        "bücher",
+        "münchen",
+        "пример",
+        "例え",
+        "उदाहरण",
+        "παράδειγμα",
+        "exämple",
+        "domain",
+    ];
+    const TOP_LEVEL_DOMAINS: &[&str] = &["com", "de", "рф", "中国", "org"];
+
+    fn pick<T: Copy, G: quickcheck::Gen>(g: &mut G, items: &[T]) -> T {
+        items[usize::arbitrary(g) % items.len()]
+    }
+
+    #[derive(Debug, Clone)]
+    struct ValidUnicodeEmailFixture(pub String);
+
+    impl quickcheck::Arbitrary for ValidUnicodeEmailFixture {
+        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+            // Dots may not start, end or repeat in a local part, so we only
+            // draw them between two letters.
+            let length = 1 + usize::arbitrary(g) % 20;
+            let mut local_part = String::new();
+            while local_part.chars().count() < length {
+                let c = pick(g, UNICODE_LOCAL_CHARACTERS);
+                if c == '.' && (local_part.is_empty() || local_part.ends_with('.')) {
+                    continue;
+                }
+                local_part.push(c);
+            }
+            if local_part.ends_with('.') {
+                local_part.push('é');
+            }
+            // Make sure every fixture exercises at least one non-ASCII part.
+            let domain = format!(
+                "{}.{}",
+                pick(g, UNICODE_DOMAIN_LABELS),
+                pick(g, TOP_LEVEL_DOMAINS)
+            );
+            let local_part = if domain.is_ascii() {
+                format!("{}ü", local_part)
+            } else {
+                local_part
+            };
+            Self(format!("{}@{}", local_part, domain))
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_unicode_emails_are_parsed_successfully_with_smtputf8(
+        valid_email: ValidUnicodeEmailFixture,
+    ) -> bool {
+        SubscriberEmail::parse(valid_email.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_unicode_emails_are_rejected_without_smtputf8(
+        valid_email: ValidUnicodeEmailFixture,
+    ) -> bool {
+        SubscriberEmail::parse_with_policy(valid_email.0, &EmailPolicy::default().ascii_only())
+            .is_err()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn unicode_domains_are_normalised_to_ascii(valid_email: ValidUnicodeEmailFixture) -> bool {
+        let email = SubscriberEmail::parse(valid_email.0).unwrap();
+        email.domain().is_ascii()
+    }
 }
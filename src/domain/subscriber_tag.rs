@@ -0,0 +1,66 @@
+/// A label attached to subscribers, e.g. `beta`, that segments can select on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Tags are case-insensitive and stored in lowercase.
+    pub fn parse(s: &str) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= 64
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if is_valid {
+            Ok(Self(tag))
+        } else {
+            Err(format!(
+                "{} is not a valid tag: use letters, digits, dashes and underscores.",
+                s.trim()
+            ))
+        }
+    }
+
+    /// Parse a comma-separated list of tags, dropping duplicates.
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = Vec::new();
+        for tag in s.split(',').filter(|t| !t.trim().is_empty()) {
+            let tag = SubscriberTag::parse(tag)?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claim::assert_err;
+
+    #[test]
+    fn tags_are_lowercased_and_trimmed() {
+        assert_eq!(SubscriberTag::parse(" Beta ").unwrap().as_ref(), "beta");
+    }
+
+    #[test]
+    fn tags_with_spaces_or_symbols_are_rejected() {
+        for tag in ["", "early access", "beta!", "a/b"] {
+            assert_err!(SubscriberTag::parse(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn tag_lists_skip_empty_entries_and_duplicates() {
+        let tags = SubscriberTag::parse_list("beta, ,BETA,vip,").unwrap();
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["beta", "vip"]);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::domain::SubscriberEmail;
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
+use std::collections::BTreeMap;
 
 pub struct EmailClient {
     http_client: Client,
@@ -19,6 +20,8 @@ pub struct SendEmailRequest<'a> {
     html_part: &'a str,
     #[serde(rename(serialize = "Text-part"))]
     text_part: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<&'a str, &'a str>,
 }
 
 #[derive(serde::Serialize)]
@@ -35,7 +38,7 @@ impl EmailClient {
     ) -> Self {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
         Self {
-            http_client: http_client,
+            http_client,
             base_url,
             sender,
             authorization_token,
@@ -48,6 +51,19 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    /// Send an email with extra headers, e.g. `List-Unsubscribe`.
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
     ) -> Result<(), reqwest::Error> {
         let url = format!("{}/send", self.base_url);
         let request_body = SendEmailRequest {
@@ -55,9 +71,10 @@ impl EmailClient {
             recipients: vec![Recipient {
                 email: recipient.as_ref(),
             }],
-            subject: subject,
+            subject,
             html_part: html_content,
             text_part: text_content,
+            headers: headers.iter().copied().collect(),
         };
 
         let authorization_header = format!("Basic {}", self.authorization_token.expose_secret());
@@ -83,7 +100,7 @@ mod tests {
     use fake::{Fake, Faker};
     use secrecy::Secret;
     use wiremock::matchers::any;
-    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::matchers::{body_partial_json, header, header_exists, method, path};
     use wiremock::Request;
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
@@ -157,6 +174,41 @@ mod tests {
         // Assert
     }
 
+    #[tokio::test]
+    async fn send_email_with_headers_sends_them_along() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(body_partial_json(serde_json::json!({
+            "Headers": {
+                "List-Unsubscribe": "<https://example.com/unsubscribe>",
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+            }
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[
+                    ("List-Unsubscribe", "<https://example.com/unsubscribe>"),
+                    ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+                ],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         // Arrange
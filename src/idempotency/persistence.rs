@@ -94,9 +94,11 @@ pub async fn save_response(
     transaction.commit().await?;
 
     let http_response = response_head.set_body(body).map_into_boxed_body();
-    return Ok(http_response);
+    Ok(http_response)
 }
 
+// The transaction is handed straight back to the caller, boxing it buys nothing.
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
@@ -1,6 +1,12 @@
-use crate::domain::SubscriberEmail;
+use crate::domain::{SubscriberAttributes, SubscriberEmail};
 use crate::email_client::EmailClient;
+use crate::newsletter_content::{Layout, MergeData, Template};
+use crate::routes::{click_tracking_url, issue_url, open_tracking_url, unsubscribe_url};
+use crate::segments::{bind_segment_values, Segment};
 use crate::{configuration::Configuration, startup::get_connection_pool};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
 use sqlx::{PgPool, Postgres, Transaction};
 use std::time::Duration;
 use tracing::{field::display, Span};
@@ -9,12 +15,23 @@ use uuid::Uuid;
 pub async fn run_worker_until_stopped(configuration: Configuration) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    )
+    .await
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, &base_url, &hmac_secret).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -42,34 +59,27 @@ pub enum ExecutionOutcome {
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (mut transaction, task) = task.unwrap();
+    let DeliveryTask {
+        issue_id,
+        ref email,
+        variant,
+        ..
+    } = task;
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-    match SubscriberEmail::parse(email.clone()) {
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&email));
+    let outcome = match SubscriberEmail::parse(email.clone()) {
         Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                        Skipping.",
-                );
-            }
+            let issue = Issue { issue_id, variant };
+            deliver_issue(pool, email_client, base_url, hmac_secret, issue, &email).await?
         }
         Err(e) => {
             tracing::error!(
@@ -78,25 +88,145 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. \
                     Their stored contact details are invalid",
             );
+            Err(anyhow::anyhow!(e).context("The stored email address is invalid"))
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
+    record_delivery(&mut transaction, &task, &outcome).await?;
+    delete_task(transaction, issue_id, email).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+// The issue to deliver, and the variant of its subject picked for the
+// subscriber if it is being tested.
+struct Issue {
+    issue_id: Uuid,
+    variant: Option<i16>,
+}
+
+/// Send an issue to a confirmed subscriber. The inner error, already
+/// logged, is recorded as a failed delivery; the outer one leaves the task
+/// in the queue to be retried.
+async fn deliver_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    Issue { issue_id, variant }: Issue,
+    email: &SubscriberEmail,
+) -> Result<Result<(), anyhow::Error>, anyhow::Error> {
+    let issue = get_issue(pool, issue_id, variant, base_url).await?;
+    let recipient = get_recipient(pool, email.as_ref()).await?;
+    let rendered = match recipient {
+        Some(recipient) => {
+            let unsubscribe_url =
+                unsubscribe_url(base_url, hmac_secret, recipient.id, issue.list_id);
+            let list_unsubscribe = format!("<{}>", unsubscribe_url);
+            let merge_data = MergeData {
+                name: &recipient.name,
+                email: email.as_ref(),
+                attributes: &recipient.attributes,
+                unsubscribe_url: &unsubscribe_url,
+            };
+            issue.render(&merge_data).map(|mut rendered| {
+                // Opens and clicks decide which subject wins.
+                if issue.tracked {
+                    rendered.html_content = with_tracking(
+                        &rendered.html_content,
+                        &open_tracking_url(base_url, hmac_secret, issue_id, recipient.id),
+                        |url| {
+                            (url != unsubscribe_url).then(|| {
+                                click_tracking_url(
+                                    base_url,
+                                    hmac_secret,
+                                    issue_id,
+                                    recipient.id,
+                                    url,
+                                )
+                            })
+                        },
+                    );
+                }
+                (rendered, list_unsubscribe)
+            })
+        }
+        None => Err(anyhow::anyhow!("The subscriber no longer exists.")),
+    };
+    let (issue, list_unsubscribe) = match rendered {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to render issue for a confirmed subscriber. \
+                    Skipping.",
+            );
+            return Ok(Err(e));
+        }
+    };
+    // Mail clients offer a one-click unsubscribe button (RFC 8058).
+    let headers = [
+        ("List-Unsubscribe", list_unsubscribe.as_str()),
+        ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ];
+    Ok(email_client
+        .send_email_with_headers(
+            email,
+            &issue.title,
+            &issue.html_content,
+            &issue.text_content,
+            &headers,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to deliver issue to a confirmed subscriber. \
+                    Skipping.",
+            );
+            anyhow::anyhow!(e).context("Failed to send the email")
+        }))
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct DeliveryTask {
+    issue_id: Uuid,
+    email: String,
+    // The variant of the subject assigned to the subscriber, if they are in
+    // the test share of a subject test.
+    variant: Option<i16>,
+    // The stage the subscriber is sent to in, if the issue is sent in stages.
+    stage: Option<i16>,
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
+    // The issue is locked for as long as the task, so that it cannot be
+    // paused or cancelled while an email is being sent. The rest of the
+    // audience of a subject test waits for the winner, and later stages for
+    // the earlier ones to be checked.
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
-        SKIP LOCKED
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.variant, q.stage
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE i.paused_at IS NULL AND i.status = 'published'
+        AND (q.variant IS NOT NULL OR NOT EXISTS (
+            SELECT 1 FROM subject_tests t
+            WHERE t.newsletter_issue_id = q.newsletter_issue_id
+            AND t.winning_variant IS NULL
+        ))
+        AND NOT EXISTS (
+            SELECT 1 FROM issue_rollouts r
+            WHERE r.newsletter_issue_id = q.newsletter_issue_id
+            AND q.stage > r.current_stage
+        )
+        FOR UPDATE OF q SKIP LOCKED
+        FOR SHARE OF i SKIP LOCKED
         LIMIT 1
         "#,
     )
@@ -105,14 +235,60 @@ async fn dequeue_task(
     if let Some(r) = r {
         Ok(Some((
             transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
+            DeliveryTask {
+                issue_id: r.newsletter_issue_id,
+                email: r.subscriber_email,
+                variant: r.variant,
+                stage: r.stage,
+            },
         )))
     } else {
         Ok(None)
     }
 }
 
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    outcome: &Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let (outcome, error) = match outcome {
+        Ok(()) => ("delivered", None),
+        Err(e) => ("failed", Some(format!("{:#}", e))),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            error,
+            variant,
+            stage,
+            attempted_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            outcome = EXCLUDED.outcome,
+            error = EXCLUDED.error,
+            variant = EXCLUDED.variant,
+            stage = EXCLUDED.stage,
+            attempted_at = now()
+        "#,
+        task.issue_id,
+        task.email,
+        outcome,
+        error,
+        task.variant,
+        task.stage,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn delete_task(
     mut transaction: PgTransaction,
@@ -135,24 +311,263 @@ async fn delete_task(
 }
 
 struct NewsletterIssue {
+    list_id: Uuid,
     title: String,
     text_content: String,
     html_content: String,
+    layout: Option<Layout>,
+    // Where the issue can be read online, unless it is hidden from the
+    // archive.
+    web_url: Option<String>,
+    // Whether opens and clicks are recorded, to pick the winner of a subject
+    // test.
+    tracked: bool,
 }
 
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    variant: Option<i16>,
+    base_url: &str,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    // The subject is the variant assigned to the subscriber or, once the
+    // test is over, the winning one.
+    let r = sqlx::query!(
         r#"
-        SELECT title, text_content, html_content
-        FROM newsletter_issues
+        SELECT
+            i.list_id,
+            coalesce(v.subject, i.title) AS "title!",
+            t.newsletter_issue_id IS NOT NULL AS "tracked!",
+            i.text_content,
+            i.html_content,
+            i.slug,
+            i.hidden_from_archive,
+            l.html_template AS "html_template?",
+            l.text_template AS "text_template?"
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_layouts l ON l.layout_id = i.layout_id
+        LEFT JOIN subject_tests t ON t.newsletter_issue_id = i.newsletter_issue_id
+        LEFT JOIN subject_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id
+            AND v.variant = coalesce($2, t.winning_variant)
         WHERE
-            newsletter_issue_id = $1
+            i.newsletter_issue_id = $1
         "#,
-        issue_id
+        issue_id,
+        variant,
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    // The layout is picked up as it is at send time.
+    let layout = match (r.html_template, r.text_template) {
+        (Some(html_template), Some(text_template)) => Some(
+            Layout::parse(html_template, text_template)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("The layout of the issue is invalid")?,
+        ),
+        _ => None,
+    };
+    Ok(NewsletterIssue {
+        list_id: r.list_id,
+        title: r.title,
+        text_content: r.text_content,
+        html_content: r.html_content,
+        layout,
+        web_url: r
+            .slug
+            .filter(|_| !r.hidden_from_archive)
+            .map(|slug| issue_url(base_url, &slug)),
+        tracked: r.tracked,
+    })
+}
+
+impl NewsletterIssue {
+    // Templates are validated on publish, so failing here means the issue
+    // was stored by other means.
+    fn render(&self, merge_data: &MergeData) -> Result<NewsletterIssue, anyhow::Error> {
+        let (text_content, html_content) = match &self.layout {
+            Some(layout) => (
+                layout.wrap_text(&self.text_content),
+                layout.wrap_html(&self.html_content),
+            ),
+            None => (self.text_content.clone(), self.html_content.clone()),
+        };
+        let render = |content: &str, html: bool| -> Result<String, anyhow::Error> {
+            let template = Template::parse(content).context("Failed to parse merge tags")?;
+            Ok(if html {
+                template.render_html(merge_data)
+            } else {
+                template.render_text(merge_data)
+            })
+        };
+        let mut text_content = render(&text_content, false)?;
+        let mut html_content = render(&html_content, true)?;
+        if let Some(web_url) = &self.web_url {
+            text_content.push_str(&format!("\n\nView in browser: {}", web_url));
+            html_content = with_web_link(&html_content, web_url);
+        }
+        Ok(NewsletterIssue {
+            list_id: self.list_id,
+            title: render(&self.title, false)?,
+            text_content,
+            html_content,
+            layout: None,
+            web_url: None,
+            tracked: self.tracked,
+        })
+    }
+}
+
+/// Add a "View in browser" link at the end of the body of `html_content`.
+fn with_web_link(html_content: &str, web_url: &str) -> String {
+    let link = format!(
+        r#"<p style="font-size: 12px; text-align: center;"><a href="{}">View in browser</a></p>"#,
+        htmlescape::encode_minimal(web_url)
+    );
+    append_to_body(html_content, &link)
+}
+
+/// Add an image loaded from `open_url` at the end of the body of
+/// `html_content`, and point its web links to what `click_url` returns for
+/// them, if anything.
+fn with_tracking(
+    html_content: &str,
+    open_url: &str,
+    click_url: impl Fn(&str) -> Option<String>,
+) -> String {
+    // Prepared HTML always quotes attributes with double quotes.
+    let mut tracked = String::with_capacity(html_content.len());
+    let mut rest = html_content;
+    while let Some(start) = rest.find(r#" href=""#) {
+        let start = start + r#" href=""#.len();
+        let end = match rest[start..].find('"') {
+            Some(end) => start + end,
+            None => break,
+        };
+        tracked.push_str(&rest[..start]);
+        let href = &rest[start..end];
+        let url = htmlescape::decode_html(href).unwrap_or_else(|_| href.to_string());
+        match click_url(&url).filter(|_| url.starts_with("https://") || url.starts_with("http://"))
+        {
+            Some(click_url) => tracked.push_str(&htmlescape::encode_minimal(&click_url)),
+            None => tracked.push_str(href),
+        }
+        rest = &rest[end..];
+    }
+    tracked.push_str(rest);
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display: block;">"#,
+        htmlescape::encode_minimal(open_url)
+    );
+    append_to_body(&tracked, &pixel)
+}
+
+fn append_to_body(html_content: &str, html: &str) -> String {
+    match html_content.to_ascii_lowercase().rfind("</body>") {
+        Some(i) => format!("{}{}{}", &html_content[..i], html, &html_content[i..]),
+        None => format!("{}{}", html_content, html),
+    }
+}
+
+struct Recipient {
+    id: Uuid,
+    name: String,
+    attributes: SubscriberAttributes,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_recipient(pool: &PgPool, email: &str) -> Result<Option<Recipient>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id, name, attributes
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    row.map(|r| {
+        Ok(Recipient {
+            id: r.id,
+            name: r.name,
+            attributes: SubscriberAttributes::parse(r.attributes)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Stored subscriber attributes are invalid")?,
+        })
+    })
+    .transpose()
+}
+
+/// Which confirmed members of the list of an issue it is sent to.
+pub enum Recipients {
+    Everyone,
+    /// Those an earlier issue was delivered to, e.g. to correct it.
+    RecipientsOf(Uuid),
+    /// Those who confirmed after the given time and have not been sent the
+    /// issue yet, e.g. to catch up on an issue published before.
+    ConfirmedSince(DateTime<Utc>),
+}
+
+/// Queue the issue for `recipients` among the confirmed members of the list,
+/// narrowed down to those in `segment` when one is given. Returns how many
+/// emails were queued.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+    recipients: &Recipients,
+) -> Result<u64, sqlx::Error> {
+    let (recipients_condition, first_segment_placeholder) = match recipients {
+        Recipients::Everyone => ("TRUE", 3),
+        Recipients::RecipientsOf(_) => (
+            r#"EXISTS (
+                SELECT 1 FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = $3
+                AND d.subscriber_email = s.email
+                AND d.outcome = 'delivered'
+            )"#,
+            4,
+        ),
+        Recipients::ConfirmedSince(_) => (
+            r#"m.confirmed_at > $3 AND NOT EXISTS (
+                SELECT 1 FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = $1
+                AND d.subscriber_email = s.email
+            )"#,
+            4,
+        ),
+    };
+    let (segment_condition, segment_values) = match segment {
+        Some(segment) => segment.to_sql(first_segment_placeholder),
+        None => ("TRUE".to_string(), Vec::new()),
+    };
+    // Those already queued for the issue are left as they are.
+    let sql = format!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, s.email
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE m.list_id = $2 AND m.status = 'confirmed' AND ({}) AND ({})
+        ON CONFLICT DO NOTHING
+        "#,
+        recipients_condition, segment_condition
+    );
+    let query = sqlx::query(&sql).bind(newsletter_issue_id).bind(list_id);
+    let query = match recipients {
+        Recipients::Everyone => query,
+        Recipients::RecipientsOf(issue_id) => query.bind(*issue_id),
+        Recipients::ConfirmedSince(since) => query.bind(*since),
+    };
+    Ok(bind_segment_values(query, &segment_values)
+        .execute(transaction)
+        .await?
+        .rows_affected())
 }
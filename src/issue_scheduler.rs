@@ -0,0 +1,175 @@
+use crate::configuration::{Configuration, RolloutConfiguration};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{enqueue_delivery_tasks, ExecutionOutcome, Recipients};
+use crate::routes::issue_slug;
+use crate::segments::Segment;
+use crate::staged_rollouts::{start_issue_rollout, try_advance_rollout};
+use crate::startup::get_connection_pool;
+use crate::subject_tests::{start_issue_subject_test, try_pick_subject_test_winner};
+use anyhow::Context;
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+pub async fn run_scheduler_until_stopped(
+    configuration: Configuration,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    scheduler_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.rollout,
+    )
+    .await
+}
+
+async fn scheduler_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    rollout: RolloutConfiguration,
+) -> Result<(), anyhow::Error> {
+    loop {
+        // Each task runs every time, for one that keeps failing not to hold
+        // up the others.
+        let outcomes = [
+            try_publish_due_issue(&pool, &rollout).await,
+            try_pick_subject_test_winner(&pool).await,
+            try_advance_rollout(&pool, &email_client, &base_url).await,
+        ];
+        if outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, Ok(ExecutionOutcome::TaskCompleted)))
+        {
+            continue;
+        }
+        if outcomes.iter().any(Result::is_err) {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        } else {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+    }
+}
+
+struct DueIssue {
+    newsletter_issue_id: Uuid,
+    list_id: Uuid,
+    segment_expression: Option<String>,
+    recipients_of: Option<Uuid>,
+    title: String,
+}
+
+/// Publish one scheduled issue whose time has come, queueing its deliveries.
+///
+/// The issue stays locked until its deliveries are queued and it is marked as
+/// published, so it is published exactly once however many instances run the
+/// scheduler. An issue that cannot be published keeps the reason and is
+/// skipped until it is rescheduled, rather than holding up the others.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_publish_due_issue(
+    pool: &PgPool,
+    rollout: &RolloutConfiguration,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let issue = sqlx::query_as!(
+        DueIssue,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            -- Issues sent to the recipients of another go to its list.
+            COALESCE(r.list_id, i.list_id) AS "list_id!",
+            i.segment_expression,
+            i.recipients_of,
+            i.title
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_issues r ON r.newsletter_issue_id = i.recipients_of
+        WHERE i.status = 'scheduled' AND i.scheduled_for <= now() AND i.publish_error IS NULL
+        ORDER BY i.scheduled_for
+        FOR UPDATE OF i
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let issue = match issue {
+        Some(issue) => issue,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("newsletter_issue_id", display(issue.newsletter_issue_id));
+
+    // The savepoint is rolled back, and the issue kept locked, if publishing
+    // fails.
+    let mut publishing = transaction.begin().await?;
+    match publish_issue(&mut publishing, &issue, rollout).await {
+        Ok(()) => publishing.commit().await?,
+        Err(e) => {
+            publishing.rollback().await?;
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to publish a scheduled issue"
+            );
+            sqlx::query!(
+                "UPDATE newsletter_issues SET publish_error = $2 WHERE newsletter_issue_id = $1",
+                issue.newsletter_issue_id,
+                format!("{:#}", e),
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue: &DueIssue,
+    rollout: &RolloutConfiguration,
+) -> Result<(), anyhow::Error> {
+    let segment = issue
+        .segment_expression
+        .as_deref()
+        .map(Segment::parse)
+        .transpose()
+        .context("A scheduled issue has an invalid segment")?;
+    enqueue_delivery_tasks(
+        transaction,
+        issue.newsletter_issue_id,
+        issue.list_id,
+        segment.as_ref(),
+        &match issue.recipients_of {
+            Some(issue_id) => Recipients::RecipientsOf(issue_id),
+            None => Recipients::Everyone,
+        },
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")?;
+    start_issue_subject_test(transaction, issue.newsletter_issue_id).await?;
+    start_issue_rollout(transaction, issue.newsletter_issue_id, rollout).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now(),
+            slug = $2,
+            html_content = COALESCE(prepared_html_content, html_content),
+            prepared_html_content = NULL,
+            list_id = $3,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue.newsletter_issue_id,
+        issue_slug(&issue.title, issue.newsletter_issue_id),
+        issue.list_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the issue as published")?;
+    Ok(())
+}
@@ -1,11 +1,18 @@
 pub mod authentication;
 pub mod configuration;
+pub mod confirmation_email_worker;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod newsletter_content;
+pub mod normalised_emails;
 pub mod routes;
+pub mod segments;
 pub mod session_state;
+pub mod staged_rollouts;
 pub mod startup;
+pub mod subject_tests;
 pub mod telemetry;
 pub mod utils;
@@ -1,9 +1,9 @@
 use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
-use zero2prod::issue_delivery_worker::run_worker_until_stopped;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::{confirmation_email_worker, issue_delivery_worker, issue_scheduler};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -13,11 +13,19 @@ async fn main() -> anyhow::Result<()> {
     let configuration = get_configuration().expect("Failed to read configuration.");
     let application = Application::build(configuration.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
+        configuration.clone(),
+    ));
+    let confirmation_worker_task = tokio::spawn(
+        confirmation_email_worker::run_worker_until_stopped(configuration.clone()),
+    );
+    let scheduler_task = tokio::spawn(issue_scheduler::run_scheduler_until_stopped(configuration));
 
     tokio::select! {
         o = application_task => report_exit("API", o),
         o = worker_task => report_exit("Background worker", o),
+        o = confirmation_worker_task => report_exit("Confirmation email worker", o),
+        o = scheduler_task => report_exit("Issue scheduler", o),
     };
 
     Ok(())
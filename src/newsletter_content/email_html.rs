@@ -0,0 +1,520 @@
+use super::Template;
+use html5ever::{namespace_url, ns};
+use kuchikiki::iter::NodeIterator;
+use kuchikiki::traits::TendrilSink;
+use kuchikiki::{NodeData, NodeRef, Selectors};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Elements removed along with their content. Other elements missing from
+/// `ALLOWED_ELEMENTS` are replaced by their content.
+const DROPPED_ELEMENTS: &[&str] = &[
+    "applet", "audio", "base", "button", "canvas", "embed", "form", "frame", "frameset", "iframe",
+    "input", "link", "noscript", "object", "option", "script", "select", "style", "template",
+    "textarea", "video",
+];
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "address",
+    "article",
+    "aside",
+    "b",
+    "bdi",
+    "bdo",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "font",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "main",
+    "mark",
+    "meta",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "section",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+const GLOBAL_ATTRIBUTES: &[&str] = &[
+    "align", "bgcolor", "border", "class", "dir", "height", "id", "lang", "style", "title",
+    "valign", "width",
+];
+const ELEMENT_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "name", "target"]),
+    ("col", &["span"]),
+    ("colgroup", &["span"]),
+    ("font", &["color", "face", "size"]),
+    ("img", &["alt", "src"]),
+    ("li", &["value"]),
+    ("meta", &["charset", "content", "name"]),
+    ("ol", &["start", "type"]),
+    ("table", &["cellpadding", "cellspacing"]),
+    ("td", &["colspan", "rowspan"]),
+    ("th", &["colspan", "rowspan"]),
+];
+const URL_ATTRIBUTES: &[&str] = &["href", "src"];
+const URL_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum HtmlError {
+    #[error("it is not well-formed: {0}.")]
+    Malformed(String),
+}
+
+/// Turn the HTML part of an issue into what email clients can cope with:
+/// the CSS of `<style>` blocks, which most clients ignore, is inlined into
+/// `style` attributes, and everything outside an allowlist of elements,
+/// attributes and URL schemes is removed.
+///
+/// The content is usually a fragment of a body, but can be a whole document.
+/// Merge tags are left as they are. Markup browsers would have to repair is
+/// rejected rather than guessed at.
+pub fn prepare_html(html: &str) -> Result<String, HtmlError> {
+    let (document, is_document) = prepare(html)?;
+    if is_document {
+        return Ok(document.to_string());
+    }
+    Ok(body_html(&document))
+}
+
+/// Like [`prepare_html`], but keeping only the body of whole documents, to
+/// embed the content in a web page.
+pub fn prepare_html_body(html: &str) -> Result<String, HtmlError> {
+    let (document, _) = prepare(html)?;
+    Ok(body_html(&document))
+}
+
+/// The prepared document, and whether the content was a whole document.
+fn prepare(html: &str) -> Result<(NodeRef, bool), HtmlError> {
+    let trimmed = html.trim_start().to_ascii_lowercase();
+    let has_doctype = trimmed.starts_with("<!doctype");
+    let is_document = has_doctype || trimmed.starts_with("<html");
+
+    let errors = Rc::new(RefCell::new(Vec::new()));
+    let mut opts = kuchikiki::ParseOpts::default();
+    opts.tree_builder.exact_errors = true;
+    opts.on_parse_error = Some(Box::new({
+        let errors = errors.clone();
+        move |message: Cow<'static, str>| errors.borrow_mut().push(message)
+    }));
+    // A missing doctype is added rather than reported as an error.
+    let document = kuchikiki::parse_html_with_options(opts).one(if has_doctype {
+        html.to_string()
+    } else {
+        format!("<!DOCTYPE html>{}", html)
+    });
+    if let Some(message) = errors.borrow().first() {
+        return Err(HtmlError::Malformed(describe_parse_error(message)));
+    }
+
+    inline_css(&document);
+    sanitise(&document);
+    Ok((document, is_document))
+}
+
+fn body_html(document: &NodeRef) -> String {
+    let body = document
+        .select_first("body")
+        .expect("The parser always creates a body");
+    body.as_node().children().map(|n| n.to_string()).collect()
+}
+
+/// Explain an error of the parser, which describes tags in its own terms,
+/// to the admin.
+fn describe_parse_error(message: &str) -> String {
+    fn tag_name<'a>(message: &'a str, before: &str) -> Option<&'a str> {
+        let start = message.find(before)? + before.len();
+        // Names are quoted, and sometimes escaped, e.g. `Atom(\'div\' ...)`.
+        let start = start + message[start..].find(|c: char| c.is_ascii_alphanumeric())?;
+        let end = message[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(message.len(), |end| start + end);
+        Some(&message[start..end])
+    }
+
+    if message.starts_with("Unexpected open tag") {
+        if let Some(name) = tag_name(message, "}:") {
+            return format!("the `<{}>` element is never closed", name);
+        }
+    }
+    if let Some(name) = tag_name(message, "Unexpected open element while closing Atom(") {
+        return format!(
+            "the `<{}>` element is closed while an element inside it is still open",
+            name
+        );
+    }
+    if let Some(name) = tag_name(message, "kind: EndTag, name: Atom(") {
+        return format!("the `</{}>` end tag does not close any element", name);
+    }
+    if let Some(name) = tag_name(message, "kind: StartTag, name: Atom(") {
+        return format!("the `<{}>` tag is not allowed where it is", name);
+    }
+    match message {
+        "Unexpected EOF" => "a tag, attribute or comment is never finished".into(),
+        "Duplicate attribute" => "an element has the same attribute twice".into(),
+        "Bad character" => "a `<` which is not part of a tag should be written `&lt;`".into(),
+        "Invalid character reference" => {
+            "a `&` which is not part of a character reference should be written `&amp;`".into()
+        }
+        _ => message.to_string(),
+    }
+}
+
+struct CssRule {
+    selectors: Selectors,
+    declarations: Vec<(String, String)>,
+}
+
+/// Move the rules of `<style>` blocks into the `style` attribute of the
+/// elements they match, following the cascade: more specific selectors win,
+/// then later rules, and existing `style` attributes win over both. At-rules
+/// and selectors that cannot be matched, e.g. `:hover`, are dropped.
+fn inline_css(document: &NodeRef) {
+    let mut rules = Vec::new();
+    let style_elements: Vec<_> = document
+        .select("style")
+        .expect("The selector is valid")
+        .collect();
+    for style_element in style_elements {
+        rules.extend(parse_stylesheet(&style_element.text_contents()));
+        style_element.as_node().detach();
+    }
+    if rules.is_empty() {
+        return;
+    }
+
+    for element in document.descendants().elements() {
+        let mut matches = Vec::new();
+        for (order, rule) in rules.iter().enumerate() {
+            let specificity = rule
+                .selectors
+                .0
+                .iter()
+                .filter(|selector| selector.matches(&element))
+                .map(|selector| selector.specificity())
+                .max();
+            if let Some(specificity) = specificity {
+                matches.push((specificity, order, &rule.declarations));
+            }
+        }
+        if matches.is_empty() {
+            continue;
+        }
+        matches.sort_by_key(|&(specificity, order, _)| (specificity, order));
+
+        let mut attributes = element.attributes.borrow_mut();
+        let inline_style = attributes.get("style").map(parse_declarations);
+        let mut declarations = Vec::new();
+        for (property, value) in matches
+            .into_iter()
+            .flat_map(|(_, _, declarations)| declarations.iter().cloned())
+            .chain(inline_style.into_iter().flatten())
+        {
+            declarations.retain(|(p, _): &(String, String)| *p != property);
+            declarations.push((property, value));
+        }
+        attributes.insert("style", format_declarations(&declarations));
+    }
+}
+
+fn parse_stylesheet(css: &str) -> Vec<CssRule> {
+    let mut css = css.to_string();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..]
+            .find("*/")
+            .map_or(css.len(), |end| start + end + 2);
+        css.replace_range(start..end, "");
+    }
+
+    let mut rules = Vec::new();
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        let mut depth = 0;
+        let close = rest[open..]
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map_or(rest.len(), |(close, _)| open + close);
+        let block = &rest[open + 1..close];
+        rest = rest.get(close + 1..).unwrap_or_default();
+        if prelude.starts_with('@') {
+            continue;
+        }
+        if let Ok(selectors) = Selectors::compile(prelude) {
+            rules.push(CssRule {
+                selectors,
+                declarations: parse_declarations(block),
+            });
+        }
+    }
+    rules
+}
+
+fn parse_declarations(css: &str) -> Vec<(String, String)> {
+    css.split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let (property, value) = (property.trim().to_ascii_lowercase(), value.trim());
+            if property.is_empty() || value.is_empty() {
+                return None;
+            }
+            Some((property, value.to_string()))
+        })
+        .collect()
+}
+
+fn format_declarations(declarations: &[(String, String)]) -> String {
+    declarations
+        .iter()
+        .map(|(property, value)| format!("{}: {};", property, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Remove what is not allowlisted from the children of `node`, recursively.
+fn sanitise(node: &NodeRef) {
+    let children: Vec<_> = node.children().collect();
+    for child in children {
+        match child.data() {
+            NodeData::Element(element) => {
+                let name = &*element.name.local;
+                if element.name.ns != ns!(html) || DROPPED_ELEMENTS.contains(&name) {
+                    child.detach();
+                    continue;
+                }
+                sanitise(&child);
+                if !ALLOWED_ELEMENTS.contains(&name) {
+                    for grandchild in child.children() {
+                        child.insert_before(grandchild);
+                    }
+                    child.detach();
+                    continue;
+                }
+                let mut attributes = element.attributes.borrow_mut();
+                attributes.map.retain(|attribute_name, attribute| {
+                    attribute_name.ns == ns!()
+                        && is_allowed_attribute(name, &attribute_name.local, &attribute.value)
+                });
+                if let Some(style) = attributes.get_mut("style") {
+                    *style = sanitise_style(style);
+                }
+            }
+            NodeData::Comment(_) | NodeData::ProcessingInstruction(_) => child.detach(),
+            _ => sanitise(&child),
+        }
+    }
+}
+
+fn is_allowed_attribute(element: &str, attribute: &str, value: &str) -> bool {
+    let allowed = GLOBAL_ATTRIBUTES.contains(&attribute)
+        || ELEMENT_ATTRIBUTES
+            .iter()
+            .any(|&(e, attributes)| e == element && attributes.contains(&attribute));
+    allowed && (!URL_ATTRIBUTES.contains(&attribute) || is_allowed_url(value))
+}
+
+fn is_allowed_url(url: &str) -> bool {
+    // We fill in the unsubscribe link ourselves.
+    if Template::parse(url.trim()).is_ok_and(|template| template.is_unsubscribe_url()) {
+        return true;
+    }
+    // Browsers ignore whitespace and control characters in schemes.
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect();
+    let scheme_end = url.find([':', '/', '?', '#']).unwrap_or(url.len());
+    // Other merge tags are filled in with subscriber data, which could turn
+    // into a scheme, e.g. `{{ name }}javascript:`.
+    if url[..scheme_end].contains("{{") {
+        return false;
+    }
+    // Relative URLs have no scheme.
+    !url[scheme_end..].starts_with(':')
+        || URL_SCHEMES.contains(&url[..scheme_end].to_ascii_lowercase().as_str())
+}
+
+/// Drop the declarations of a `style` attribute which can run scripts in old
+/// clients.
+fn sanitise_style(style: &str) -> String {
+    let declarations: Vec<_> = parse_declarations(style)
+        .into_iter()
+        .filter(|(_, value)| {
+            let value = value.to_ascii_lowercase();
+            !value.contains("expression(") && !value.contains("javascript:")
+        })
+        .collect();
+    format_declarations(&declarations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prepare_html, prepare_html_body, HtmlError};
+
+    #[test]
+    fn css_is_inlined_following_the_cascade() {
+        let html = prepare_html(
+            r#"<style>
+                /* Brand colours */
+                p { color: red; margin: 0 }
+                .lead { color: blue; }
+                p { font-size: 16px }
+                a:hover { color: green }
+                @media (max-width: 600px) { p { color: black } }
+            </style>
+            <p class="lead" style="margin: 4px">Hi</p><p>There</p>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            html.trim(),
+            r#"<p class="lead" style="font-size: 16px; color: blue; margin: 4px;">Hi</p><p style="color: red; margin: 0; font-size: 16px;">There</p>"#
+        );
+    }
+
+    #[test]
+    fn disallowed_elements_and_attributes_are_removed() {
+        let html = prepare_html(
+            r#"<script>alert(1)</script><p onclick="steal()" class="x">Hi <custom>there</custom><!-- note --></p><iframe src="https://example.com"></iframe>"#,
+        )
+        .unwrap();
+        assert_eq!(html, r#"<p class="x">Hi there</p>"#);
+    }
+
+    #[test]
+    fn only_safe_urls_are_kept() {
+        let html = prepare_html(
+            r#"<a href="java&#x09;script:alert(1)">a</a><a href="https://example.com/?a=1&amp;b=2">b</a><a href="{{ unsubscribe_url }}">c</a><img src="/logo.png" alt="Logo">"#,
+        )
+        .unwrap();
+        assert_eq!(
+            html,
+            r#"<a>a</a><a href="https://example.com/?a=1&amp;b=2">b</a><a href="{{ unsubscribe_url }}">c</a><img src="/logo.png" alt="Logo">"#
+        );
+    }
+
+    #[test]
+    fn merge_tags_cannot_make_up_a_scheme() {
+        let html = prepare_html(
+            r#"<a href="{{ name }}javascript:alert(1)">a</a><a href="{{ attributes.website }}">b</a><a href="{{ unsubscribe_url | default: 'javascript:alert(1)' }}">c</a><a href="https://example.com/?email={{ email }}">d</a><a href="/issues/{{ attributes.slug }}">e</a>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            html,
+            r#"<a>a</a><a>b</a><a>c</a><a href="https://example.com/?email={{ email }}">d</a><a href="/issues/{{ attributes.slug }}">e</a>"#
+        );
+    }
+
+    #[test]
+    fn dangerous_styles_are_removed() {
+        let html =
+            prepare_html(r#"<p style="color: red; background: url(javascript:alert(1))">Hi</p>"#)
+                .unwrap();
+        assert_eq!(html, r#"<p style="color: red;">Hi</p>"#);
+    }
+
+    #[test]
+    fn whole_documents_are_kept_whole() {
+        let html = prepare_html(
+            "<!DOCTYPE html><html><head><title>Issue</title><style>p { color: red }</style></head><body><p>Hi</p></body></html>",
+        )
+        .unwrap();
+        assert_eq!(
+            html,
+            r#"<!DOCTYPE html><html><head><title>Issue</title></head><body><p style="color: red;">Hi</p></body></html>"#
+        );
+    }
+
+    #[test]
+    fn only_the_body_is_kept_for_web_pages() {
+        let html = prepare_html_body(
+            "<!DOCTYPE html><html><head><title>Issue</title></head><body><p>Hi</p></body></html>",
+        )
+        .unwrap();
+        assert_eq!(html, "<p>Hi</p>");
+    }
+
+    #[test]
+    fn malformed_html_is_rejected() {
+        for (html, error) in [
+            ("<div><p>Hi</p>", "the `<div>` element is never closed"),
+            (
+                "<p>Hi</div>",
+                "the `</div>` end tag does not close any element",
+            ),
+            (
+                "<p><b>Hi</p></b>",
+                "the `<p>` element is closed while an element inside it is still open",
+            ),
+            (
+                "<p class='a>Hi</p>",
+                "a tag, attribute or comment is never finished",
+            ),
+        ] {
+            assert_eq!(
+                prepare_html(html),
+                Err(HtmlError::Malformed(error.into())),
+                "{}",
+                html
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::newsletter_content::Template;
+use std::ops::Range;
+
+/// What surrounds the content of an issue, e.g. branding and a footer, as
+/// an HTML and a plain text template with a single `{{ content }}` slot.
+/// Other merge tags are rendered for each recipient like in the issue.
+#[derive(Debug)]
+pub struct Layout {
+    html_template: String,
+    html_slot: Range<usize>,
+    text_template: String,
+    text_slot: Range<usize>,
+}
+
+impl Layout {
+    /// The error is a message for the admin.
+    pub fn parse(html_template: String, text_template: String) -> Result<Layout, String> {
+        let html_slot =
+            find_slot(&html_template).map_err(|e| format!("The HTML layout is invalid: {}", e))?;
+        let text_slot = find_slot(&text_template)
+            .map_err(|e| format!("The plain text layout is invalid: {}", e))?;
+        Ok(Self {
+            html_template,
+            html_slot,
+            text_template,
+            text_slot,
+        })
+    }
+
+    pub fn wrap_html(&self, html_content: &str) -> String {
+        wrap(&self.html_template, &self.html_slot, html_content)
+    }
+
+    pub fn wrap_text(&self, text_content: &str) -> String {
+        wrap(&self.text_template, &self.text_slot, text_content)
+    }
+}
+
+fn wrap(template: &str, slot: &Range<usize>, content: &str) -> String {
+    format!(
+        "{}{}{}",
+        &template[..slot.start],
+        content,
+        &template[slot.end..]
+    )
+}
+
+/// Where the one `{{ content }}` slot of `template` is, checking the merge
+/// tags around it.
+fn find_slot(template: &str) -> Result<Range<usize>, String> {
+    let mut slots = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{") {
+        let start = offset + start;
+        let end = match template[start..].find("}}") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        if template[start + 2..end - 2].trim() == "content" {
+            slots.push(start..end);
+        }
+        offset = end;
+    }
+    let slot = match slots.as_slice() {
+        [slot] => slot.clone(),
+        [] => return Err("it has no `{{ content }}` slot.".into()),
+        _ => return Err("it has more than one `{{ content }}` slot.".into()),
+    };
+    Template::parse(&wrap(template, &slot, "")).map_err(|e| e.to_string())?;
+    Ok(slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn content_is_wrapped_in_both_templates() {
+        let layout = Layout::parse(
+            "<header>Logo</header>{{content}}<footer>{{ unsubscribe_url }}</footer>".into(),
+            "Hello!\n\n{{ content }}\n\n-- \nUnsubscribe: {{ unsubscribe_url }}".into(),
+        )
+        .unwrap();
+        assert_eq!(
+            layout.wrap_html("<p>News</p>"),
+            "<header>Logo</header><p>News</p><footer>{{ unsubscribe_url }}</footer>"
+        );
+        assert_eq!(
+            layout.wrap_text("News"),
+            "Hello!\n\nNews\n\n-- \nUnsubscribe: {{ unsubscribe_url }}"
+        );
+    }
+
+    #[test]
+    fn templates_need_exactly_one_slot() {
+        assert_err!(Layout::parse(
+            "<p>No slot</p>".into(),
+            "{{ content }}".into()
+        ));
+        assert_err!(Layout::parse(
+            "{{ content }}".into(),
+            "{{ content }} and {{ content }}".into()
+        ));
+        assert_ok!(Layout::parse("{{ content }}".into(), "{{content}}".into()));
+    }
+
+    #[test]
+    fn other_merge_tags_must_be_valid() {
+        let e =
+            Layout::parse("{{ content }} {{ nmae }}".into(), "{{ content }}".into()).unwrap_err();
+        assert_eq!(
+            e,
+            "The HTML layout is invalid: `{{ nmae }}` is not a known merge tag."
+        );
+        assert_err!(Layout::parse(
+            "{{ content }} {{".into(),
+            "{{ content }}".into()
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::newsletter_content::{prepare_html, Layout, Template};
+use kuchikiki::traits::TendrilSink;
+use std::collections::{BTreeSet, HashSet};
+
+const MAX_TITLE_LENGTH: usize = 150;
+/// Gmail hides the rest of messages whose HTML is larger than this behind a
+/// "View entire message" link.
+const GMAIL_CLIPPING_SIZE: usize = 102 * 1024;
+/// The share of words the text and HTML parts must have in common not to be
+/// reported as different.
+const MIN_PARTS_OVERLAP: f64 = 0.5;
+
+/// What is wrong with an issue: errors prevent it from being published,
+/// warnings are worth a look.
+#[derive(serde::Serialize, Debug, Default)]
+pub struct LintReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Check an issue as written, wrapped in `layout` if any, before it is
+/// published.
+pub fn lint_issue(
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    layout: Option<&Layout>,
+) -> LintReport {
+    let mut report = LintReport::default();
+    let title_length = title.trim().chars().count();
+    if title_length == 0 {
+        report.errors.push("The title is empty.".into());
+    } else if title_length > MAX_TITLE_LENGTH {
+        report.errors.push(format!(
+            "The title is {} characters long, over the limit of {}.",
+            title_length, MAX_TITLE_LENGTH
+        ));
+    }
+
+    let text_content = match layout {
+        Some(layout) => layout.wrap_text(text_content),
+        None => text_content.to_string(),
+    };
+    let html_content = match prepare_html(html_content) {
+        Ok(html_content) => Some(match layout {
+            Some(layout) => layout.wrap_html(&html_content),
+            None => html_content,
+        }),
+        Err(e) => {
+            report
+                .errors
+                .push(format!("The HTML content could not be published: {}", e));
+            None
+        }
+    };
+
+    let mut attributes_without_default = BTreeSet::new();
+    for (field, part, content) in [
+        ("title", None, Some(title)),
+        (
+            "plain text content",
+            Some("plain text part"),
+            Some(&*text_content),
+        ),
+        ("HTML content", Some("HTML part"), html_content.as_deref()),
+    ] {
+        let content = match content {
+            Some(content) => content,
+            None => continue,
+        };
+        let template = match Template::parse(content) {
+            Ok(template) => template,
+            Err(e) => {
+                report
+                    .errors
+                    .push(format!("The {} could not be published: {}", field, e));
+                continue;
+            }
+        };
+        attributes_without_default.extend(template.attributes_without_default().map(String::from));
+        match part {
+            Some(part) if !template.has_unsubscribe_url() => report.warnings.push(format!(
+                "The {} has no `{{{{ unsubscribe_url }}}}` link for subscribers to leave.",
+                part
+            )),
+            _ => {}
+        }
+    }
+    for key in attributes_without_default {
+        report.warnings.push(format!(
+            "`{{{{ attributes.{0} }}}}` has no default, so it is blank for subscribers \
+            without a {0} attribute.",
+            key
+        ));
+    }
+
+    if let Some(html_content) = html_content {
+        lint_html(&html_content, &text_content, &mut report.warnings);
+    }
+    report
+}
+
+fn lint_html(html_content: &str, text_content: &str, warnings: &mut Vec<String>) {
+    if html_content.len() > GMAIL_CLIPPING_SIZE {
+        warnings.push(format!(
+            "The HTML part is {}KB, so Gmail clips it at {}KB.",
+            html_content.len() / 1024,
+            GMAIL_CLIPPING_SIZE / 1024
+        ));
+    }
+
+    let document = kuchikiki::parse_html().one(html_content);
+    let images_without_alt = document
+        .select("img:not([alt])")
+        .expect("The selector is valid")
+        .count();
+    match images_without_alt {
+        0 => {}
+        1 => warnings
+            .push("An image has no `alt` text, which is shown when images are blocked.".into()),
+        n => warnings.push(format!(
+            "{} images have no `alt` text, which is shown when images are blocked.",
+            n
+        )),
+    }
+    let mut insecure_urls = BTreeSet::new();
+    for element in document
+        .select("a[href], img[src]")
+        .expect("The selector is valid")
+    {
+        let attributes = element.attributes.borrow();
+        let url = attributes
+            .get("href")
+            .or_else(|| attributes.get("src"))
+            .unwrap_or_default()
+            .trim();
+        if url.to_ascii_lowercase().starts_with("http://") {
+            insecure_urls.insert(url.to_string());
+        }
+    }
+    for url in insecure_urls {
+        warnings.push(format!(
+            "{} is not a secure link: use https:// if the site supports it.",
+            url
+        ));
+    }
+
+    let html_text = document
+        .select_first("body")
+        .map(|body| body.text_contents())
+        .unwrap_or_default();
+    let (text_words, html_words) = (words(text_content), words(&html_text));
+    let all_words = text_words.union(&html_words).count();
+    if all_words > 0 {
+        let overlap = text_words.intersection(&html_words).count() as f64 / all_words as f64;
+        if overlap < MIN_PARTS_OVERLAP {
+            warnings.push(format!(
+                "The plain text and HTML parts differ substantially: they only have {:.0}% \
+                of their words in common.",
+                overlap * 100.0
+            ));
+        }
+    }
+}
+
+fn words(s: &str) -> HashSet<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lint_issue;
+    use crate::newsletter_content::Layout;
+
+    const TEXT: &str = "Hi {{ name }}, here is our news.\n\nUnsubscribe: {{ unsubscribe_url }}";
+    const HTML: &str =
+        r#"<p>Hi {{ name }}, here is our news.</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#;
+
+    #[test]
+    fn a_good_issue_has_no_errors_or_warnings() {
+        let report = lint_issue("Our news", TEXT, HTML, None);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn titles_must_not_be_empty_or_too_long() {
+        assert_eq!(
+            lint_issue(" ", TEXT, HTML, None).errors,
+            vec!["The title is empty."]
+        );
+        assert_eq!(
+            lint_issue(&"a".repeat(151), TEXT, HTML, None).errors,
+            vec!["The title is 151 characters long, over the limit of 150."]
+        );
+    }
+
+    #[test]
+    fn invalid_merge_tags_and_html_are_errors() {
+        let report = lint_issue("Hi {{ nmae }}", TEXT, "<div>", None);
+        assert_eq!(
+            report.errors,
+            vec![
+                "The HTML content could not be published: it is not well-formed: \
+                the `<div>` element is never closed.",
+                "The title could not be published: `{{ nmae }}` is not a known merge tag.",
+            ]
+        );
+    }
+
+    #[test]
+    fn questionable_content_is_warned_about() {
+        let html = format!(
+            r#"<p title="{}">Completely different words {{{{ attributes.company }}}}</p>
+            <img src="http://example.com/logo.png"><img src="/a.png" alt="">
+            <a href="http://example.com">Site</a>"#,
+            "x".repeat(103 * 1024)
+        );
+        let report = lint_issue("Our news", "Hi {{ name }}", &html, None);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(
+            report.warnings,
+            vec![
+                "The plain text part has no `{{ unsubscribe_url }}` link for subscribers to leave.",
+                "The HTML part has no `{{ unsubscribe_url }}` link for subscribers to leave.",
+                "`{{ attributes.company }}` has no default, so it is blank for subscribers \
+                without a company attribute.",
+                "The HTML part is 103KB, so Gmail clips it at 102KB.",
+                "An image has no `alt` text, which is shown when images are blocked.",
+                "http://example.com is not a secure link: use https:// if the site supports it.",
+                "http://example.com/logo.png is not a secure link: use https:// if the site \
+                supports it.",
+                "The plain text and HTML parts differ substantially: they only have 0% of \
+                their words in common.",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_layout_is_checked_with_the_issue() {
+        let layout = Layout::parse(
+            r#"{{ content }}<a href="{{ unsubscribe_url }}">Unsubscribe</a>"#.into(),
+            "{{ content }}\n\nUnsubscribe: {{ unsubscribe_url }}".into(),
+        )
+        .unwrap();
+        let report = lint_issue(
+            "Our news",
+            "Hi {{ name }}",
+            "<p>Hi {{ name }}</p>",
+            Some(&layout),
+        );
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+}
@@ -0,0 +1,394 @@
+use pulldown_cmark::{html, Alignment, Event, HeadingLevel, Options, Parser, Tag};
+
+const CONTAINER_STYLE: &str =
+    "font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; \
+    font-size: 16px; line-height: 1.5; color: #222222; max-width: 600px; margin: 0 auto;";
+const TABLE_STYLE: &str = "border-collapse: collapse; margin: 1em 0;";
+const CELL_STYLE: &str = "border: 1px solid #dddddd; padding: 6px 12px;";
+const BLOCKQUOTE_STYLE: &str =
+    "margin: 1em 0; padding-left: 1em; border-left: 4px solid #dddddd; color: #555555;";
+const PRE_STYLE: &str = "background: #f6f8fa; padding: 12px; overflow-x: auto;";
+
+/// The two parts of an issue written in Markdown.
+#[derive(Debug)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+/// The plain text and HTML parts of an issue: generated from its Markdown
+/// source if it has one, as written otherwise.
+pub fn issue_parts(markdown: &str, text: &str, html: &str) -> (String, String) {
+    if markdown.trim().is_empty() {
+        (text.to_string(), html.to_string())
+    } else {
+        let rendered = render_markdown(markdown);
+        (rendered.text, rendered.html)
+    }
+}
+
+/// Render CommonMark, plus tables, to an email-friendly HTML part with
+/// inline styles and a plain text part meant to be read as is.
+///
+/// Merge tags are left untouched in both parts, to be rendered for each
+/// recipient like in any other issue.
+pub fn render_markdown(markdown: &str) -> RenderedMarkdown {
+    let (markdown, merge_tags) = protect_merge_tags(markdown);
+    let mut html = String::new();
+    html::push_html(&mut html, styled_html_events(parser(&markdown)).into_iter());
+    let html = format!(
+        "<div style=\"{}\">\n{}</div>\n",
+        CONTAINER_STYLE,
+        restore_merge_tags(&html, &merge_tags)
+    );
+    let text = restore_merge_tags(&render_text(parser(&markdown)), &merge_tags);
+    RenderedMarkdown { html, text }
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(markdown, Options::ENABLE_TABLES)
+}
+
+// Markdown would escape quotes in `default` filters and percent-encode
+// merge tags in link destinations, so they are swapped for placeholders
+// made of characters that go through untouched.
+fn placeholder(i: usize) -> String {
+    format!("zzmergetag{}zz", i)
+}
+
+fn protect_merge_tags(markdown: &str) -> (String, Vec<&str>) {
+    let mut protected = String::with_capacity(markdown.len());
+    let mut merge_tags = Vec::new();
+    let mut rest = markdown;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end + 2,
+            // Left for the merge tag parser to report.
+            None => break,
+        };
+        protected.push_str(&rest[..start]);
+        protected.push_str(&placeholder(merge_tags.len()));
+        merge_tags.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+    (protected, merge_tags)
+}
+
+fn restore_merge_tags(rendered: &str, merge_tags: &[&str]) -> String {
+    let mut restored = rendered.to_string();
+    // In reverse, so that `zzmergetag1zz` does not match `zzmergetag10zz`.
+    for (i, merge_tag) in merge_tags.iter().enumerate().rev() {
+        restored = restored.replace(&placeholder(i), merge_tag);
+    }
+    restored
+}
+
+fn styled_html_events<'a>(parser: Parser<'a, 'a>) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+    let mut alignments = Vec::new();
+    let mut in_head = false;
+    let mut cell_index = 0;
+    for event in parser {
+        let styled = match &event {
+            Event::Start(Tag::Table(table_alignments)) => {
+                alignments = table_alignments.clone();
+                format!("<table style=\"{}\">", TABLE_STYLE)
+            }
+            Event::End(Tag::Table(_)) => "</tbody></table>\n".into(),
+            Event::Start(Tag::TableHead) => {
+                in_head = true;
+                cell_index = 0;
+                "<thead><tr>".into()
+            }
+            Event::End(Tag::TableHead) => {
+                in_head = false;
+                "</tr></thead><tbody>\n".into()
+            }
+            Event::Start(Tag::TableRow) => {
+                cell_index = 0;
+                "<tr>".into()
+            }
+            Event::End(Tag::TableRow) => "</tr>\n".into(),
+            Event::Start(Tag::TableCell) => {
+                let text_align = match alignments.get(cell_index) {
+                    Some(Alignment::Center) => "center",
+                    Some(Alignment::Right) => "right",
+                    _ => "left",
+                };
+                format!(
+                    "<{} style=\"{} text-align: {};\">",
+                    if in_head { "th" } else { "td" },
+                    CELL_STYLE,
+                    text_align
+                )
+            }
+            Event::End(Tag::TableCell) => {
+                cell_index += 1;
+                if in_head { "</th>" } else { "</td>" }.into()
+            }
+            Event::Start(Tag::BlockQuote) => {
+                format!("<blockquote style=\"{}\">\n", BLOCKQUOTE_STYLE)
+            }
+            Event::End(Tag::BlockQuote) => "</blockquote>\n".into(),
+            Event::Start(Tag::CodeBlock(_)) => format!("<pre style=\"{}\"><code>", PRE_STYLE),
+            Event::End(Tag::CodeBlock(_)) => "</code></pre>\n".into(),
+            _ => {
+                events.push(event);
+                continue;
+            }
+        };
+        events.push(Event::Html(styled.into()));
+    }
+    events
+}
+
+/// Lays out Markdown as plain text, indenting nested blocks.
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    // Written at the start of each line, e.g. `> ` inside a quote.
+    prefixes: Vec<String>,
+    at_line_start: bool,
+    // Set right after a list marker, whose item's first block starts on
+    // the same line.
+    after_marker: bool,
+    // The next number of each ordered list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    // Where the text of each open heading and link starts.
+    starts: Vec<usize>,
+    links: Vec<String>,
+    first_cell: bool,
+}
+
+impl TextWriter {
+    fn push(&mut self, s: &str) {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            if !line.is_empty() {
+                if self.at_line_start {
+                    self.text.push_str(&self.prefixes.concat());
+                    self.at_line_start = false;
+                }
+                self.after_marker = false;
+                self.text.push_str(line);
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.text.push('\n');
+        self.at_line_start = true;
+    }
+
+    /// Start a block, separated from the previous one by a blank line
+    /// unless it directly follows a list marker.
+    fn block(&mut self) {
+        if self.after_marker || self.text.is_empty() {
+            return;
+        }
+        if !self.at_line_start {
+            self.newline();
+        }
+        if !self.text.ends_with("\n\n") {
+            // Blank lines inside quotes keep their `>`.
+            self.text
+                .push_str(self.prefixes.concat().trim_end_matches(' '));
+            self.newline();
+        }
+    }
+
+    fn line_length_since(&self, start: usize) -> usize {
+        self.text[start..]
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+    }
+}
+
+fn render_text(parser: Parser<'_, '_>) -> String {
+    let mut w = TextWriter {
+        at_line_start: true,
+        ..Default::default()
+    };
+    for event in parser {
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Table(_)) => w.block(),
+            Event::Start(Tag::Heading(..)) => {
+                w.block();
+                w.starts.push(w.text.len());
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                let start = w.starts.pop().unwrap_or_default();
+                let underline = match level {
+                    HeadingLevel::H1 => '=',
+                    HeadingLevel::H2 => '-',
+                    _ => continue,
+                };
+                let length = w.line_length_since(start);
+                w.newline();
+                w.push(&underline.to_string().repeat(length));
+            }
+            Event::Start(Tag::BlockQuote) => {
+                w.block();
+                w.prefixes.push("> ".into());
+            }
+            Event::End(Tag::BlockQuote) => {
+                w.prefixes.pop();
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                w.block();
+                w.prefixes.push("    ".into());
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                w.prefixes.pop();
+                // Code blocks end with a newline of their own.
+                if w.text.ends_with('\n') {
+                    w.text.pop();
+                    w.at_line_start = false;
+                }
+            }
+            Event::Start(Tag::List(first_number)) => {
+                if w.lists.is_empty() {
+                    w.block();
+                }
+                w.lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                w.lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                if !w.at_line_start {
+                    w.newline();
+                }
+                let marker = match w.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".into(),
+                };
+                w.push(&marker);
+                w.prefixes.push(" ".repeat(marker.len()));
+                w.after_marker = true;
+            }
+            Event::End(Tag::Item) => {
+                w.prefixes.pop();
+            }
+            Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
+                if !w.at_line_start {
+                    w.newline();
+                }
+                w.first_cell = true;
+            }
+            Event::Start(Tag::TableCell) => {
+                if !w.first_cell {
+                    w.push(" | ");
+                }
+                w.first_cell = false;
+            }
+            Event::Start(Tag::Link(_, url, _)) => {
+                w.starts.push(w.text.len());
+                w.links.push(url.to_string());
+            }
+            Event::End(Tag::Link(..)) => {
+                let start = w.starts.pop().unwrap_or_default();
+                let url = w.links.pop().unwrap_or_default();
+                let label = &w.text[start..];
+                let is_autolink = label == url || url.strip_prefix("mailto:") == Some(label);
+                if !is_autolink {
+                    w.push(&format!(" ({})", url));
+                }
+            }
+            Event::Text(text) | Event::Code(text) => w.push(&text),
+            Event::SoftBreak | Event::HardBreak => w.newline(),
+            Event::Rule => {
+                w.block();
+                w.push("----------");
+            }
+            // Raw HTML has no plain text equivalent.
+            _ => {}
+        }
+    }
+    let mut text = w.text.trim_end().to_string();
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_parts, render_markdown};
+    use crate::newsletter_content::Template;
+
+    #[test]
+    fn paragraphs_headings_and_lists_are_laid_out_as_text() {
+        let rendered = render_markdown(
+            "# Monthly update\n\nHello *there*.\nSecond line.\n\n- One\n- Two\n  1. Nested\n  2. Again\n\n> Quoted\n> text\n",
+        );
+        assert_eq!(
+            rendered.text,
+            "Monthly update\n==============\n\nHello there.\nSecond line.\n\n- One\n- Two\n  1. Nested\n  2. Again\n\n> Quoted\n> text\n"
+        );
+        assert!(rendered.html.contains("<h1>Monthly update</h1>"));
+        assert!(rendered.html.contains("<em>there</em>"));
+        assert!(rendered.html.contains("<blockquote style="));
+    }
+
+    #[test]
+    fn links_show_their_destination_in_the_text_part() {
+        let rendered =
+            render_markdown("Read [the post](https://example.com/post) or <https://example.com>.");
+        assert_eq!(
+            rendered.text,
+            "Read the post (https://example.com/post) or https://example.com.\n"
+        );
+    }
+
+    #[test]
+    fn tables_are_styled_and_aligned() {
+        let rendered = render_markdown("| Name | Count |\n| ---- | ----: |\n| Ada | 3 |\n");
+        assert!(rendered.html.contains("<table style="));
+        assert!(rendered.html.contains("text-align: right;\">3</td>"));
+        assert!(rendered.html.contains("text-align: left;\">Name</th>"));
+        assert_eq!(rendered.text, "Name | Count\nAda | 3\n");
+    }
+
+    #[test]
+    fn code_blocks_are_indented_in_the_text_part() {
+        let rendered = render_markdown("Run:\n\n```\ncargo test\n```\n\nDone.");
+        assert_eq!(rendered.text, "Run:\n\n    cargo test\n\nDone.\n");
+        assert!(rendered.html.contains("<code>cargo test\n</code></pre>"));
+    }
+
+    #[test]
+    fn merge_tags_survive_rendering() {
+        let rendered = render_markdown(
+            "Hi {{ attributes.nickname | default: \"friend\" }},\n\n[Unsubscribe]({{unsubscribe_url}})",
+        );
+        assert!(rendered
+            .html
+            .contains("Hi {{ attributes.nickname | default: \"friend\" }},"));
+        assert!(rendered
+            .html
+            .contains("<a href=\"{{unsubscribe_url}}\">Unsubscribe</a>"));
+        assert!(Template::parse(&rendered.html).is_ok());
+        assert_eq!(
+            rendered.text,
+            "Hi {{ attributes.nickname | default: \"friend\" }},\n\nUnsubscribe ({{unsubscribe_url}})\n"
+        );
+    }
+
+    #[test]
+    fn handwritten_parts_are_kept_without_markdown() {
+        let (text, html) = issue_parts(" ", "Text", "<p>HTML</p>");
+        assert_eq!(text, "Text");
+        assert_eq!(html, "<p>HTML</p>");
+        let (text, html) = issue_parts("**Bold**", "Text", "<p>HTML</p>");
+        assert_eq!(text, "Bold\n");
+        assert!(html.contains("<strong>Bold</strong>"));
+    }
+}
@@ -0,0 +1,382 @@
+use crate::domain::SubscriberAttributes;
+use serde_json::Value;
+
+/// A newsletter title or body containing merge tags such as `{{ name }}` or
+/// `{{ attributes.company | default: "friend" }}`, parsed once and rendered
+/// for each recipient.
+#[derive(Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug)]
+enum Part {
+    Literal(String),
+    Tag(MergeTag),
+}
+
+#[derive(Debug)]
+struct MergeTag {
+    variable: Variable,
+    default: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Variable {
+    Name,
+    Email,
+    Attribute(String),
+    UnsubscribeUrl,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("The merge tag starting at character {0} is never closed with `}}}}`.")]
+    UnclosedTag(usize),
+    #[error("`{{{{ {0} }}}}` is not a known merge tag.")]
+    UnknownVariable(String),
+    #[error("`{0}` is not a known merge tag filter.")]
+    UnknownFilter(String),
+    #[error("`{{{{ {0} }}}}` is not a valid merge tag.")]
+    InvalidTag(String),
+}
+
+/// The values merge tags are replaced with for a single recipient.
+pub struct MergeData<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub attributes: &'a SubscriberAttributes,
+    pub unsubscribe_url: &'a str,
+}
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Template, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open.find("}}").ok_or_else(|| {
+                TemplateError::UnclosedTag(s[..s.len() - rest.len() + start].chars().count())
+            })?;
+            parts.push(Part::Tag(MergeTag::parse(&after_open[..end])?));
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Render for a plain-text context, e.g. the subject or the text part.
+    pub fn render_text(&self, data: &MergeData) -> String {
+        self.render(data, |value| value.to_string())
+    }
+
+    /// Render for an HTML context: merged values are escaped, the template
+    /// itself is trusted.
+    pub fn render_html(&self, data: &MergeData) -> String {
+        self.render(data, htmlescape::encode_minimal)
+    }
+
+    /// Whether the template links to the unsubscribe page.
+    pub fn has_unsubscribe_url(&self) -> bool {
+        self.tags()
+            .any(|tag| tag.variable == Variable::UnsubscribeUrl)
+    }
+
+    /// Whether the template is `{{ unsubscribe_url }}` and nothing else.
+    pub fn is_unsubscribe_url(&self) -> bool {
+        matches!(
+            self.parts.as_slice(),
+            [Part::Tag(MergeTag {
+                variable: Variable::UnsubscribeUrl,
+                default: None
+            })]
+        )
+    }
+
+    /// The attributes merged without a default, which are left blank for
+    /// subscribers who do not have them.
+    pub fn attributes_without_default(&self) -> impl Iterator<Item = &str> {
+        self.tags()
+            .filter_map(|tag| match (&tag.variable, &tag.default) {
+                (Variable::Attribute(key), None) => Some(key.as_str()),
+                _ => None,
+            })
+    }
+
+    fn tags(&self) -> impl Iterator<Item = &MergeTag> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Tag(tag) => Some(tag),
+            Part::Literal(_) => None,
+        })
+    }
+
+    fn render(&self, data: &MergeData, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Tag(tag) => rendered.push_str(&escape(&tag.value(data))),
+            }
+        }
+        rendered
+    }
+}
+
+impl MergeTag {
+    fn parse(s: &str) -> Result<MergeTag, TemplateError> {
+        let invalid = || TemplateError::InvalidTag(s.trim().to_string());
+        let mut segments = split_filters(s).ok_or_else(invalid)?.into_iter();
+        let variable = segments.next().ok_or_else(invalid)?;
+        let variable = match variable {
+            "name" => Variable::Name,
+            "email" => Variable::Email,
+            "unsubscribe_url" => Variable::UnsubscribeUrl,
+            v => match v.strip_prefix("attributes.") {
+                Some(key)
+                    if !key.is_empty()
+                        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+                {
+                    Variable::Attribute(key.to_string())
+                }
+                _ => return Err(TemplateError::UnknownVariable(v.to_string())),
+            },
+        };
+
+        let mut default = None;
+        for filter in segments {
+            let (name, argument) = match filter.split_once(':') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim())),
+                None => (filter, None),
+            };
+            match (name, argument) {
+                ("default", Some(argument)) => {
+                    default = Some(parse_string_literal(argument).ok_or_else(invalid)?);
+                }
+                ("default", None) => return Err(invalid()),
+                (name, _) => return Err(TemplateError::UnknownFilter(name.to_string())),
+            }
+        }
+        Ok(Self { variable, default })
+    }
+
+    fn value(&self, data: &MergeData) -> String {
+        let value = match &self.variable {
+            Variable::Name => Some(data.name.to_string()),
+            Variable::Email => Some(data.email.to_string()),
+            Variable::UnsubscribeUrl => Some(data.unsubscribe_url.to_string()),
+            Variable::Attribute(key) => match data.attributes.get(key) {
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(Value::Null) | None => None,
+                Some(v) => Some(v.to_string()),
+            },
+        };
+        match (value, &self.default) {
+            (Some(value), _) if !value.is_empty() => value,
+            (_, Some(default)) => default.clone(),
+            (value, None) => value.unwrap_or_default(),
+        }
+    }
+}
+
+// Split `attributes.company | default: "a | b"` on the pipes that are not
+// inside a quoted argument, trimming each segment.
+fn split_filters(s: &str) -> Option<Vec<&str>> {
+    let mut segments = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '|') => {
+                segments.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    segments.push(s[start..].trim());
+    if segments.iter().any(|s| s.is_empty()) {
+        return None;
+    }
+    Some(segments)
+}
+
+fn parse_string_literal(s: &str) -> Option<String> {
+    let quote = s.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let inner = s.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut literal = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => literal.push(chars.next()?),
+            c if c == quote => return None,
+            c => literal.push(c),
+        }
+    }
+    Some(literal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeData, Template, TemplateError};
+    use crate::domain::SubscriberAttributes;
+    use claim::assert_ok;
+    use serde_json::json;
+
+    fn render_text(template: &str, attributes: serde_json::Value) -> String {
+        let attributes = SubscriberAttributes::parse(attributes).unwrap();
+        Template::parse(template).unwrap().render_text(&MergeData {
+            name: "Ursula",
+            email: "ursula@domain.com",
+            attributes: &attributes,
+            unsubscribe_url: "https://zero2prod.dev/unsubscribe",
+        })
+    }
+
+    #[test]
+    fn content_without_merge_tags_is_left_untouched() {
+        assert_eq!(render_text("Hello world!", json!({})), "Hello world!");
+    }
+
+    #[test]
+    fn subscriber_fields_are_merged() {
+        assert_eq!(
+            render_text(
+                "Hi {{ name }} ({{email}}), bye: {{ unsubscribe_url }}",
+                json!({})
+            ),
+            "Hi Ursula (ursula@domain.com), bye: https://zero2prod.dev/unsubscribe"
+        );
+    }
+
+    #[test]
+    fn attributes_are_merged() {
+        assert_eq!(
+            render_text(
+                "{{ attributes.company }} has {{ attributes.seats }} seats",
+                json!({ "company": "Acme", "seats": 12 })
+            ),
+            "Acme has 12 seats"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_fall_back_to_the_default() {
+        let template = r#"Dear {{ attributes.company | default: "friend" }}"#;
+        assert_eq!(render_text(template, json!({})), "Dear friend");
+        assert_eq!(
+            render_text(template, json!({ "company": null })),
+            "Dear friend"
+        );
+        assert_eq!(
+            render_text(template, json!({ "company": "" })),
+            "Dear friend"
+        );
+        assert_eq!(
+            render_text(template, json!({ "company": "Acme" })),
+            "Dear Acme"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_without_a_default_render_as_empty() {
+        assert_eq!(render_text("[{{ attributes.company }}]", json!({})), "[]");
+    }
+
+    #[test]
+    fn default_arguments_may_contain_pipes_and_escaped_quotes() {
+        let template = r#"{{ attributes.company | default: "a | \"b\"" }}"#;
+        assert_eq!(render_text(template, json!({})), r#"a | "b""#);
+    }
+
+    #[test]
+    fn html_rendering_escapes_merged_values_only() {
+        let attributes =
+            SubscriberAttributes::parse(json!({ "company": "<script>Acme & co</script>" }))
+                .unwrap();
+        let rendered = Template::parse("<p>{{ attributes.company }}</p>")
+            .unwrap()
+            .render_html(&MergeData {
+                name: "Ursula",
+                email: "ursula@domain.com",
+                attributes: &attributes,
+                unsubscribe_url: "",
+            });
+        assert_eq!(
+            rendered,
+            "<p>&lt;script&gt;Acme &amp; co&lt;/script&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_eq!(
+            Template::parse("Hi {{ nmae }}").unwrap_err(),
+            TemplateError::UnknownVariable("nmae".into())
+        );
+        assert_eq!(
+            Template::parse("Hi {{ attributes. }}").unwrap_err(),
+            TemplateError::UnknownVariable("attributes.".into())
+        );
+    }
+
+    #[test]
+    fn unknown_filters_are_rejected() {
+        assert_eq!(
+            Template::parse("{{ name | upcase }}").unwrap_err(),
+            TemplateError::UnknownFilter("upcase".into())
+        );
+    }
+
+    #[test]
+    fn unclosed_tags_are_rejected() {
+        assert_eq!(
+            Template::parse("Hé {{ name").unwrap_err(),
+            TemplateError::UnclosedTag(3)
+        );
+    }
+
+    #[test]
+    fn malformed_tags_are_rejected() {
+        for template in [
+            "{{ }}",
+            "{{ name | }}",
+            "{{ name | default }}",
+            "{{ name | default: friend }}",
+            r#"{{ name | default: "friend }}"#,
+        ] {
+            assert!(Template::parse(template).is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn single_braces_are_left_untouched() {
+        assert_ok!(Template::parse("p { color: red; }"));
+    }
+
+    #[test]
+    fn merge_tags_can_be_inspected() {
+        let template = Template::parse(
+            r#"{{ attributes.company }} {{ attributes.city | default: "here" }} {{ unsubscribe_url }}"#,
+        )
+        .unwrap();
+        assert!(template.has_unsubscribe_url());
+        assert_eq!(
+            template.attributes_without_default().collect::<Vec<_>>(),
+            vec!["company"]
+        );
+        assert!(!Template::parse("{{ name }}").unwrap().has_unsubscribe_url());
+    }
+}
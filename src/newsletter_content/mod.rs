@@ -0,0 +1,11 @@
+mod email_html;
+mod layout;
+mod lint;
+mod markdown;
+mod merge_tags;
+
+pub use email_html::{prepare_html, prepare_html_body, HtmlError};
+pub use layout::Layout;
+pub use lint::{lint_issue, LintReport};
+pub use markdown::{issue_parts, render_markdown, RenderedMarkdown};
+pub use merge_tags::{MergeData, Template, TemplateError};
@@ -0,0 +1,164 @@
+use crate::domain::{EmailPolicy, SubscriberEmail};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Recompute the normalised email of subscriptions whose address is not
+/// ASCII. The SQL backfill that introduced `normalised_email` could not
+/// convert IDN domains to punycode nor compose local parts, so these rows
+/// would never match the same address subscribing again. Subscriptions
+/// found to be duplicates are merged into one, as the backfill did.
+///
+/// Run once by the `backfill_normalised_emails` binary; everything is rolled
+/// back if any step fails.
+///
+/// Returns the number of subscriptions updated or merged.
+#[tracing::instrument(skip_all)]
+pub async fn backfill_normalised_emails(
+    pool: &PgPool,
+    policy: &EmailPolicy,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriptions = sqlx::query!(
+        r#"
+        SELECT id, email, normalised_email
+        FROM subscriptions
+        WHERE octet_length(email) <> char_length(email)
+        ORDER BY id
+        FOR UPDATE
+        "#,
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriptions with non-ASCII addresses")?;
+    let mut n_fixed = 0;
+    for subscription in subscriptions {
+        let normalised = match SubscriberEmail::parse_with_policy(subscription.email, policy) {
+            Ok(email) => email.normalised().to_string(),
+            Err(e) => {
+                tracing::warn!(
+                    subscriber_id = %subscription.id,
+                    error.message = %e,
+                    "Skipping a subscription whose address cannot be parsed",
+                );
+                continue;
+            }
+        };
+        if normalised.to_lowercase() == subscription.normalised_email.to_lowercase() {
+            continue;
+        }
+        renormalise(&mut transaction, subscription.id, &normalised).await?;
+        n_fixed += 1;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the normalised emails")?;
+    if n_fixed > 0 {
+        tracing::info!(n_fixed, "Fixed the normalised email of subscriptions");
+    }
+    Ok(n_fixed)
+}
+
+async fn renormalise(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    normalised: &str,
+) -> Result<(), anyhow::Error> {
+    // As in the backfill, a confirmed subscription is kept over a pending one
+    // and the oldest subscription otherwise.
+    let ranked = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE id = $1 OR lower(normalised_email) = lower($2)
+        ORDER BY (status = 'confirmed') DESC, subscribed_at ASC, id ASC
+        "#,
+        subscriber_id,
+        normalised,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to look for subscriptions with the same normalised email")?;
+    let keeper_id = ranked[0].id;
+    for duplicate in ranked.iter().skip(1) {
+        merge_subscription(transaction, duplicate.id, keeper_id).await?;
+    }
+    sqlx::query!(
+        "UPDATE subscriptions SET normalised_email = $2 WHERE id = $1",
+        keeper_id,
+        normalised,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the normalised email")?;
+    Ok(())
+}
+
+/// Move the lists, tags and pending confirmations of `duplicate_id` over to
+/// `keeper_id`, then delete it.
+async fn merge_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    duplicate_id: Uuid,
+    keeper_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (
+            list_id, subscriber_id, status, subscribed_at, confirmed_at, consent_source
+        )
+        SELECT list_id, $2, status, subscribed_at, confirmed_at, consent_source
+        FROM list_memberships
+        WHERE subscriber_id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        duplicate_id,
+        keeper_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to merge the lists of duplicate subscriptions")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, added_at)
+        SELECT $2, tag, added_at
+        FROM subscriber_tags
+        WHERE subscriber_id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        duplicate_id,
+        keeper_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to merge the tags of duplicate subscriptions")?;
+    sqlx::query!(
+        "UPDATE subscription_tokens SET subscriber_id = $2 WHERE subscriber_id = $1",
+        duplicate_id,
+        keeper_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to move the tokens of a duplicate subscription")?;
+    // Emails still queued for the duplicate address are dropped: the keeper
+    // gets its own for the lists it is confirmed on.
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue q
+        USING subscriptions s
+        WHERE s.id = $1 AND q.subscriber_email = s.email
+        "#,
+        duplicate_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the queued emails of a duplicate subscription")?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", duplicate_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete a duplicate subscription")?;
+    Ok(())
+}
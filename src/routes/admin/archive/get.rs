@@ -0,0 +1,131 @@
+use crate::routes::search_issues;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    search: String,
+}
+
+struct ArchivedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    slug: String,
+    hidden_from_archive: bool,
+    snippet_html: Option<String>,
+}
+
+/// Published issues, which can be hidden from the public archive.
+pub async fn archive_page(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let search = query.search.trim();
+    let issues = if search.is_empty() {
+        sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id, title, slug AS "slug!", hidden_from_archive
+            FROM newsletter_issues
+            WHERE status = 'published' AND slug IS NOT NULL
+            ORDER BY published_at DESC
+            "#
+        )
+        .fetch_all(pool.get_ref())
+        .await
+        .context("Failed to retrieve the published issues")
+        .map_err(e500)?
+        .into_iter()
+        .map(|r| ArchivedIssue {
+            newsletter_issue_id: r.newsletter_issue_id,
+            title: r.title,
+            slug: r.slug,
+            hidden_from_archive: r.hidden_from_archive,
+            snippet_html: None,
+        })
+        .collect()
+    } else {
+        search_issues(&pool, search, true)
+            .await
+            .map_err(e500)?
+            .into_iter()
+            .map(|issue| ArchivedIssue {
+                snippet_html: Some(issue.snippet_html()),
+                newsletter_issue_id: issue.newsletter_issue_id,
+                title: issue.title,
+                slug: issue.slug,
+                hidden_from_archive: issue.hidden_from_archive,
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut issues_html = String::new();
+    for issue in &issues {
+        let (status, action, button) = if issue.hidden_from_archive {
+            (" (hidden)", "show", "Show")
+        } else {
+            ("", "hide", "Hide")
+        };
+        let snippet_html = issue
+            .snippet_html
+            .as_ref()
+            .map(|snippet_html| format!("<br>{}", snippet_html))
+            .unwrap_or_default();
+        writeln!(
+            issues_html,
+            r#"<li><a href="/issues/{slug}">{title}</a>{status}
+        <form action="/admin/archive/{id}/{action}" method="post" style="display: inline;">
+            <button type="submit">{button}</button>
+        </form>{snippet_html}
+    </li>"#,
+            slug = issue.slug,
+            title = htmlescape::encode_minimal(&issue.title),
+            id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    if issues.is_empty() {
+        issues_html.push_str(if search.is_empty() {
+            "<li>No issues have been published yet.</li>"
+        } else {
+            "<li>No issues match your search.</li>"
+        });
+    }
+    let search = htmlescape::encode_attribute(search);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Public archive</title>
+</head>
+<body>
+    {msg_html}
+    <p>Published issues can be read by anyone at <a href="/issues">/issues</a>,
+    unless they are hidden.</p>
+    <form action="/admin/archive" method="get">
+        <input type="search" name="search" value="{search}" aria-label="Search issues">
+        <button type="submit">Search</button>
+    </form>
+    <ul>
+    {issues_html}
+    </ul>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::archive_page;
+pub use post::{hide_issue, show_issue};
@@ -0,0 +1,67 @@
+use crate::authentication::UserId;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(
+    name = "Hide an issue from the archive",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn hide_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_hidden_from_archive(&pool, issue_id.into_inner(), true)
+        .await
+        .map_err(e500)
+}
+
+#[tracing::instrument(
+    name = "Show an issue in the archive",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn show_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_hidden_from_archive(&pool, issue_id.into_inner(), false)
+        .await
+        .map_err(e500)
+}
+
+async fn set_hidden_from_archive(
+    pool: &PgPool,
+    issue_id: Uuid,
+    hidden: bool,
+) -> Result<HttpResponse, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET hidden_from_archive = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'published'
+        "#,
+        issue_id,
+        hidden,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the issue")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info(if hidden {
+        "The issue is hidden from the archive."
+    } else {
+        "The issue is shown in the archive again."
+    })
+    .send();
+    Ok(see_other("/admin/archive"))
+}
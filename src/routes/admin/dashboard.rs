@@ -31,6 +31,17 @@ pub async fn admin_dashboard(
     <p>Available actions:</p>
     <ol>
         <li><a href="/admin/newsletter">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters/drafts">Newsletter drafts</a></li>
+        <li><a href="/admin/issues">Issues and delivery progress</a></li>
+        <li><a href="/admin/layouts">Manage newsletter layouts</a></li>
+        <li><a href="/admin/archive">Manage the public archive</a></li>
+        <li><a href="/admin/lists">Manage mailing lists</a></li>
+        <li><a href="/admin/subscribers">Browse subscribers</a></li>
+        <li><a href="/admin/subscribers/import">Import subscribers</a></li>
+        <li><a href="/admin/subscribers/export">Export subscribers</a></li>
+        <li><a href="/admin/segments">Manage segments</a></li>
+        <li><a href="/admin/subscribers/attributes">Edit subscriber attributes and tags</a></li>
+        <li><a href="/admin/email_domains">Manage denied email domains</a></li>
         <li><a href="/admin/password">Change password</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
@@ -0,0 +1,72 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn email_domains_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let domains = get_denied_domains(&pool).await.map_err(e500)?;
+    let mut domains_html = String::new();
+    for domain in domains {
+        let domain = htmlescape::encode_minimal(&domain);
+        writeln!(
+            domains_html,
+            r#"<li>{domain}
+        <form action="/admin/email_domains/remove" method="post" style="display:inline">
+            <input hidden type="text" name="domain" value="{domain}">
+            <button type="submit">Remove</button>
+        </form>
+    </li>"#
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Denied email domains</title>
+</head>
+<body>
+    {msg_html}
+    <p>Addresses at these domains, or any of their subdomains, cannot subscribe.</p>
+    <ul>
+    {domains_html}
+    </ul>
+    <form action="/admin/email_domains" method="post">
+        <label>Domain
+            <input
+                type="text"
+                placeholder="e.g. example.com"
+                name="domain"
+            >
+        </label>
+        <button type="submit">Deny</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get denied email domains", skip(pool))]
+async fn get_denied_domains(pool: &PgPool) -> Result<Vec<String>, anyhow::Error> {
+    let rows = sqlx::query!(r#"SELECT domain FROM denied_email_domains ORDER BY domain"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to perform a query to retrieve denied email domains.")?;
+    Ok(rows.into_iter().map(|r| r.domain).collect())
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::email_domains_form;
+pub use post::{add_denied_domain, remove_denied_domain};
@@ -0,0 +1,71 @@
+use crate::authentication::UserId;
+use crate::domain::parse_domain;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    domain: String,
+}
+
+#[tracing::instrument(
+    name = "Deny an email domain",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, domain=%form.domain)
+)]
+pub async fn add_denied_domain(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let domain = match parse_domain(&form.domain) {
+        Ok(domain) => domain,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/email_domains"));
+        }
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO denied_email_domains (domain, added_by, added_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        domain,
+        *user_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the denied email domain.")
+    .map_err(e500)?;
+    FlashMessage::info(format!("{} has been added to the deny list.", domain)).send();
+    Ok(see_other("/admin/email_domains"))
+}
+
+#[tracing::instrument(
+    name = "Remove a denied email domain",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, domain=%form.domain)
+)]
+pub async fn remove_denied_domain(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let removed = sqlx::query!(
+        r#"DELETE FROM denied_email_domains WHERE domain = $1 RETURNING domain"#,
+        form.domain,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to remove the denied email domain.")
+    .map_err(e500)?;
+    if let Some(r) = removed {
+        FlashMessage::info(format!("{} has been removed from the deny list.", r.domain)).send();
+    }
+    Ok(see_other("/admin/email_domains"))
+}
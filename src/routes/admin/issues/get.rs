@@ -0,0 +1,406 @@
+use crate::routes::{snippet_html, snippet_options};
+use crate::staged_rollouts::{get_rollout_progress, RolloutProgress};
+use crate::subject_tests::{get_subject_test_results, SubjectTestResults};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+// Refreshes the progress of issues being sent every few seconds, until none
+// is left.
+const PROGRESS_SCRIPT: &str = r#"<script>
+    const refresh = setInterval(async () => {
+        const rows = document.querySelectorAll("tr[data-status='sending'], tr[data-status='scheduled']");
+        if (rows.length === 0) {
+            clearInterval(refresh);
+            return;
+        }
+        const response = await fetch("/admin/issues/progress");
+        if (!response.ok) {
+            return;
+        }
+        for (const issue of await response.json()) {
+            const row = document.getElementById("issue-" + issue.newsletter_issue_id);
+            if (!row) {
+                continue;
+            }
+            row.dataset.status = issue.status;
+            for (const field of ["status", "queued", "delivered", "failed"]) {
+                row.querySelector("[data-field='" + field + "']").textContent = issue[field];
+            }
+        }
+    }, 5000);
+</script>"#;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    search: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueProgress {
+    newsletter_issue_id: Uuid,
+    status: &'static str,
+    queued: i64,
+    delivered: i64,
+    failed: i64,
+}
+
+/// What admins see of an issue: drafts, scheduled and cancelled issues are
+/// stored as such, published ones are being sent, unless paused, until their
+/// queue is empty.
+fn issue_status(status: &str, paused: bool, queued: i64) -> &'static str {
+    match status {
+        "draft" => "draft",
+        "scheduled" => "scheduled",
+        "cancelled" => "cancelled",
+        _ if queued == 0 => "sent",
+        _ if paused => "paused",
+        _ => "sending",
+    }
+}
+
+/// Forms to control the delivery of an issue with the given status.
+fn actions_html(issue_id: Uuid, status: &str) -> String {
+    let actions: &[(&str, &str)] = match status {
+        "sending" => &[("pause", "Pause"), ("cancel", "Cancel")],
+        "paused" => &[("resume", "Resume"), ("cancel", "Cancel")],
+        "sent" => &[("send_to_new_subscribers", "Send to new subscribers")],
+        _ => &[],
+    };
+    let mut html = String::new();
+    if status != "draft" {
+        write!(html, r#"<a href="/admin/issues/{}">Details</a> "#, issue_id).unwrap();
+    }
+    for (action, label) in actions {
+        write!(
+            html,
+            r#"<form action="/admin/issues/{}/{}" method="post" style="display: inline;"><button type="submit">{}</button></form>"#,
+            issue_id, action, label
+        )
+        .unwrap();
+    }
+    html
+}
+
+/// Every issue with its delivery progress, most recent first.
+pub async fn issues_page(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let search = query.search.trim();
+    let issues = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.status,
+            i.slug,
+            i.hidden_from_archive,
+            i.paused_at IS NOT NULL AS "paused!",
+            coalesce(i.published_at, i.scheduled_for, i.updated_at) AS "date!",
+            p.queued AS "queued!",
+            p.delivered AS "delivered!",
+            p.failed AS "failed!",
+            CASE WHEN $1 = '' THEN NULL
+                ELSE ts_headline('english', i.text_content, query, $2)
+            END AS snippet
+        FROM newsletter_issues i
+        JOIN issue_delivery_progress p USING (newsletter_issue_id)
+        CROSS JOIN websearch_to_tsquery('english', $1) query
+        WHERE $1 = '' OR i.search_vector @@ query
+        ORDER BY
+            CASE WHEN $1 = '' THEN 0 ELSE ts_rank(i.search_vector, query) END DESC,
+            coalesce(i.published_at, i.scheduled_for, i.updated_at) DESC
+        "#,
+        search,
+        snippet_options(),
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the issues")
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for issue in &issues {
+        let status = issue_status(&issue.status, issue.paused, issue.queued);
+        let title = htmlescape::encode_minimal(&issue.title);
+        let title_html = match (status, &issue.slug) {
+            ("draft", _) => format!(
+                r#"<a href="/admin/newsletters/drafts/{}">{}</a>"#,
+                issue.newsletter_issue_id, title
+            ),
+            (_, Some(slug)) if !issue.hidden_from_archive => {
+                format!(r#"<a href="/issues/{}">{}</a>"#, slug, title)
+            }
+            _ => title,
+        };
+        let snippet_html = issue
+            .snippet
+            .as_deref()
+            .map(|snippet| format!("<br>{}", snippet_html(snippet)))
+            .unwrap_or_default();
+        writeln!(
+            rows_html,
+            r#"<tr id="issue-{id}" data-status="{status}"><td>{title_html}{snippet_html}</td><td data-field="status">{status}</td><td data-field="queued">{queued}</td><td data-field="delivered">{delivered}</td><td data-field="failed">{failed}</td><td>{date}</td><td>{actions_html}</td></tr>"#,
+            id = issue.newsletter_issue_id,
+            queued = issue.queued,
+            delivered = issue.delivered,
+            failed = issue.failed,
+            date = format_date(issue.date),
+            actions_html = actions_html(issue.newsletter_issue_id, status),
+        )
+        .unwrap();
+    }
+    if issues.is_empty() {
+        rows_html.push_str(if search.is_empty() {
+            r#"<tr><td colspan="7">There are no issues yet.</td></tr>"#
+        } else {
+            r#"<tr><td colspan="7">No issues match your search.</td></tr>"#
+        });
+    }
+    let search = htmlescape::encode_attribute(search);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Issues</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/issues" method="get">
+        <input type="search" name="search" value="{search}" aria-label="Search issues">
+        <button type="submit">Search</button>
+    </form>
+    <table>
+        <tr><th>Title</th><th>Status</th><th>Queued</th><th>Delivered</th><th>Failed</th><th>Date</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    {PROGRESS_SCRIPT}
+</body>
+</html>"#,
+        )))
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// The delivery progress of issues that are not drafts, for the issue list
+/// to refresh itself.
+pub async fn issues_progress(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let progress: Vec<_> = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.status,
+            i.paused_at IS NOT NULL AS "paused!",
+            p.queued AS "queued!",
+            p.delivered AS "delivered!",
+            p.failed AS "failed!"
+        FROM newsletter_issues i
+        JOIN issue_delivery_progress p USING (newsletter_issue_id)
+        WHERE i.status <> 'draft'
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the delivery progress")
+    .map_err(e500)?
+    .into_iter()
+    .map(|r| IssueProgress {
+        newsletter_issue_id: r.newsletter_issue_id,
+        status: issue_status(&r.status, r.paused, r.queued),
+        queued: r.queued,
+        delivered: r.delivered,
+        failed: r.failed,
+    })
+    .collect();
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+/// An issue with its delivery progress, and the results of its subject test
+/// or the progress of its stages, if any.
+pub async fn issue_details(
+    issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let issue_id = issue_id.into_inner();
+    let issue = match sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            i.status,
+            i.paused_at IS NOT NULL AS "paused!",
+            p.queued AS "queued!",
+            p.delivered AS "delivered!",
+            p.failed AS "failed!"
+        FROM newsletter_issues i
+        JOIN issue_delivery_progress p USING (newsletter_issue_id)
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?
+    {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let status = issue_status(&issue.status, issue.paused, issue.queued);
+    let subject_test_html = match get_subject_test_results(&pool, issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(results) => subject_test_html(&results),
+        None => String::new(),
+    };
+    let rollout_html = match get_rollout_progress(&pool, issue_id).await.map_err(e500)? {
+        Some(progress) => rollout_html(&progress),
+        None => String::new(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <p>Status: {status} {actions_html}</p>
+    <p>Queued: {queued}, delivered: {delivered}, failed: {failed}</p>
+    {subject_test_html}
+    {rollout_html}
+    <p><a href="/admin/issues">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            actions_html = actions_html(issue_id, status),
+            queued = issue.queued,
+            delivered = issue.delivered,
+            failed = issue.failed,
+        )))
+}
+
+fn subject_test_html(results: &SubjectTestResults) -> String {
+    let mut rows_html = String::new();
+    for variant in &results.variants {
+        let winner = if results.winning_variant == Some(variant.variant) {
+            " (winner)"
+        } else {
+            ""
+        };
+        writeln!(
+            rows_html,
+            r#"<tr data-variant="{}"><td>{}{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td><td>{}</td><td>{:.1}%</td></tr>"#,
+            variant.variant,
+            htmlescape::encode_minimal(&variant.subject),
+            winner,
+            variant.assigned,
+            variant.delivered,
+            variant.opens,
+            variant.open_rate(),
+            variant.clicks,
+            variant.click_rate(),
+        )
+        .unwrap();
+    }
+    let outcome = match results.winning_variant {
+        Some(_) => "The winning subject is being sent to the rest of the audience.".to_string(),
+        None => format!(
+            "The subject with the highest {} rate is picked after {}, then sent to the rest of the audience.",
+            results.metric.trim_end_matches('s'),
+            format_date(results.decide_at)
+        ),
+    };
+    format!(
+        r#"<h2>Subject test</h2>
+    <p>Each subject is sent to {}% of the audience. {}</p>
+    <table>
+        <tr><th>Subject</th><th>Assigned</th><th>Delivered</th><th>Opens</th><th>Open rate</th><th>Clicks</th><th>Click rate</th></tr>
+        {}
+    </table>"#,
+        results.variant_share, outcome, rows_html
+    )
+}
+
+fn rollout_html(progress: &RolloutProgress) -> String {
+    let mut rows_html = String::new();
+    for (stage, p) in progress.stages.iter().enumerate() {
+        let attempted = p.delivered + p.failed;
+        let failure_rate = if attempted == 0 {
+            0.0
+        } else {
+            (p.failed + p.bounced) as f64 * 100.0 / attempted as f64
+        };
+        writeln!(
+            rows_html,
+            r#"<tr data-stage="{}"><td>{}</td><td>{}%</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>"#,
+            stage + 1,
+            stage + 1,
+            p.percentage,
+            p.queued,
+            p.delivered,
+            p.failed,
+            p.bounced,
+            failure_rate,
+        )
+        .unwrap();
+    }
+    let current_stage = progress.current_stage + 1;
+    let state = match progress.halted_failure_rate {
+        Some(failure_rate) => format!(
+            "Sending was paused automatically: {:.1}% of the emails of stage {} failed or bounced, over the limit of {}%. Resuming the issue moves on to the next stage.",
+            failure_rate, current_stage, progress.max_failure_rate
+        ),
+        None if current_stage as usize == progress.stages.len() => {
+            "Every stage has been released.".to_string()
+        }
+        None => format!(
+            "Stage {} of {} is being sent. The next one starts {} minutes after it is, unless more than {}% of its emails failed or bounced.",
+            current_stage,
+            progress.stages.len(),
+            progress.settle_minutes,
+            progress.max_failure_rate
+        ),
+    };
+    format!(
+        r#"<h2>Stages</h2>
+    <p>{}</p>
+    <table>
+        <tr><th>Stage</th><th>Audience sent to</th><th>Queued</th><th>Delivered</th><th>Failed</th><th>Bounced</th><th>Failure rate</th></tr>
+        {}
+    </table>"#,
+        state, rows_html
+    )
+}
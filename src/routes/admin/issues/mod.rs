@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{issue_details, issues_page, issues_progress};
+pub use post::{cancel_issue, pause_issue, resume_issue, send_to_new_subscribers};
@@ -0,0 +1,212 @@
+use crate::authentication::UserId;
+use crate::issue_delivery_worker::{enqueue_delivery_tasks, Recipients};
+use crate::segments::Segment;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stop sending an issue until it is resumed. Emails being sent at the time
+/// still go out.
+#[tracing::instrument(name = "Pause an issue", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn pause_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let paused = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET paused_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'published' AND paused_at IS NULL
+        AND EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = $1
+        )
+        "#,
+        issue_id.into_inner(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to pause the issue")
+    .map_err(e500)?
+    .rows_affected()
+        == 1;
+    if paused {
+        FlashMessage::info("The issue is paused: no more emails will go out until it is resumed.")
+            .send();
+    } else {
+        FlashMessage::error("Only issues being sent can be paused.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[tracing::instrument(name = "Resume an issue", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn resume_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let resumed = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET paused_at = NULL
+        WHERE newsletter_issue_id = $1 AND status = 'published' AND paused_at IS NOT NULL
+        "#,
+        issue_id.into_inner(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to resume the issue")
+    .map_err(e500)?
+    .rows_affected()
+        == 1;
+    if resumed {
+        FlashMessage::info("The issue is being sent again.").send();
+    } else {
+        FlashMessage::error("Only paused issues can be resumed.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+/// Stop sending an issue for good. Who it was already sent to is kept in
+/// `issue_deliveries`.
+#[tracing::instrument(name = "Cancel an issue", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn cancel_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Waits for emails being sent to go out, as the worker locks the issue
+    // while sending them.
+    let cancelled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled', paused_at = NULL, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'published'
+        AND EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = $1
+        )
+        "#,
+        issue_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to cancel the issue")
+    .map_err(e500)?
+    .rows_affected()
+        == 1;
+    if !cancelled {
+        FlashMessage::error("Only issues being sent can be cancelled.").send();
+        return Ok(see_other("/admin/issues"));
+    }
+    let n_unsent = sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1",
+        issue_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the remaining delivery tasks")
+    .map_err(e500)?
+    .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel the issue")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "The issue is cancelled: {} emails will not be sent.",
+        n_unsent
+    ))
+    .send();
+    Ok(see_other("/admin/issues"))
+}
+
+/// Send an issue to those who joined its list, and its segment, since it
+/// was published.
+#[tracing::instrument(
+    name = "Send an issue to new subscribers",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn send_to_new_subscribers(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT list_id, segment_expression, recipients_of, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'published'
+        FOR UPDATE
+        "#,
+        issue_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?;
+    let issue = match issue {
+        Some(issue) => issue,
+        None => {
+            FlashMessage::error("Only published issues can be sent to new subscribers.").send();
+            return Ok(see_other("/admin/issues"));
+        }
+    };
+    // Those who joined since never got the issue it was sent after.
+    if issue.recipients_of.is_some() {
+        FlashMessage::error(
+            "The issue was only sent to the recipients of another one: it cannot be sent to new subscribers.",
+        )
+        .send();
+        return Ok(see_other("/admin/issues"));
+    }
+    let segment = issue
+        .segment_expression
+        .as_deref()
+        .map(Segment::parse)
+        .transpose()
+        .context("The segment of the issue is invalid")
+        .map_err(e500)?;
+    let n_queued = enqueue_delivery_tasks(
+        &mut transaction,
+        issue_id,
+        issue.list_id,
+        segment.as_ref(),
+        &Recipients::ConfirmedSince(issue.published_at),
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to send the issue")
+        .map_err(e500)?;
+    if n_queued == 0 {
+        FlashMessage::error("Nobody has subscribed since the issue was published.").send();
+    } else {
+        FlashMessage::info(format!(
+            "The issue is being sent to {} new subscribers.",
+            n_queued
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/issues"))
+}
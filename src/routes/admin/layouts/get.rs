@@ -0,0 +1,269 @@
+use crate::newsletter_content::Layout;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const DEFAULT_HTML_TEMPLATE: &str = r#"{{ content }}
+<p style="font-size: 12px; color: #777777;">
+    You are receiving this email because you subscribed to our newsletter.
+    <a href="{{ unsubscribe_url }}">Unsubscribe</a>
+</p>"#;
+const DEFAULT_TEXT_TEMPLATE: &str = "{{ content }}
+
+--
+You are receiving this email because you subscribed to our newsletter.
+Unsubscribe: {{ unsubscribe_url }}";
+
+pub struct SavedLayout {
+    pub layout_id: Uuid,
+    pub name: String,
+    pub issues: i64,
+}
+
+pub async fn layouts_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let layouts = get_layouts(&pool).await.map_err(e500)?;
+    let mut layouts_html = String::new();
+    for layout in &layouts {
+        writeln!(
+            layouts_html,
+            r#"<li><a href="/admin/layouts/{}">{}</a> - used by {} issues</li>"#,
+            layout.layout_id,
+            htmlescape::encode_minimal(&layout.name),
+            layout.issues,
+        )
+        .unwrap();
+    }
+    if layouts.is_empty() {
+        layouts_html.push_str("<li>There are no layouts.</li>");
+    }
+    let form_html = layout_form_html(
+        "/admin/layouts",
+        "",
+        DEFAULT_HTML_TEMPLATE,
+        DEFAULT_TEXT_TEMPLATE,
+        "Create layout",
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Layouts</title>
+</head>
+<body>
+    {msg_html}
+    <ul>
+    {layouts_html}
+    </ul>
+    {form_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn edit_layout_form(
+    layout_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let layout_id = layout_id.into_inner();
+    let layout = match sqlx::query!(
+        r#"
+        SELECT name, html_template, text_template
+        FROM newsletter_layouts
+        WHERE layout_id = $1
+        "#,
+        layout_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve a layout.")
+    .map_err(e500)?
+    {
+        Some(layout) => layout,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let form_html = layout_form_html(
+        &format!("/admin/layouts/{}", layout_id),
+        &layout.name,
+        &layout.html_template,
+        &layout.text_template,
+        "Save layout",
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit layout</title>
+</head>
+<body>
+    {msg_html}
+    {form_html}
+    <form action="/admin/layouts/{layout_id}/delete" method="post">
+        <button type="submit">Delete layout</button>
+    </form>
+    <p><a href="/admin/layouts">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn layout_form_html(
+    action: &str,
+    name: &str,
+    html_template: &str,
+    text_template: &str,
+    submit: &str,
+) -> String {
+    format!(
+        r#"<p>
+        Both templates need a <code>{{{{ content }}}}</code> slot for the issue,
+        and may use the same merge tags as issues.
+    </p>
+    <form action="{action}" method="post">
+        <label>Name<br>
+            <input type="text" placeholder="e.g. Branded" name="name" value="{name}">
+        </label>
+        <br>
+        <label>HTML template:<br>
+            <textarea name="html_template" rows="15" cols="50">{html_template}</textarea>
+        </label>
+        <br>
+        <label>Plain text template:<br>
+            <textarea name="text_template" rows="15" cols="50">{text_template}</textarea>
+        </label>
+        <br>
+        <button type="submit">{submit}</button>
+    </form>"#,
+        name = htmlescape::encode_attribute(name),
+        html_template = htmlescape::encode_minimal(html_template),
+        text_template = htmlescape::encode_minimal(text_template),
+    )
+}
+
+#[tracing::instrument(name = "Get layouts", skip(pool))]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<SavedLayout>, anyhow::Error> {
+    let layouts = sqlx::query_as!(
+        SavedLayout,
+        r#"
+        SELECT l.layout_id, l.name, count(i.newsletter_issue_id) AS "issues!"
+        FROM newsletter_layouts l
+        LEFT JOIN newsletter_issues i ON i.layout_id = l.layout_id
+        GROUP BY l.layout_id
+        ORDER BY l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve layouts.")?;
+    Ok(layouts)
+}
+
+/// The `<option>`s of the layout picker, selecting `layout_id` if any.
+pub async fn layout_options(
+    pool: &PgPool,
+    layout_id: Option<Uuid>,
+) -> Result<String, anyhow::Error> {
+    let mut layout_options_html = String::new();
+    for layout in get_layouts(pool).await? {
+        writeln!(
+            layout_options_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            htmlescape::encode_attribute(&layout.name),
+            if Some(layout.layout_id) == layout_id {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&layout.name),
+        )
+        .unwrap();
+    }
+    Ok(layout_options_html)
+}
+
+/// Look up the layout an issue is wrapped in, if any.
+pub async fn get_layout(
+    pool: &PgPool,
+    layout_id: Option<Uuid>,
+) -> Result<Option<Layout>, anyhow::Error> {
+    let layout_id = match layout_id {
+        Some(layout_id) => layout_id,
+        None => return Ok(None),
+    };
+    let saved = sqlx::query!(
+        r#"
+        SELECT html_template, text_template
+        FROM newsletter_layouts
+        WHERE layout_id = $1
+        "#,
+        layout_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to look up the layout")?;
+    Layout::parse(saved.html_template, saved.text_template)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("A saved layout is invalid")
+}
+
+/// Look up the layout called `name`, if any. The inner error is an HTML
+/// message for the admin.
+pub async fn resolve_layout(
+    pool: &PgPool,
+    name: &str,
+) -> Result<Result<Option<(Uuid, Layout)>, String>, anyhow::Error> {
+    if name.is_empty() {
+        return Ok(Ok(None));
+    }
+    let saved = sqlx::query!(
+        r#"
+        SELECT layout_id, html_template, text_template
+        FROM newsletter_layouts
+        WHERE name = $1
+        "#,
+        name,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the layout")?;
+    match saved {
+        Some(saved) => Ok(Ok(Some((
+            saved.layout_id,
+            Layout::parse(saved.html_template, saved.text_template)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("A saved layout is invalid")?,
+        )))),
+        None => Ok(Err(format!(
+            "There is no layout called {}.",
+            htmlescape::encode_minimal(name)
+        ))),
+    }
+}
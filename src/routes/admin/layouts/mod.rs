@@ -0,0 +1,7 @@
+mod get;
+mod post;
+
+pub use get::{
+    edit_layout_form, get_layout, get_layouts, layout_options, layouts_page, resolve_layout,
+};
+pub use post::{create_layout, delete_layout, save_layout};
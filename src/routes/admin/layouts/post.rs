@@ -0,0 +1,167 @@
+use crate::authentication::UserId;
+use crate::newsletter_content::Layout;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    html_template: String,
+    text_template: String,
+}
+
+fn layout_location(layout_id: Uuid) -> String {
+    format!("/admin/layouts/{}", layout_id)
+}
+
+/// Check the form, describing the first error found as HTML.
+fn validate(form: &FormData) -> Result<(), String> {
+    if form.name.trim().is_empty() {
+        return Err("The layout name cannot be empty.".into());
+    }
+    Layout::parse(form.html_template.clone(), form.text_template.clone())
+        .map_err(|e| htmlescape::encode_minimal(&e))?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Create a layout",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, name=%form.name)
+)]
+pub async fn create_layout(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(e) = validate(&form) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    let name = form.name.trim();
+    let layout_id = Uuid::new_v4();
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_layouts (layout_id, name, html_template, text_template, updated_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        layout_id,
+        name,
+        form.html_template,
+        form.text_template,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the layout.")
+    .map_err(e500)?
+    .rows_affected()
+        == 1;
+    if !created {
+        FlashMessage::error(format!(
+            "There already is a layout called {}.",
+            htmlescape::encode_minimal(name)
+        ))
+        .send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    FlashMessage::info("The layout has been created.").send();
+    Ok(see_other(&layout_location(layout_id)))
+}
+
+/// Save changes to a layout, which apply to issues that are yet to be sent.
+#[tracing::instrument(
+    name = "Save a layout",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, name=%form.name)
+)]
+pub async fn save_layout(
+    layout_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = layout_id.into_inner();
+    if let Err(e) = validate(&form) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&layout_location(layout_id)));
+    }
+    let name = form.name.trim();
+    let name_taken = sqlx::query!(
+        r#"
+        SELECT layout_id FROM newsletter_layouts
+        WHERE name = $1 AND layout_id <> $2
+        "#,
+        name,
+        layout_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up the layout name.")
+    .map_err(e500)?
+    .is_some();
+    if name_taken {
+        FlashMessage::error(format!(
+            "There already is a layout called {}.",
+            htmlescape::encode_minimal(name)
+        ))
+        .send();
+        return Ok(see_other(&layout_location(layout_id)));
+    }
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_layouts
+        SET name = $2, html_template = $3, text_template = $4, updated_at = now()
+        WHERE layout_id = $1
+        "#,
+        layout_id,
+        name,
+        form.html_template,
+        form.text_template,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the layout.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The layout has been saved.").send();
+    Ok(see_other(&layout_location(layout_id)))
+}
+
+/// Delete a layout, as long as no issue uses it.
+#[tracing::instrument(name = "Delete a layout", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn delete_layout(
+    layout_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = layout_id.into_inner();
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_layouts l
+        WHERE layout_id = $1
+        AND NOT EXISTS (
+            SELECT 1 FROM newsletter_issues i WHERE i.layout_id = l.layout_id
+        )
+        "#,
+        layout_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the layout.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_deleted_rows == 0 {
+        FlashMessage::error("The layout is used by issues, or no longer exists.").send();
+        return Ok(see_other(&layout_location(layout_id)));
+    }
+    FlashMessage::info("The layout has been deleted.").send();
+    Ok(see_other("/admin/layouts"))
+}
@@ -0,0 +1,118 @@
+use crate::domain::DEFAULT_LIST_SLUG;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub confirmed_members: i64,
+}
+
+pub async fn lists_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let mut lists_html = String::new();
+    for list in lists {
+        writeln!(
+            lists_html,
+            "<li>{} (<code>{}</code>) - {} confirmed subscribers</li>",
+            htmlescape::encode_minimal(&list.name),
+            htmlescape::encode_minimal(&list.slug),
+            list.confirmed_members,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailing lists</title>
+</head>
+<body>
+    {msg_html}
+    <ul>
+    {lists_html}
+    </ul>
+    <form action="/admin/lists" method="post">
+        <label>Name
+            <input
+                type="text"
+                placeholder="e.g. Product updates"
+                name="name"
+            >
+        </label>
+        <label>Slug
+            <input
+                type="text"
+                placeholder="e.g. product-updates"
+                name="slug"
+            >
+        </label>
+        <button type="submit">Create list</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT
+            l.list_id,
+            l.slug,
+            l.name,
+            count(m.subscriber_id) AS "confirmed_members!"
+        FROM lists l
+        LEFT JOIN list_memberships m
+            ON m.list_id = l.list_id AND m.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.created_at, l.slug
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve mailing lists.")?;
+    Ok(lists)
+}
+
+/// Check that `list_id` exists, falling back to the default list when it is
+/// not given.
+#[tracing::instrument(skip(pool))]
+pub async fn get_list_id_or_default(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT list_id FROM lists
+        WHERE list_id = $1 OR ($1::uuid IS NULL AND slug = $2)
+        "#,
+        list_id,
+        DEFAULT_LIST_SLUG,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| r.list_id))
+}
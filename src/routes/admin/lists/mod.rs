@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{get_list_id_or_default, get_lists, lists_form, MailingList};
+pub use post::create_list;
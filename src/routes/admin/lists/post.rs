@@ -0,0 +1,69 @@
+use crate::authentication::UserId;
+use crate::domain::ListSlug;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    slug: String,
+}
+
+#[tracing::instrument(
+    name = "Create a mailing list",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, slug=%form.slug)
+)]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { name, slug } = form.0;
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list name cannot be empty.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    let slug = match ListSlug::parse(slug.trim().to_string()) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the mailing list.")
+    .map_err(e500)?
+    .rows_affected()
+        == 1;
+    if created {
+        FlashMessage::info(format!(
+            "The {} list has been created.",
+            htmlescape::encode_minimal(name)
+        ))
+        .send();
+    } else {
+        FlashMessage::error(format!(
+            "A list with the slug {} already exists.",
+            slug.as_ref()
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -1,9 +1,29 @@
+mod archive;
 mod dashboard;
+mod email_domains;
+mod issues;
+mod layouts;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod segments;
+mod subscriber_attributes;
+mod subscriber_export;
+mod subscriber_import;
+mod subscribers;
 
+pub use archive::*;
 pub use dashboard::admin_dashboard;
+pub use email_domains::*;
+pub use issues::*;
+pub use layouts::*;
+pub use lists::*;
 pub use logout::log_out;
 pub use newsletter::*;
 pub use password::*;
+pub use segments::*;
+pub use subscriber_attributes::*;
+pub use subscriber_export::*;
+pub use subscriber_import::*;
+pub use subscribers::*;
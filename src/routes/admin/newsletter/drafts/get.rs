@@ -0,0 +1,357 @@
+use super::super::get::{audience_options, recipients_options, rollout_options};
+use super::super::preview::{get_user_email, preview_controls_html};
+use crate::authentication::UserId;
+use crate::configuration::RolloutConfiguration;
+use crate::routes::admin::layouts::layout_options;
+use crate::subject_tests::{DEFAULT_VARIANT_SHARE, DEFAULT_WAIT_HOURS};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+// Saves the draft form every few seconds if it changed since the last save.
+const AUTOSAVE_SCRIPT: &str = r#"<script>
+    const form = document.getElementById("draft");
+    const status = document.getElementById("autosave-status");
+    let saved = new URLSearchParams(new FormData(form)).toString();
+    setInterval(async () => {
+        const body = new URLSearchParams(new FormData(form)).toString();
+        if (body === saved) {
+            return;
+        }
+        const response = await fetch(form.dataset.autosave, {
+            method: "POST",
+            headers: { "Content-Type": "application/x-www-form-urlencoded" },
+            body,
+        });
+        if (response.ok) {
+            saved = body;
+            status.textContent = "Saved at " + new Date().toLocaleTimeString() + ".";
+        } else {
+            status.textContent = "The draft could not be saved automatically.";
+        }
+    }, 10000);
+</script>"#;
+
+pub async fn drafts_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let drafts = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, updated_at
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve drafts.")
+    .map_err(e500)?;
+    let mut drafts_html = String::new();
+    for draft in &drafts {
+        let title = if draft.title.trim().is_empty() {
+            "(untitled)".to_string()
+        } else {
+            htmlescape::encode_minimal(&draft.title)
+        };
+        writeln!(
+            drafts_html,
+            r#"<li><a href="/admin/newsletters/drafts/{}">{}</a> - last saved {}</li>"#,
+            draft.newsletter_issue_id,
+            title,
+            draft.updated_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+    if drafts.is_empty() {
+        drafts_html.push_str("<li>There are no drafts.</li>");
+    }
+
+    let scheduled = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, scheduled_for AS "scheduled_for!", publish_error
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY scheduled_for
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve scheduled issues.")
+    .map_err(e500)?;
+    let mut scheduled_html = String::new();
+    for issue in &scheduled {
+        let publish_error = match &issue.publish_error {
+            Some(e) => format!(
+                " - <b>could not be published: {}</b> Reschedule it to try again.",
+                htmlescape::encode_minimal(e)
+            ),
+            None => String::new(),
+        };
+        writeln!(
+            scheduled_html,
+            r#"<li>{title} - to be published on {scheduled_for}{publish_error}
+    <form action="/admin/newsletters/drafts/{id}/schedule" method="post">
+        <input type="datetime-local" name="scheduled_for">
+        <input type="text" name="timezone" value="UTC">
+        <button type="submit">Reschedule</button>
+    </form>
+    <form action="/admin/newsletters/drafts/{id}/unschedule" method="post">
+        <button type="submit">Cancel schedule</button>
+    </form>
+</li>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            scheduled_for = issue.scheduled_for.format("%Y-%m-%d %H:%M UTC"),
+            id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    if scheduled.is_empty() {
+        scheduled_html.push_str("<li>There are no scheduled issues.</li>");
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Drafts</h2>
+    <ul>
+    {drafts_html}
+    </ul>
+    <h2>Scheduled</h2>
+    <ul>
+    {scheduled_html}
+    </ul>
+    <p><a href="/admin/newsletters">Write a new issue</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    rollout_configuration: web::Data<RolloutConfiguration>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let draft_id = draft_id.into_inner();
+    let draft = match sqlx::query!(
+        r#"
+        SELECT
+            list_id,
+            segment_name,
+            recipients_of,
+            layout_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            test_subjects,
+            test_share,
+            test_wait_hours,
+            test_metric,
+            staged_rollout
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve a draft.")
+    .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let (list_options_html, segment_options_html) = audience_options(
+        &pool,
+        Some(draft.list_id),
+        draft.segment_name.as_deref().unwrap_or_default(),
+    )
+    .await
+    .map_err(e500)?;
+    let recipients_options_html = recipients_options(&pool, draft.recipients_of)
+        .await
+        .map_err(e500)?;
+    let rollout_options_html = rollout_options(&rollout_configuration, draft.staged_rollout);
+    let layout_options_html = layout_options(&pool, draft.layout_id).await.map_err(e500)?;
+    let title = htmlescape::encode_attribute(&draft.title);
+    let text_content = htmlescape::encode_minimal(&draft.text_content);
+    let html_content = htmlescape::encode_minimal(&draft.html_content);
+    let markdown_content =
+        htmlescape::encode_minimal(draft.markdown_content.as_deref().unwrap_or_default());
+    let [subject_b, subject_c, subject_d] = [0, 1, 2].map(|i| {
+        htmlescape::encode_attribute(draft.test_subjects.get(i).map_or("", String::as_str))
+    });
+    let test_share = draft
+        .test_share
+        .unwrap_or_else(|| DEFAULT_VARIANT_SHARE.into());
+    let test_wait_hours = draft.test_wait_hours.unwrap_or(DEFAULT_WAIT_HOURS as i16);
+    let clicks_selected = if draft.test_metric.as_deref() == Some("clicks") {
+        " selected"
+    } else {
+        ""
+    };
+    let my_email = get_user_email(&pool, **user_id).await.map_err(e500)?;
+    let preview_controls_html = preview_controls_html(my_email.as_deref());
+    let idempotency_key = Uuid::new_v4();
+    let autosave_script = AUTOSAVE_SCRIPT;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Edit draft</title>
+</head>
+<body>
+{msg_html}
+<form
+    id="draft"
+    action="/admin/newsletters/drafts/{draft_id}"
+    method="post"
+    data-autosave="/admin/newsletters/drafts/{draft_id}/autosave"
+>
+    <label>List:<br>
+        <select name="list_id">
+            {list_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Segment:<br>
+        <select name="segment">
+            <option value="">Everyone on the list</option>
+            {segment_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Only send to:<br>
+        <select name="recipients_of">
+            <option value="">The list and segment above</option>
+            {recipients_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Delivery:<br>
+        <select name="rollout">
+            {rollout_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Layout:<br>
+        <select name="layout">
+            <option value="">No layout</option>
+            {layout_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Title:<br>
+        <input
+            type="text"
+            placeholder="Enter the issue title"
+            name="title"
+            value="{title}"
+        >
+    </label>
+    <br>
+    <fieldset>
+        <legend>Subject test (optional)</legend>
+        <p>Alternatives to the title, each sent to a share of the audience before the best one goes to the rest.</p>
+        <label>Subject B: <input type="text" name="subject_b" value="{subject_b}"></label><br>
+        <label>Subject C: <input type="text" name="subject_c" value="{subject_c}"></label><br>
+        <label>Subject D: <input type="text" name="subject_d" value="{subject_d}"></label><br>
+        <label>Share of the audience per subject, in %:
+            <input type="number" name="test_share" min="1" max="25" value="{test_share}">
+        </label><br>
+        <label>Pick the winner after, in hours:
+            <input type="number" name="test_wait_hours" min="1" max="168" value="{test_wait_hours}">
+        </label><br>
+        <label>Winner:
+            <select name="test_metric">
+                <option value="opens">Highest open rate</option>
+                <option value="clicks"{clicks_selected}>Highest click rate</option>
+            </select>
+        </label>
+    </fieldset>
+    <label>Markdown content (optional, generates both parts below when filled in):<br>
+        <textarea
+            placeholder="Enter the content in Markdown"
+            name="markdown_content"
+            rows="20"
+            cols="50"
+        >{markdown_content}</textarea>
+    </label>
+    <br>
+    <label>Plain text content:<br>
+        <textarea
+            placeholder="Enter the content in plain text"
+            name="text_content"
+            rows="20"
+            cols="50"
+        >{text_content}</textarea>
+    </label>
+    <br>
+    <label>HTML content:<br>
+        <textarea
+            placeholder="Enter the content in HTML format"
+            name="html_content"
+            rows="20"
+            cols="50"
+        >{html_content}</textarea>
+    </label>
+    <br>
+    <button type="submit">Save draft</button>
+    {preview_controls_html}
+</form>
+<p id="autosave-status"></p>
+<form action="/admin/newsletters/drafts/{draft_id}/publish" method="post">
+    <input hidden type="text" name="idempotency_key" value="{idempotency_key}" />
+    <button type="submit">Publish the saved draft</button>
+</form>
+<form action="/admin/newsletters/drafts/{draft_id}/schedule" method="post">
+    <label>Publish the saved draft on:
+        <input type="datetime-local" name="scheduled_for">
+    </label>
+    <label>Time zone:
+        <input type="text" name="timezone" value="UTC">
+    </label>
+    <button type="submit">Schedule</button>
+</form>
+<form action="/admin/newsletters/drafts/{draft_id}/delete" method="post">
+    <button type="submit">Delete draft</button>
+</form>
+<p><a href="/admin/newsletters/drafts">&lt;- Back</a></p>
+{autosave_script}
+</body>
+</html>"#,
+        )))
+}
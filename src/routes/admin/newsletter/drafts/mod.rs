@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod schedule;
+
+pub use get::{drafts_page, edit_draft_form};
+pub use post::{autosave_draft, create_draft, delete_draft, publish_draft, save_draft};
+pub use schedule::{schedule_draft, unschedule_issue};
@@ -0,0 +1,500 @@
+use super::super::post::{
+    check_rollout, parse_recipients_of, parse_rollout, prepare_content, resolve_recipients,
+    resolve_recipients_of, resolve_segment, send_flash_messages, success_message,
+};
+use crate::authentication::UserId;
+use crate::configuration::RolloutConfiguration;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_delivery_worker::{enqueue_delivery_tasks, Recipients};
+use crate::newsletter_content::issue_parts;
+use crate::routes::admin::layouts::{get_layout, resolve_layout};
+use crate::routes::admin::lists::get_list_id_or_default;
+use crate::routes::issue_slug;
+use crate::staged_rollouts::start_issue_rollout;
+use crate::subject_tests::{start_issue_subject_test, SubjectTest, SubjectTestFields};
+use crate::utils::{e400, e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    markdown_content: String,
+    list_id: Option<Uuid>,
+    #[serde(default)]
+    segment: String,
+    #[serde(default)]
+    recipients_of: String,
+    #[serde(default)]
+    layout: String,
+    #[serde(default)]
+    subject_b: String,
+    #[serde(default)]
+    subject_c: String,
+    #[serde(default)]
+    subject_d: String,
+    test_share: Option<u8>,
+    test_wait_hours: Option<u16>,
+    #[serde(default)]
+    test_metric: String,
+    #[serde(default)]
+    rollout: String,
+}
+
+impl DraftFormData {
+    // Drafts written in Markdown store the generated parts next to the
+    // source, so they are published like any other issue.
+    fn parts(&self) -> (String, String) {
+        issue_parts(
+            &self.markdown_content,
+            &self.text_content,
+            &self.html_content,
+        )
+    }
+
+    fn markdown_content(&self) -> Option<&str> {
+        Some(self.markdown_content.as_str()).filter(|s| !s.trim().is_empty())
+    }
+
+    // Checked when the draft is published or scheduled.
+    fn subject_test(&self) -> SubjectTestFields<'_> {
+        SubjectTestFields {
+            alternatives: [&self.subject_b, &self.subject_c, &self.subject_d],
+            variant_share: self.test_share,
+            wait_hours: self.test_wait_hours,
+            metric: &self.test_metric,
+        }
+    }
+
+    /// The inner error is an HTML message for the admin.
+    async fn layout_id(
+        &self,
+        pool: &PgPool,
+    ) -> Result<Result<Option<Uuid>, String>, actix_web::Error> {
+        Ok(resolve_layout(pool, &self.layout)
+            .await
+            .map_err(e500)?
+            .map(|layout| layout.map(|(layout_id, _)| layout_id)))
+    }
+
+    /// The inner error is an HTML message for the admin.
+    async fn recipients_of(
+        &self,
+        pool: &PgPool,
+    ) -> Result<Result<Option<Uuid>, String>, actix_web::Error> {
+        let issue_id = match parse_recipients_of(&self.recipients_of)? {
+            Some(issue_id) => issue_id,
+            None => return Ok(Ok(None)),
+        };
+        Ok(resolve_recipients_of(pool, issue_id)
+            .await
+            .map_err(e500)?
+            .map(|_| Some(issue_id)))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishFormData {
+    idempotency_key: String,
+}
+
+pub(super) fn draft_location(draft_id: Uuid) -> String {
+    format!("/admin/newsletters/drafts/{}", draft_id)
+}
+
+pub(super) fn missing_draft() -> HttpResponse {
+    FlashMessage::error("This draft has been published or deleted.").send();
+    see_other("/admin/newsletters/drafts")
+}
+
+/// Save the content of the newsletter form as a new draft.
+#[tracing::instrument(name = "Create a draft", skip(form, pool, user_id), fields(user_id=%*user_id))]
+pub async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list_id = match get_list_id_or_default(&pool, form.list_id)
+        .await
+        .context("Failed to look up the mailing list")
+        .map_err(e500)?
+    {
+        Some(list_id) => list_id,
+        None => {
+            FlashMessage::error("The selected mailing list does not exist.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let layout_id = match form.layout_id(&pool).await? {
+        Ok(layout_id) => layout_id,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let recipients_of = match form.recipients_of(&pool).await? {
+        Ok(recipients_of) => recipients_of,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let draft_id = Uuid::new_v4();
+    let (text_content, html_content) = form.parts();
+    let subject_test = form.subject_test();
+    let staged_rollout = parse_rollout(&form.rollout)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            segment_name,
+            recipients_of,
+            layout_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            test_subjects,
+            test_share,
+            test_wait_hours,
+            test_metric,
+            staged_rollout,
+            status,
+            updated_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, 'draft', now()
+        )
+        "#,
+        draft_id,
+        list_id,
+        Some(form.segment.as_str()).filter(|s| !s.is_empty()),
+        recipients_of,
+        layout_id,
+        form.title,
+        text_content,
+        html_content,
+        form.markdown_content(),
+        &subject_test.test_subjects(),
+        subject_test.test_share(),
+        subject_test.test_wait_hours(),
+        subject_test.test_metric(),
+        staged_rollout,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the draft")
+    .map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&draft_location(draft_id)))
+}
+
+#[tracing::instrument(name = "Save a draft", skip(form, pool, user_id), fields(user_id=%*user_id))]
+pub async fn save_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    match update_draft(&pool, draft_id, &form).await? {
+        Ok(true) => {
+            FlashMessage::info("The draft has been saved.").send();
+            Ok(see_other(&draft_location(draft_id)))
+        }
+        Ok(false) => Ok(missing_draft()),
+        Err(e) => {
+            FlashMessage::error(e).send();
+            Ok(see_other(&draft_location(draft_id)))
+        }
+    }
+}
+
+/// Save a draft in the background while it is being edited.
+#[tracing::instrument(name = "Autosave a draft", skip(form, pool, user_id), fields(user_id=%*user_id))]
+pub async fn autosave_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match update_draft(&pool, draft_id.into_inner(), &form).await? {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().finish()),
+        Err(e) => Err(e400(e)),
+    }
+}
+
+/// The inner result is `false` if there is no such draft, or an error for
+/// the admin.
+async fn update_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    form: &DraftFormData,
+) -> Result<Result<bool, String>, actix_web::Error> {
+    let list_id = match get_list_id_or_default(pool, form.list_id)
+        .await
+        .context("Failed to look up the mailing list")
+        .map_err(e500)?
+    {
+        Some(list_id) => list_id,
+        None => return Ok(Err("The selected mailing list does not exist.".into())),
+    };
+    let layout_id = match form.layout_id(pool).await? {
+        Ok(layout_id) => layout_id,
+        Err(e) => return Ok(Err(e)),
+    };
+    let recipients_of = match form.recipients_of(pool).await? {
+        Ok(recipients_of) => recipients_of,
+        Err(e) => return Ok(Err(e)),
+    };
+    let (text_content, html_content) = form.parts();
+    let subject_test = form.subject_test();
+    let staged_rollout = parse_rollout(&form.rollout)?;
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            list_id = $2,
+            segment_name = $3,
+            recipients_of = $4,
+            layout_id = $5,
+            title = $6,
+            text_content = $7,
+            html_content = $8,
+            markdown_content = $9,
+            test_subjects = $10,
+            test_share = $11,
+            test_wait_hours = $12,
+            test_metric = $13,
+            staged_rollout = $14,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id,
+        list_id,
+        Some(form.segment.as_str()).filter(|s| !s.is_empty()),
+        recipients_of,
+        layout_id,
+        form.title,
+        text_content,
+        html_content,
+        form.markdown_content(),
+        &subject_test.test_subjects(),
+        subject_test.test_share(),
+        subject_test.test_wait_hours(),
+        subject_test.test_metric(),
+        staged_rollout,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the draft")
+    .map_err(e500)?
+    .rows_affected();
+    Ok(Ok(n_updated_rows == 1))
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id.into_inner(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the draft")
+    .map_err(e500)?
+    .rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(missing_draft());
+    }
+    FlashMessage::info("The draft has been deleted.").send();
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+/// Publish a draft, queueing it for delivery. Like the newsletter form,
+/// submissions are deduplicated with an idempotency key.
+#[tracing::instrument(
+    name = "Publish a draft",
+    skip(form, pool, rollout_configuration, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<PublishFormData>,
+    pool: web::Data<PgPool>,
+    rollout_configuration: web::Data<RolloutConfiguration>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft_id = draft_id.into_inner();
+    let idempotency_key: IdempotencyKey = form.0.idempotency_key.try_into().map_err(e400)?;
+    // Published drafts are looked up too, for retries to get the saved
+    // response rather than an error.
+    let draft = match sqlx::query!(
+        r#"
+        SELECT
+            list_id,
+            segment_name,
+            recipients_of,
+            layout_id,
+            title,
+            text_content,
+            html_content,
+            test_subjects,
+            test_share,
+            test_wait_hours,
+            test_metric,
+            staged_rollout,
+            status
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        draft_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the draft")
+    .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(missing_draft()),
+    };
+    let mut list_id = draft.list_id;
+    let mut segment = None;
+    let mut recipients = Recipients::Everyone;
+    let mut html_content = draft.html_content;
+    let mut warnings = Vec::new();
+    if draft.status == "draft" {
+        let layout = get_layout(&pool, draft.layout_id).await.map_err(e500)?;
+        (html_content, warnings) = match prepare_content(
+            &draft.title,
+            &draft.text_content,
+            &html_content,
+            layout.as_ref(),
+        ) {
+            Ok(prepared) => prepared,
+            Err(errors) => {
+                send_flash_messages(FlashMessage::error, &errors);
+                return Ok(see_other(&draft_location(draft_id)));
+            }
+        };
+        let subject_test = match SubjectTest::from_issue(
+            &draft.title,
+            &draft.test_subjects,
+            draft.test_share,
+            draft.test_wait_hours,
+            draft.test_metric.as_deref(),
+        ) {
+            Ok(subject_test) => subject_test,
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&draft_location(draft_id)));
+            }
+        };
+        if let Err(e) = check_rollout(draft.staged_rollout, subject_test.as_ref()) {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_location(draft_id)));
+        }
+        let segment_name = draft.segment_name.unwrap_or_default();
+        let saved_segment = match resolve_segment(&pool, &segment_name).await.map_err(e500)? {
+            Ok(segment) => segment,
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&draft_location(draft_id)));
+            }
+        };
+        (list_id, segment, recipients) =
+            match resolve_recipients(&pool, draft.list_id, saved_segment, draft.recipients_of)
+                .await
+                .map_err(e500)?
+            {
+                Ok(audience) => audience,
+                Err(e) => {
+                    FlashMessage::error(e).send();
+                    return Ok(see_other(&draft_location(draft_id)));
+                }
+            };
+    }
+
+    let mut transaction = match try_processing(&pool, *user_id, &idempotency_key)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message().send();
+            return Ok(saved_response);
+        }
+    };
+    // Only one concurrent publication of a draft can succeed, whatever
+    // their idempotency keys.
+    let n_published = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now(),
+            segment_expression = $2,
+            html_content = $3,
+            slug = $4,
+            list_id = $5,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id,
+        segment.as_ref().map(AsRef::as_ref),
+        html_content,
+        issue_slug(&draft.title, draft_id),
+        list_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to publish the draft")
+    .map_err(e500)?
+    .rows_affected();
+    if n_published == 0 {
+        return Ok(missing_draft());
+    }
+    enqueue_delivery_tasks(
+        &mut transaction,
+        draft_id,
+        list_id,
+        segment.as_ref(),
+        &recipients,
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")
+    .map_err(e500)?;
+    start_issue_subject_test(&mut transaction, draft_id)
+        .await
+        .map_err(e500)?;
+    start_issue_rollout(&mut transaction, draft_id, &rollout_configuration)
+        .await
+        .map_err(e500)?;
+    let response = see_other("/admin/newsletters/drafts");
+    let response = save_response(transaction, *user_id, &idempotency_key, response)
+        .await
+        .map_err(e500)?;
+    success_message().send();
+    send_flash_messages(FlashMessage::warning, &warnings);
+    Ok(response)
+}
@@ -0,0 +1,284 @@
+use super::super::post::{
+    check_rollout, prepare_content, resolve_recipients, resolve_segment, send_flash_messages,
+};
+use super::post::{draft_location, missing_draft};
+use crate::authentication::UserId;
+use crate::routes::admin::layouts::get_layout;
+use crate::subject_tests::SubjectTest;
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleFormData {
+    // As sent by `<input type="datetime-local">`, e.g. 2022-12-06T09:00.
+    scheduled_for: String,
+    // An IANA time zone, e.g. Europe/London; UTC if left blank.
+    #[serde(default)]
+    timezone: String,
+}
+
+/// Resolve a local date and time in `timezone` to the instant it denotes,
+/// which must be after `now`.
+fn parse_schedule(
+    scheduled_for: &str,
+    timezone: &str,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>, String> {
+    let timezone = timezone.trim();
+    let tz: Tz = if timezone.is_empty() {
+        Tz::UTC
+    } else {
+        timezone
+            .parse()
+            .map_err(|_| format!("{} is not a known time zone.", timezone))?
+    };
+    let local = NaiveDateTime::parse_from_str(scheduled_for.trim(), "%Y-%m-%dT%H:%M")
+        .map_err(|_| "Pick the date and time to publish the issue at.".to_string())?;
+    // Ambiguous times, when clocks go back, resolve to the first occurrence.
+    let scheduled_for = tz
+        .from_local_datetime(&local)
+        .earliest()
+        .ok_or_else(|| format!("{} does not exist in {}.", local, tz))?
+        .with_timezone(&Utc);
+    if scheduled_for <= now {
+        return Err("The issue must be scheduled in the future.".into());
+    }
+    Ok(scheduled_for)
+}
+
+/// Schedule a draft, or reschedule an issue that has not been published yet.
+#[tracing::instrument(
+    name = "Schedule an issue",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn schedule_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<ScheduleFormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let issue = match sqlx::query!(
+        r#"
+        SELECT
+            status,
+            list_id,
+            segment_name,
+            recipients_of,
+            layout_id,
+            title,
+            text_content,
+            html_content,
+            test_subjects,
+            test_share,
+            test_wait_hours,
+            test_metric,
+            staged_rollout
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status IN ('draft', 'scheduled')
+        "#,
+        draft_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?
+    {
+        Some(issue) => issue,
+        None => return Ok(missing_draft()),
+    };
+    let is_draft = issue.status == "draft";
+    let error_location = if is_draft {
+        draft_location(draft_id)
+    } else {
+        "/admin/newsletters/drafts".into()
+    };
+    let scheduled_for = match parse_schedule(&form.scheduled_for, &form.timezone, Utc::now()) {
+        Ok(scheduled_for) => scheduled_for,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other(&error_location));
+        }
+    };
+
+    let mut warnings = Vec::new();
+    let n_scheduled = if is_draft {
+        // Drafts are checked now rather than when nobody is around to fix them.
+        // The HTML as it will be sent is kept aside, for the draft to be
+        // edited as authored if it is unscheduled.
+        let layout = get_layout(&pool, issue.layout_id).await.map_err(e500)?;
+        let html_content;
+        (html_content, warnings) = match prepare_content(
+            &issue.title,
+            &issue.text_content,
+            &issue.html_content,
+            layout.as_ref(),
+        ) {
+            Ok(prepared) => prepared,
+            Err(errors) => {
+                send_flash_messages(FlashMessage::error, &errors);
+                return Ok(see_other(&error_location));
+            }
+        };
+        let subject_test = match SubjectTest::from_issue(
+            &issue.title,
+            &issue.test_subjects,
+            issue.test_share,
+            issue.test_wait_hours,
+            issue.test_metric.as_deref(),
+        ) {
+            Ok(subject_test) => subject_test,
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&error_location));
+            }
+        };
+        if let Err(e) = check_rollout(issue.staged_rollout, subject_test.as_ref()) {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&error_location));
+        }
+        let segment_name = issue.segment_name.unwrap_or_default();
+        let segment = match resolve_segment(&pool, &segment_name).await.map_err(e500)? {
+            Ok(segment) => segment,
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&error_location));
+            }
+        };
+        // The scheduler looks up the list of `recipients_of` again.
+        let (_, segment, _) =
+            match resolve_recipients(&pool, issue.list_id, segment, issue.recipients_of)
+                .await
+                .map_err(e500)?
+            {
+                Ok(audience) => audience,
+                Err(e) => {
+                    FlashMessage::error(e).send();
+                    return Ok(see_other(&error_location));
+                }
+            };
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET
+                status = 'scheduled',
+                scheduled_for = $2,
+                segment_expression = $3,
+                prepared_html_content = $4,
+                updated_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'draft'
+            "#,
+            draft_id,
+            scheduled_for,
+            segment.as_ref().map(AsRef::as_ref),
+            html_content,
+        )
+        .execute(pool.get_ref())
+        .await
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET scheduled_for = $2, publish_error = NULL, updated_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+            "#,
+            draft_id,
+            scheduled_for,
+        )
+        .execute(pool.get_ref())
+        .await
+    }
+    .context("Failed to schedule the issue")
+    .map_err(e500)?
+    .rows_affected();
+    // The scheduler may have published the issue in the meantime.
+    if n_scheduled == 0 {
+        return Ok(missing_draft());
+    }
+    FlashMessage::info(format!(
+        "The issue will be published on {}.",
+        scheduled_for.format("%Y-%m-%d %H:%M UTC")
+    ))
+    .send();
+    send_flash_messages(FlashMessage::warning, &warnings);
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+/// Turn a scheduled issue back into a draft, as long as it has not been
+/// published yet.
+#[tracing::instrument(name = "Unschedule an issue", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn unschedule_issue(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let n_unscheduled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'draft',
+            scheduled_for = NULL,
+            segment_expression = NULL,
+            prepared_html_content = NULL,
+            publish_error = NULL,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        draft_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to unschedule the issue")
+    .map_err(e500)?
+    .rows_affected();
+    if n_unscheduled == 0 {
+        return Ok(missing_draft());
+    }
+    FlashMessage::info("The issue is no longer scheduled and is a draft again.").send();
+    Ok(see_other(&draft_location(draft_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_schedule;
+    use chrono::{TimeZone, Utc};
+    use claim::assert_err;
+
+    #[test]
+    fn local_times_are_converted_from_the_time_zone() {
+        let now = Utc.with_ymd_and_hms(2022, 12, 1, 0, 0, 0).unwrap();
+        let scheduled_for = parse_schedule("2022-12-06T09:00", "America/New_York", now).unwrap();
+        assert_eq!(
+            scheduled_for,
+            Utc.with_ymd_and_hms(2022, 12, 6, 14, 0, 0).unwrap()
+        );
+        let scheduled_for = parse_schedule("2022-12-06T09:00", " ", now).unwrap();
+        assert_eq!(
+            scheduled_for,
+            Utc.with_ymd_and_hms(2022, 12, 6, 9, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn past_times_and_unknown_time_zones_are_rejected() {
+        let now = Utc.with_ymd_and_hms(2022, 12, 1, 0, 0, 0).unwrap();
+        assert_err!(parse_schedule("2022-11-30T09:00", "UTC", now));
+        assert_err!(parse_schedule("2022-12-06T09:00", "Mars/Olympus_Mons", now));
+        assert_err!(parse_schedule("", "UTC", now));
+    }
+
+    #[test]
+    fn times_skipped_by_daylight_saving_are_rejected() {
+        let now = Utc.with_ymd_and_hms(2022, 12, 1, 0, 0, 0).unwrap();
+        assert_err!(parse_schedule("2023-03-26T01:30", "Europe/London", now));
+    }
+}
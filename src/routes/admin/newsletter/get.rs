@@ -1,17 +1,41 @@
+use super::preview::{get_user_email, preview_controls_html};
+use crate::authentication::UserId;
+use crate::configuration::RolloutConfiguration;
+use crate::domain::DEFAULT_LIST_SLUG;
+use crate::routes::admin::layouts::layout_options;
+use crate::routes::admin::lists::get_lists;
+use crate::segments::get_saved_segments;
+use crate::subject_tests::{DEFAULT_VARIANT_SHARE, DEFAULT_WAIT_HOURS};
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
 
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    rollout_configuration: web::Data<RolloutConfiguration>,
+    user_id: ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
-    let idempotency_key = uuid::Uuid::new_v4();
+    let (list_options_html, segment_options_html) =
+        audience_options(&pool, None, "").await.map_err(e500)?;
+    let layout_options_html = layout_options(&pool, None).await.map_err(e500)?;
+    let recipients_options_html = recipients_options(&pool, None).await.map_err(e500)?;
+    let rollout_options_html = rollout_options(&rollout_configuration, true);
+    let my_email = get_user_email(&pool, **user_id).await.map_err(e500)?;
+    let preview_controls_html = preview_controls_html(my_email.as_deref());
+
+    let idempotency_key = Uuid::new_v4();
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -23,7 +47,46 @@ pub async fn publish_newsletter_form(
 </head>
 <body>
 {msg_html}
+<p>
+    Title and content may use merge tags:
+    <code>{{{{ name }}}}</code>, <code>{{{{ email }}}}</code>,
+    <code>{{{{ attributes.company | default: "friend" }}}}</code>
+    and <code>{{{{ unsubscribe_url }}}}</code>.
+</p>
 <form action="/admin/newsletters" method="post">
+    <label>List:<br>
+        <select name="list_id">
+            {list_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Segment:<br>
+        <select name="segment">
+            <option value="">Everyone on the list</option>
+            {segment_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Only send to:<br>
+        <select name="recipients_of">
+            <option value="">The list and segment above</option>
+            {recipients_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Delivery:<br>
+        <select name="rollout">
+            {rollout_options_html}
+        </select>
+    </label>
+    <br>
+    <label>Layout:<br>
+        <select name="layout">
+            <option value="">No layout</option>
+            {layout_options_html}
+        </select>
+    </label>
+    <br>
     <label>Title:<br>
         <input
             type="text"
@@ -32,6 +95,34 @@ pub async fn publish_newsletter_form(
         >
     </label>
     <br>
+    <fieldset>
+        <legend>Subject test (optional)</legend>
+        <p>Alternatives to the title, each sent to a share of the audience before the best one goes to the rest.</p>
+        <label>Subject B: <input type="text" name="subject_b"></label><br>
+        <label>Subject C: <input type="text" name="subject_c"></label><br>
+        <label>Subject D: <input type="text" name="subject_d"></label><br>
+        <label>Share of the audience per subject, in %:
+            <input type="number" name="test_share" min="1" max="25" value="{DEFAULT_VARIANT_SHARE}">
+        </label><br>
+        <label>Pick the winner after, in hours:
+            <input type="number" name="test_wait_hours" min="1" max="168" value="{DEFAULT_WAIT_HOURS}">
+        </label><br>
+        <label>Winner:
+            <select name="test_metric">
+                <option value="opens">Highest open rate</option>
+                <option value="clicks">Highest click rate</option>
+            </select>
+        </label>
+    </fieldset>
+    <label>Markdown content (optional, generates both parts below when filled in):<br>
+        <textarea
+            placeholder="Enter the content in Markdown"
+            name="markdown_content"
+            rows="20"
+            cols="50"
+        ></textarea>
+    </label>
+    <br>
     <label>Plain text content:<br>
         <textarea
             placeholder="Enter the content in plain text"
@@ -52,9 +143,112 @@ pub async fn publish_newsletter_form(
     <br>
     <input hidden type="text" name="idempotency_key" value="{idempotency_key}" />
     <button type="submit">Publish</button>
+    <button type="submit" formaction="/admin/newsletters/drafts">Save as draft</button>
+    {preview_controls_html}
 </form>
+<p><a href="/admin/newsletters/drafts">Drafts</a></p>
 <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
         )))
 }
+
+/// The `<option>`s of the delivery picker, selecting a staged send if
+/// `staged`.
+pub fn rollout_options(rollout_configuration: &RolloutConfiguration, staged: bool) -> String {
+    let stages = rollout_configuration
+        .stages
+        .iter()
+        .map(|percentage| format!("{}%", percentage))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let (staged_selected, at_once_selected) = if staged {
+        (" selected", "")
+    } else {
+        ("", " selected")
+    };
+    format!(
+        r#"<option value="staged"{staged_selected}>In stages ({stages} of the audience), pausing if over {}% of a stage fails</option>
+            <option value=""{at_once_selected}>To the whole audience at once</option>"#,
+        rollout_configuration.max_failure_rate,
+    )
+}
+
+/// The `<option>`s to send an issue only to those an earlier one was
+/// delivered to, most recent first, selecting `recipients_of`.
+pub async fn recipients_options(
+    pool: &PgPool,
+    recipients_of: Option<Uuid>,
+) -> Result<String, anyhow::Error> {
+    let issues = sqlx::query!(
+        r#"
+        SELECT i.newsletter_issue_id, i.title, p.delivered AS "delivered!"
+        FROM newsletter_issues i
+        JOIN issue_delivery_progress p USING (newsletter_issue_id)
+        WHERE i.status = 'published' AND p.delivered > 0
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivered issues")?;
+    let mut options_html = String::new();
+    for issue in issues {
+        writeln!(
+            options_html,
+            r#"<option value="{}"{}>Recipients of "{}" ({} delivered)</option>"#,
+            issue.newsletter_issue_id,
+            if recipients_of == Some(issue.newsletter_issue_id) {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&issue.title),
+            issue.delivered,
+        )
+        .unwrap();
+    }
+    Ok(options_html)
+}
+
+/// The `<option>`s of the list and segment pickers, selecting `list_id` (or
+/// the default list) and the segment called `segment`.
+pub async fn audience_options(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+    segment: &str,
+) -> Result<(String, String), anyhow::Error> {
+    let mut list_options_html = String::new();
+    for list in get_lists(pool).await? {
+        let selected = match list_id {
+            Some(list_id) => list.list_id == list_id,
+            None => list.slug == DEFAULT_LIST_SLUG,
+        };
+        writeln!(
+            list_options_html,
+            r#"<option value="{}"{}>{} ({} confirmed subscribers)</option>"#,
+            list.list_id,
+            if selected { " selected" } else { "" },
+            htmlescape::encode_minimal(&list.name),
+            list.confirmed_members,
+        )
+        .unwrap();
+    }
+
+    let mut segment_options_html = String::new();
+    for saved in get_saved_segments(pool).await? {
+        writeln!(
+            segment_options_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            htmlescape::encode_attribute(&saved.name),
+            if saved.name == segment {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&saved.name),
+        )
+        .unwrap();
+    }
+    Ok((list_options_html, segment_options_html))
+}
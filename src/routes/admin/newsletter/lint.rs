@@ -0,0 +1,40 @@
+use super::preview::PreviewFormData;
+use crate::newsletter_content::{lint_issue, LintReport};
+use crate::routes::admin::layouts::resolve_layout;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Lint the content of the newsletter form, wrapped in the chosen layout.
+pub(super) async fn lint_content(
+    pool: &PgPool,
+    content: &PreviewFormData,
+) -> Result<LintReport, anyhow::Error> {
+    let layout = match resolve_layout(pool, &content.layout).await? {
+        Ok(layout) => layout.map(|(_, layout)| layout),
+        Err(_) => {
+            return Ok(LintReport {
+                errors: vec![format!("There is no layout called {}.", content.layout)],
+                warnings: Vec::new(),
+            })
+        }
+    };
+    let (text_content, html_content) = content.parts();
+    Ok(lint_issue(
+        &content.title,
+        &text_content,
+        &html_content,
+        layout.as_ref(),
+    ))
+}
+
+/// Report the errors and warnings publishing an issue would run into, for
+/// editors' tooling. The issue is sent as JSON, with the fields of the
+/// newsletter form.
+pub async fn lint_newsletter(
+    content: web::Json<PreviewFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let report = lint_content(&pool, &content).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -1,5 +1,11 @@
+mod drafts;
 mod get;
+mod lint;
 mod post;
+mod preview;
 
+pub use drafts::*;
 pub use get::publish_newsletter_form;
+pub use lint::lint_newsletter;
 pub use post::publish_newsletter;
+pub use preview::{preview_newsletter, send_test_newsletter};
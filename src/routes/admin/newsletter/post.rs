@@ -1,5 +1,17 @@
 use crate::authentication::UserId;
+use crate::configuration::RolloutConfiguration;
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_delivery_worker::{enqueue_delivery_tasks, Recipients};
+use crate::newsletter_content::{issue_parts, lint_issue, prepare_html, Layout};
+use crate::routes::admin::layouts::resolve_layout;
+use crate::routes::admin::lists::get_list_id_or_default;
+use crate::routes::issue_slug;
+use crate::segments::{get_saved_segment, Segment};
+use crate::staged_rollouts::start_issue_rollout;
+use crate::subject_tests::{
+    start_issue_subject_test, SubjectTest, SubjectTestFields, DEFAULT_VARIANT_SHARE,
+    DEFAULT_WAIT_HOURS,
+};
 use crate::utils::{e400, e500, see_other};
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
@@ -14,27 +26,135 @@ pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
+    // Replaces both parts when the issue is written in Markdown.
+    #[serde(default)]
+    markdown_content: String,
     idempotency_key: String,
+    // Issues go to the default list unless another one is picked.
+    list_id: Option<Uuid>,
+    // The name of a saved segment narrowing down the list, if any.
+    #[serde(default)]
+    segment: String,
+    // The name of the layout to wrap the content in, if any.
+    #[serde(default)]
+    layout: String,
+    // The id of an earlier issue whose recipients, and only them, get this
+    // one, e.g. to correct it. Overrides the list and segment.
+    #[serde(default)]
+    recipients_of: String,
+    // Alternatives to the title tested on a share of the audience, the best
+    // of which is sent to the rest.
+    #[serde(default)]
+    subject_b: String,
+    #[serde(default)]
+    subject_c: String,
+    #[serde(default)]
+    subject_d: String,
+    test_share: Option<u8>,
+    test_wait_hours: Option<u16>,
+    #[serde(default)]
+    test_metric: String,
+    // `staged` to send the issue to a growing share of the audience, pausing
+    // it if too many emails fail.
+    #[serde(default)]
+    rollout: String,
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool, user_id),
+    skip(form, pool, rollout_configuration, user_id),
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     user_id: ReqData<UserId>,
     pool: web::Data<PgPool>,
+    rollout_configuration: web::Data<RolloutConfiguration>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let FormData {
         title,
         text_content,
         html_content,
+        markdown_content,
         idempotency_key,
+        list_id,
+        segment,
+        layout,
+        recipients_of,
+        subject_b,
+        subject_c,
+        subject_d,
+        test_share,
+        test_wait_hours,
+        test_metric,
+        rollout,
     } = form.0;
+    let (text_content, html_content) = issue_parts(&markdown_content, &text_content, &html_content);
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let list_id = match get_list_id_or_default(&pool, list_id)
+        .await
+        .context("Failed to look up the mailing list")
+        .map_err(e500)?
+    {
+        Some(list_id) => list_id,
+        None => {
+            FlashMessage::error("The selected mailing list does not exist.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let segment = match resolve_segment(&pool, &segment).await.map_err(e500)? {
+        Ok(segment) => segment,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let recipients_of = parse_recipients_of(&recipients_of)?;
+    let (list_id, segment, recipients) =
+        match resolve_recipients(&pool, list_id, segment, recipients_of)
+            .await
+            .map_err(e500)?
+        {
+            Ok(audience) => audience,
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other("/admin/newsletters"));
+            }
+        };
+    let subject_test = match SubjectTest::parse(
+        &title,
+        &[&subject_b, &subject_c, &subject_d],
+        test_share.unwrap_or(DEFAULT_VARIANT_SHARE),
+        test_wait_hours.unwrap_or(DEFAULT_WAIT_HOURS),
+        &test_metric,
+    ) {
+        Ok(subject_test) => subject_test,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let staged = parse_rollout(&rollout)?;
+    if let Err(e) = check_rollout(staged, subject_test.as_ref()) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let (layout_id, layout) = match resolve_layout(&pool, &layout).await.map_err(e500)? {
+        Ok(layout) => layout.unzip(),
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let (html_content, warnings) =
+        match prepare_content(&title, &text_content, &html_content, layout.as_ref()) {
+            Ok(prepared) => prepared,
+            Err(errors) => {
+                send_flash_messages(FlashMessage::error, &errors);
+                return Ok(see_other("/admin/newsletters"));
+            }
+        };
     let mut transaction = match try_processing(&pool, *user_id, &idempotency_key)
         .await
         .map_err(e500)?
@@ -46,77 +166,253 @@ pub async fn publish_newsletter(
         }
     };
 
-    let newsletter_issue_id =
-        insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-            .await
-            .context("Failed to store newsletter issue details")
-            .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, newsletter_issue_id)
+    let newsletter_issue_id = insert_newsletter_issue(
+        &mut transaction,
+        list_id,
+        segment.as_ref(),
+        recipients_of,
+        layout_id,
+        &IssueContent {
+            title: &title,
+            text_content: &text_content,
+            html_content: &html_content,
+            markdown_content: &markdown_content,
+        },
+        &IssueSending {
+            subject_test: SubjectTestFields {
+                alternatives: [&subject_b, &subject_c, &subject_d],
+                variant_share: test_share,
+                wait_hours: test_wait_hours,
+                metric: &test_metric,
+            },
+            staged_rollout: staged,
+        },
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(
+        &mut transaction,
+        newsletter_issue_id,
+        list_id,
+        segment.as_ref(),
+        &recipients,
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")
+    .map_err(e500)?;
+    start_issue_subject_test(&mut transaction, newsletter_issue_id)
         .await
-        .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
+    start_issue_rollout(
+        &mut transaction,
+        newsletter_issue_id,
+        &rollout_configuration,
+    )
+    .await
+    .map_err(e500)?;
     let response = see_other("/admin/newsletters");
     let response = save_response(transaction, *user_id, &idempotency_key, response)
         .await
         .map_err(e500)?;
     success_message().send();
+    send_flash_messages(FlashMessage::warning, &warnings);
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
+/// Lint an issue wrapped in `layout`, if any, returning the HTML part as it
+/// will be sent along with the warnings, or the errors preventing it from
+/// being published.
+pub fn prepare_content(
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    layout: Option<&Layout>,
+) -> Result<(String, Vec<String>), Vec<String>> {
+    let report = lint_issue(title, text_content, html_content, layout);
+    if !report.errors.is_empty() {
+        return Err(report.errors);
+    }
+    let html_content = prepare_html(html_content).map_err(|e| vec![e.to_string()])?;
+    Ok((html_content, report.warnings))
+}
+
+/// Whether the `rollout` picked in the form sends the issue in stages.
+pub fn parse_rollout(rollout: &str) -> Result<bool, actix_web::Error> {
+    match rollout {
+        "" => Ok(false),
+        "staged" => Ok(true),
+        _ => Err(e400(anyhow::anyhow!("Unknown rollout: {}", rollout))),
+    }
+}
+
+/// The error is a message for the admin.
+pub fn check_rollout(staged: bool, subject_test: Option<&SubjectTest>) -> Result<(), &'static str> {
+    if staged && subject_test.is_some() {
+        return Err(
+            "A subject test already sends the issue to a share of the audience first: \
+            it cannot be sent in stages too.",
+        );
+    }
+    Ok(())
+}
+
+pub fn send_flash_messages(flash_message: fn(String) -> FlashMessage, messages: &[String]) {
+    for message in messages {
+        flash_message(htmlescape::encode_minimal(message)).send();
+    }
+}
+
+/// Look up the saved segment called `name`, if any. The inner error is an
+/// HTML message for the admin.
+pub async fn resolve_segment(
+    pool: &PgPool,
+    name: &str,
+) -> Result<Result<Option<Segment>, String>, anyhow::Error> {
+    if name.is_empty() {
+        return Ok(Ok(None));
+    }
+    match get_saved_segment(pool, name)
+        .await
+        .context("Failed to look up the segment")?
+    {
+        Some(saved) => Ok(Ok(Some(
+            Segment::parse(&saved.expression).context("A saved segment is invalid")?,
+        ))),
+        None => Ok(Err(format!(
+            "There is no segment called {}.",
+            htmlescape::encode_minimal(name)
+        ))),
+    }
+}
+
+/// The id of the earlier issue picked in the "Only send to" field, if any.
+pub fn parse_recipients_of(issue_id: &str) -> Result<Option<Uuid>, actix_web::Error> {
+    Some(issue_id)
+        .filter(|s| !s.is_empty())
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(e400)
+}
+
+/// The list, segment and recipients of an issue sent to `list_id` and
+/// `segment`, or only to those `recipients_of` was delivered to if given.
+/// The inner error is a message for the admin.
+pub async fn resolve_recipients(
+    pool: &PgPool,
+    list_id: Uuid,
+    segment: Option<Segment>,
+    recipients_of: Option<Uuid>,
+) -> Result<Result<(Uuid, Option<Segment>, Recipients), String>, anyhow::Error> {
+    let issue_id = match recipients_of {
+        Some(issue_id) => issue_id,
+        None => return Ok(Ok((list_id, segment, Recipients::Everyone))),
+    };
+    Ok(resolve_recipients_of(pool, issue_id)
+        .await?
+        .map(|list_id| (list_id, None, Recipients::RecipientsOf(issue_id))))
+}
+
+/// Look up the list of the earlier issue whose recipients get the new one.
+/// The inner error is a message for the admin when it was delivered to
+/// nobody.
+pub async fn resolve_recipients_of(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Result<Uuid, String>, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT i.list_id
+        FROM newsletter_issues i
+        JOIN issue_delivery_progress p USING (newsletter_issue_id)
+        WHERE i.newsletter_issue_id = $1 AND i.status = 'published' AND p.delivered > 0
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the issue to follow up on")?;
+    Ok(match issue {
+        Some(issue) => Ok(issue.list_id),
+        None => Err("The selected issue has not been delivered to anyone.".to_string()),
+    })
+}
+
+pub fn success_message() -> FlashMessage {
     FlashMessage::info(
         "The newsletter issue has been accepted - \
         emails will go out shortly.",
     )
 }
 
+struct IssueContent<'a> {
+    title: &'a str,
+    text_content: &'a str,
+    html_content: &'a str,
+    markdown_content: &'a str,
+}
+
+// How the issue is sent once its deliveries are queued.
+struct IssueSending<'a> {
+    subject_test: SubjectTestFields<'a>,
+    staged_rollout: bool,
+}
+
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+    recipients_of: Option<Uuid>,
+    layout_id: Option<Uuid>,
+    content: &IssueContent<'_>,
+    sending: &IssueSending<'_>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
             newsletter_issue_id,
+            list_id,
+            segment_expression,
+            recipients_of,
+            layout_id,
             title,
             text_content,
             html_content,
-            published_at
+            markdown_content,
+            test_subjects,
+            test_share,
+            test_wait_hours,
+            test_metric,
+            staged_rollout,
+            slug,
+            status,
+            published_at,
+            updated_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15,
+            'published', now(), now()
         )
-        VALUES ($1, $2, $3, $4, now())
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        list_id,
+        segment.map(AsRef::as_ref),
+        recipients_of,
+        layout_id,
+        content.title,
+        content.text_content,
+        content.html_content,
+        Some(content.markdown_content).filter(|s| !s.trim().is_empty()),
+        &sending.subject_test.test_subjects(),
+        sending.subject_test.test_share(),
+        sending.subject_test.test_wait_hours(),
+        sending.subject_test.test_metric(),
+        sending.staged_rollout,
+        issue_slug(content.title, newsletter_issue_id),
     )
     .execute(transaction)
     .await?;
     Ok(newsletter_issue_id)
 }
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
-}
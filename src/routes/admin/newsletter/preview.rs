@@ -0,0 +1,345 @@
+use super::lint::lint_content;
+use crate::authentication::UserId;
+use crate::domain::{SubscriberAttributes, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::newsletter_content::{issue_parts, prepare_html, Layout, MergeData, Template};
+use crate::routes::admin::layouts::resolve_layout;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const MAX_TEST_RECIPIENTS: usize = 10;
+
+#[derive(serde::Deserialize)]
+pub struct PreviewFormData {
+    #[serde(default)]
+    pub(super) title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    markdown_content: String,
+    // The name of the layout to wrap the content in, if any.
+    #[serde(default)]
+    pub(super) layout: String,
+}
+
+impl PreviewFormData {
+    pub(super) fn parts(&self) -> (String, String) {
+        issue_parts(
+            &self.markdown_content,
+            &self.text_content,
+            &self.html_content,
+        )
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    #[serde(flatten)]
+    content: PreviewFormData,
+    send_to: SendTo,
+    #[serde(default)]
+    my_email: String,
+    // Separated by commas, semicolons or whitespace.
+    #[serde(default)]
+    test_recipients: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SendTo {
+    Me,
+    Addresses,
+}
+
+struct RenderedIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// The controls previewing or test sending the content of the enclosing
+/// newsletter form, opening the result in a new tab so the form is kept.
+pub fn preview_controls_html(my_email: Option<&str>) -> String {
+    let my_email = htmlescape::encode_attribute(my_email.unwrap_or_default());
+    format!(
+        r#"<fieldset>
+        <legend>Try it out</legend>
+        <button type="submit" formaction="/admin/newsletters/preview" formtarget="_blank">Preview</button>
+        <br>
+        <label>Your email address:
+            <input type="email" name="my_email" value="{my_email}">
+        </label>
+        <button
+            type="submit"
+            name="send_to"
+            value="me"
+            formaction="/admin/newsletters/test"
+            formtarget="_blank"
+        >Send test to me</button>
+        <br>
+        <label>Other addresses:
+            <input type="text" name="test_recipients" placeholder="Separated by commas">
+        </label>
+        <button
+            type="submit"
+            name="send_to"
+            value="addresses"
+            formaction="/admin/newsletters/test"
+            formtarget="_blank"
+        >Send test to these addresses</button>
+    </fieldset>"#
+    )
+}
+
+/// The address the admin sends tests to themselves at, if they ever did.
+pub async fn get_user_email(pool: &PgPool, user_id: Uuid) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE id = $1"#, user_id)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the email of the user")?;
+    Ok(row.and_then(|r| r.email))
+}
+
+/// Render the content of the newsletter form as a made-up subscriber would
+/// receive it, in the chosen layout. The inner error is an HTML message for
+/// the admin.
+async fn render_with_sample_data(
+    pool: &PgPool,
+    content: &PreviewFormData,
+    base_url: &str,
+) -> Result<Result<RenderedIssue, String>, anyhow::Error> {
+    Ok(match resolve_layout(pool, &content.layout).await? {
+        Ok(layout) => render_sample(content, layout.map(|(_, layout)| layout), base_url),
+        Err(e) => Err(e),
+    })
+}
+
+fn render_sample(
+    content: &PreviewFormData,
+    layout: Option<Layout>,
+    base_url: &str,
+) -> Result<RenderedIssue, String> {
+    let attributes = SubscriberAttributes::parse(serde_json::json!({
+        "company": "Analytical Engines Ltd",
+    }))
+    .expect("The sample attributes are valid");
+    // Unsubscribing needs a real subscriber, so the sample link is inert.
+    let unsubscribe_url = format!("{}/subscriptions/unsubscribe", base_url);
+    let merge_data = MergeData {
+        name: "Ada Lovelace",
+        email: "ada.lovelace@example.com",
+        attributes: &attributes,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let (text_content, html_content) = content.parts();
+    let html_content = prepare_html(&html_content).map_err(|e| {
+        htmlescape::encode_minimal(&format!("The HTML content could not be rendered: {}", e))
+    })?;
+    let (text_content, html_content) = match layout {
+        Some(layout) => (
+            layout.wrap_text(&text_content),
+            layout.wrap_html(&html_content),
+        ),
+        None => (text_content, html_content),
+    };
+    let render = |field: &str, content: &str, html: bool| -> Result<String, String> {
+        let template = Template::parse(content).map_err(|e| {
+            htmlescape::encode_minimal(&format!("The {} could not be rendered: {}", field, e))
+        })?;
+        Ok(if html {
+            template.render_html(&merge_data)
+        } else {
+            template.render_text(&merge_data)
+        })
+    };
+    Ok(RenderedIssue {
+        title: render("title", &content.title, false)?,
+        text_content: render("plain text content", &text_content, false)?,
+        html_content: render("HTML content", &html_content, true)?,
+    })
+}
+
+fn page(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>{title}</title>
+</head>
+<body>
+{body}
+</body>
+</html>"#,
+        ))
+}
+
+/// Show the issue being written, rendered for a sample subscriber: the HTML
+/// part in a sandboxed frame, which cannot run scripts or reach the admin
+/// session, next to the plain text part.
+pub async fn preview_newsletter(
+    form: web::Form<PreviewFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let rendered = match render_with_sample_data(&pool, &form, &base_url.0)
+        .await
+        .map_err(e500)?
+    {
+        Ok(rendered) => rendered,
+        Err(e) => return Ok(page("Preview", &format!("<p><i>{}</i></p>", e))),
+    };
+    let report = lint_content(&pool, &form).await.map_err(e500)?;
+    let mut checks_html = String::new();
+    for (level, messages) in [("Error", &report.errors), ("Warning", &report.warnings)] {
+        for message in messages {
+            writeln!(
+                checks_html,
+                "<li>{}: {}</li>",
+                level,
+                htmlescape::encode_minimal(message)
+            )
+            .unwrap();
+        }
+    }
+    if checks_html.is_empty() {
+        checks_html.push_str("<li>No problems found.</li>");
+    }
+    Ok(page(
+        "Preview",
+        &format!(
+            r#"<p>Rendered for Ada Lovelace &lt;ada.lovelace@example.com&gt;.</p>
+<ul>
+{checks_html}
+</ul>
+<h1>{title}</h1>
+<div style="display: flex; gap: 1em;">
+    <iframe
+        sandbox
+        title="HTML version"
+        srcdoc="{html_content}"
+        style="flex: 1; height: 80vh;"
+    ></iframe>
+    <pre style="flex: 1; white-space: pre-wrap;">{text_content}</pre>
+</div>"#,
+            title = htmlescape::encode_minimal(&rendered.title),
+            html_content = htmlescape::encode_attribute(&rendered.html_content),
+            text_content = htmlescape::encode_minimal(&rendered.text_content),
+        ),
+    ))
+}
+
+/// Email the issue being written, rendered for a sample subscriber, to the
+/// admin or a few addresses of their choosing. Nothing is stored or queued.
+#[tracing::instrument(
+    name = "Send a test newsletter issue",
+    skip(form, pool, email_client, base_url, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn send_test_newsletter(
+    form: web::Form<TestEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let rendered = match render_with_sample_data(&pool, &form.content, &base_url.0)
+        .await
+        .map_err(e500)?
+    {
+        Ok(rendered) => rendered,
+        Err(e) => return Ok(page("Test email", &format!("<p><i>{}</i></p>", e))),
+    };
+    let addresses: Vec<&str> = match form.send_to {
+        SendTo::Me => vec![form.my_email.trim()],
+        SendTo::Addresses => form
+            .test_recipients
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .collect(),
+    };
+    let addresses: Vec<&str> = addresses.into_iter().filter(|a| !a.is_empty()).collect();
+    if addresses.is_empty() {
+        return Ok(page(
+            "Test email",
+            "<p><i>Enter the address to send the test to.</i></p>",
+        ));
+    }
+    if addresses.len() > MAX_TEST_RECIPIENTS {
+        return Ok(page(
+            "Test email",
+            &format!(
+                "<p><i>A test can be sent to at most {} addresses.</i></p>",
+                MAX_TEST_RECIPIENTS
+            ),
+        ));
+    }
+    let mut recipients = Vec::new();
+    let mut msg_html = String::new();
+    for address in addresses {
+        match SubscriberEmail::parse(address.to_string()) {
+            Ok(email) => recipients.push(email),
+            Err(e) => {
+                writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(&e)).unwrap()
+            }
+        }
+    }
+    if !msg_html.is_empty() {
+        return Ok(page("Test email", &msg_html));
+    }
+
+    if let SendTo::Me = form.send_to {
+        sqlx::query!(
+            r#"UPDATE users SET email = $2 WHERE id = $1"#,
+            *user_id,
+            recipients[0].as_ref(),
+        )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to save the email of the user")
+        .map_err(e500)?;
+    }
+    for recipient in &recipients {
+        let address = htmlescape::encode_minimal(recipient.as_ref());
+        match email_client
+            .send_email(
+                recipient,
+                &rendered.title,
+                &rendered.html_content,
+                &rendered.text_content,
+            )
+            .await
+        {
+            Ok(()) => writeln!(
+                msg_html,
+                "<p><i>A test has been sent to {}.</i></p>",
+                address
+            ),
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a test newsletter issue",
+                );
+                writeln!(
+                    msg_html,
+                    "<p><i>The test could not be sent to {}.</i></p>",
+                    address
+                )
+            }
+        }
+        .unwrap();
+    }
+    Ok(page("Test email", &msg_html))
+}
@@ -0,0 +1,119 @@
+use crate::segments::{count_subscribers, get_saved_segments, Segment};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    // A segment expression to preview before saving it.
+    expression: Option<String>,
+}
+
+pub async fn segments_form(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let expression = query.0.expression.unwrap_or_default();
+    let mut preview_html = String::new();
+    if !expression.trim().is_empty() {
+        match Segment::parse(&expression) {
+            Ok(segment) => {
+                let count = count_subscribers(&pool, &segment).await.map_err(e500)?;
+                writeln!(
+                    preview_html,
+                    "<p>{} subscribers match <code>{}</code>.</p>",
+                    count,
+                    htmlescape::encode_minimal(segment.as_ref())
+                )
+                .unwrap();
+            }
+            Err(e) => writeln!(
+                preview_html,
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(&e.to_string())
+            )
+            .unwrap(),
+        }
+    }
+
+    let mut segments_html = String::new();
+    for saved in get_saved_segments(&pool).await.map_err(e500)? {
+        // Saved segments are validated on save, but may reference a predicate
+        // that has since changed.
+        let count = match Segment::parse(&saved.expression) {
+            Ok(segment) => count_subscribers(&pool, &segment)
+                .await
+                .map_err(e500)?
+                .to_string(),
+            Err(_) => "?".to_string(),
+        };
+        writeln!(
+            segments_html,
+            "<li><strong>{}</strong>: <code>{}</code> - {} subscribers</li>",
+            htmlescape::encode_minimal(&saved.name),
+            htmlescape::encode_minimal(&saved.expression),
+            count,
+        )
+        .unwrap();
+    }
+    let expression = htmlescape::encode_minimal(&expression);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Segments</title>
+</head>
+<body>
+    {msg_html}
+    <ul>
+    {segments_html}
+    </ul>
+    <p>
+        Combine predicates with <code>and</code>, <code>or</code>, <code>not</code> and parentheses:
+        <code>status:confirmed</code>, <code>tag:beta</code>, <code>list:newsletter</code>,
+        <code>subscribed_within:90d</code>, <code>subscribed_since:2022-01-31</code>,
+        <code>subscribed_before:2022-01-31</code>, <code>bounced</code>,
+        <code>attributes.plan:"pro plus"</code>.
+    </p>
+    <form action="/admin/segments" method="get">
+        <label>Expression<br>
+            <textarea
+                placeholder="e.g. status:confirmed and tag:beta and not bounced"
+                name="expression"
+                rows="3"
+                cols="50"
+            >{expression}</textarea>
+        </label>
+        <br>
+        <button type="submit">Preview</button>
+    </form>
+    {preview_html}
+    <form action="/admin/segments" method="post">
+        <input hidden type="text" name="expression" value="{expression}">
+        <label>Name
+            <input
+                type="text"
+                placeholder="e.g. Recent beta testers"
+                name="name"
+            >
+        </label>
+        <button type="submit">Save segment</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,56 @@
+use crate::authentication::UserId;
+use crate::segments::{self, Segment};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    expression: String,
+}
+
+#[tracing::instrument(
+    name = "Save a segment",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, name=%form.name)
+)]
+pub async fn save_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { name, expression } = form.0;
+    let location = format!(
+        "/admin/segments?expression={}",
+        urlencoding::encode(&expression)
+    );
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The segment name cannot be empty.").send();
+        return Ok(see_other(&location));
+    }
+    let segment = match Segment::parse(&expression) {
+        Ok(segment) => segment,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&format!(
+                "The segment could not be saved: {}",
+                e
+            )))
+            .send();
+            return Ok(see_other(&location));
+        }
+    };
+    segments::save_segment(&pool, name, &segment)
+        .await
+        .context("Failed to save the segment.")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "The {} segment has been saved.",
+        htmlescape::encode_minimal(name)
+    ))
+    .send();
+    Ok(see_other("/admin/segments"))
+}
@@ -0,0 +1,127 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    email: Option<String>,
+}
+
+pub async fn subscriber_attributes_form(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let email = query.0.email.unwrap_or_default();
+    let attributes = if email.is_empty() {
+        None
+    } else {
+        get_subscriber_attributes(&pool, &email)
+            .await
+            .map_err(e500)?
+    };
+    let tags = if attributes.is_some() {
+        get_subscriber_tags(&pool, &email).await.map_err(e500)?
+    } else {
+        vec![]
+    };
+    let attributes = attributes
+        .map(|a| serde_json::to_string_pretty(&a).unwrap())
+        .unwrap_or_else(|| "{}".into());
+    let email = htmlescape::encode_attribute(&email);
+    let attributes = htmlescape::encode_minimal(&attributes);
+    let tags = htmlescape::encode_attribute(&tags.join(", "));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber attributes</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers/attributes" method="get">
+        <label>Subscriber email
+            <input
+                type="text"
+                placeholder="Enter the subscriber's email"
+                name="email"
+                value="{email}"
+            >
+        </label>
+        <button type="submit">Load</button>
+    </form>
+    <form action="/admin/subscribers/attributes" method="post">
+        <input hidden type="text" name="email" value="{email}">
+        <label>Attributes (a JSON object, available as <code>{{{{ attributes.&lt;name&gt; }}}}</code>):<br>
+            <textarea
+                name="attributes"
+                rows="20"
+                cols="50"
+            >{attributes}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save attributes</button>
+    </form>
+    <form action="/admin/subscribers/tags" method="post">
+        <input hidden type="text" name="email" value="{email}">
+        <label>Tags (comma-separated, used by segments):<br>
+            <input
+                type="text"
+                placeholder="e.g. beta, vip"
+                name="tags"
+                value="{tags}"
+            >
+        </label>
+        <button type="submit">Save tags</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get subscriber attributes", skip(pool))]
+async fn get_subscriber_attributes(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<serde_json::Value>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT attributes FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.trim(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscriber attributes.")?;
+    Ok(row.map(|r| r.attributes))
+}
+
+#[tracing::instrument(name = "Get subscriber tags", skip(pool))]
+async fn get_subscriber_tags(pool: &PgPool, email: &str) -> Result<Vec<String>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT t.tag
+        FROM subscriber_tags t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        ORDER BY t.tag
+        "#,
+        email.trim(),
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscriber tags.")?;
+    Ok(rows.into_iter().map(|r| r.tag).collect())
+}
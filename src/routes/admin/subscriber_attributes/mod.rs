@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::subscriber_attributes_form;
+pub use post::{update_subscriber_attributes, update_subscriber_tags};
@@ -0,0 +1,137 @@
+use crate::authentication::UserId;
+use crate::domain::{SubscriberAttributes, SubscriberTag};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    attributes: String,
+}
+
+#[tracing::instrument(
+    name = "Update subscriber attributes",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, subscriber_email=%form.email)
+)]
+pub async fn update_subscriber_attributes(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { email, attributes } = form.0;
+    let location = format!(
+        "/admin/subscribers/attributes?email={}",
+        urlencoding::encode(&email)
+    );
+    let attributes = serde_json::from_str(&attributes)
+        .map_err(|e| format!("The attributes are not valid JSON: {}", e))
+        .and_then(SubscriberAttributes::parse);
+    let attributes = match attributes {
+        Ok(attributes) => attributes,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let n_updated_rows = sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = $1 WHERE lower(email) = lower($2)"#,
+        serde_json::Value::from(attributes),
+        email.trim(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update subscriber attributes.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        FlashMessage::error("There is no subscriber with this email.").send();
+    } else {
+        FlashMessage::info("The subscriber's attributes have been saved.").send();
+    }
+    Ok(see_other(&location))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagsFormData {
+    email: String,
+    tags: String,
+}
+
+#[tracing::instrument(
+    name = "Update subscriber tags",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, subscriber_email=%form.email)
+)]
+pub async fn update_subscriber_tags(
+    form: web::Form<TagsFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let TagsFormData { email, tags } = form.0;
+    let location = format!(
+        "/admin/subscribers/attributes?email={}",
+        urlencoding::encode(&email)
+    );
+    let tags = match SubscriberTag::parse_list(&tags) {
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_string()).collect();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let subscriber = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.trim(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the subscriber.")
+    .map_err(e500)?;
+    let subscriber_id = match subscriber {
+        Some(r) => r.id,
+        None => {
+            FlashMessage::error("There is no subscriber with this email.").send();
+            return Ok(see_other(&location));
+        }
+    };
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND NOT (tag = ANY($2))"#,
+        subscriber_id,
+        &tags[..],
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove subscriber tags.")
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, added_at)
+        SELECT $1, tag, now() FROM unnest($2::text[]) AS tag
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags[..],
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store subscriber tags.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store subscriber tags.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber's tags have been saved.").send();
+    Ok(see_other(&location))
+}
@@ -0,0 +1,315 @@
+use crate::domain::{ListSlug, SubscriberTag};
+use crate::utils::e400;
+use actix_web::http::header::CONTENT_DISPOSITION;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+// Rows are sent to the client in chunks of roughly this size.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+// Empty strings come from the filters left blank in the export form.
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    format: ExportFormat,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    list: String,
+    #[serde(default)]
+    tag: String,
+    #[serde(default)]
+    subscribed_since: String,
+    #[serde(default)]
+    subscribed_before: String,
+}
+
+#[derive(Debug)]
+struct ExportFilter {
+    // The membership status when a list is given, the status of the
+    // subscriber's address otherwise.
+    status: Option<String>,
+    list: Option<String>,
+    tag: Option<String>,
+    subscribed_since: Option<NaiveDate>,
+    subscribed_before: Option<NaiveDate>,
+}
+
+impl TryFrom<&QueryParams> for ExportFilter {
+    type Error = String;
+
+    fn try_from(query: &QueryParams) -> Result<Self, Self::Error> {
+        let status = non_empty(&query.status).map(str::to_string);
+        let list = non_empty(&query.list)
+            .map(|l| ListSlug::parse(l.to_string()).map(|l| l.as_ref().to_string()))
+            .transpose()?;
+        match status.as_deref() {
+            None | Some("confirmed" | "pending_confirmation") => {}
+            Some("unsubscribed") if list.is_some() => {}
+            Some("unsubscribed") => {
+                return Err("Pick a list to export the subscribers who left it.".into())
+            }
+            Some(s) => return Err(format!("{} is not a valid subscriber status.", s)),
+        }
+        let tag = non_empty(&query.tag)
+            .map(|t| SubscriberTag::parse(t).map(|t| t.as_ref().to_string()))
+            .transpose()?;
+        let date = |s: &str| {
+            non_empty(s)
+                .map(|d| {
+                    NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                        .map_err(|_| format!("{} is not a valid date (yyyy-mm-dd).", d))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            status,
+            list,
+            tag,
+            subscribed_since: date(&query.subscribed_since)?,
+            subscribed_before: date(&query.subscribed_before)?,
+        })
+    }
+}
+
+fn non_empty(s: &str) -> Option<&str> {
+    Some(s.trim()).filter(|s| !s.is_empty())
+}
+
+struct ExportedSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    attributes: serde_json::Value,
+    lists: Vec<String>,
+    tags: Vec<String>,
+}
+
+/// Stream the subscribers matching the filters, without ever holding the
+/// whole result set in memory.
+#[tracing::instrument(name = "Export subscribers", skip(query, pool))]
+pub async fn export_subscribers(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = ExportFilter::try_from(&query.0).map_err(e400)?;
+    let format = query.0.format;
+    let (content_type, file_name) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "subscribers.csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "subscribers.ndjson"),
+    };
+
+    // A small buffer makes the query wait for slow clients.
+    let (sender, receiver) = mpsc::channel(4);
+    actix_web::rt::spawn(stream_subscribers(
+        pool.get_ref().clone(),
+        filter,
+        format,
+        sender,
+    ));
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!(r#"attachment; filename="{}""#, file_name),
+        ))
+        .streaming(body))
+}
+
+type Chunk = Result<Bytes, anyhow::Error>;
+
+async fn stream_subscribers(
+    pool: PgPool,
+    filter: ExportFilter,
+    format: ExportFormat,
+    sender: mpsc::Sender<Chunk>,
+) {
+    if let Err(e) = try_stream_subscribers(&pool, &filter, format, &sender).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to export subscribers",
+        );
+        // Aborts the response, so the client does not mistake a partial
+        // export for a complete one.
+        let _ = sender.send(Err(e)).await;
+    }
+}
+
+async fn try_stream_subscribers(
+    pool: &PgPool,
+    filter: &ExportFilter,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Chunk>,
+) -> Result<(), anyhow::Error> {
+    let mut rows = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            s.attributes,
+            ARRAY(
+                SELECT l.slug
+                FROM list_memberships m
+                JOIN lists l ON l.list_id = m.list_id
+                WHERE m.subscriber_id = s.id AND m.status = 'confirmed'
+                ORDER BY l.slug
+            ) AS "lists!",
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS "tags!"
+        FROM subscriptions s
+        WHERE ($1::text IS NULL OR $2::text IS NOT NULL OR s.status = $1)
+        AND ($2::text IS NULL OR EXISTS (
+            SELECT 1
+            FROM list_memberships m
+            JOIN lists l ON l.list_id = m.list_id
+            WHERE m.subscriber_id = s.id
+            AND l.slug = $2
+            AND ($1::text IS NULL OR m.status = $1)
+        ))
+        AND ($3::text IS NULL OR EXISTS (
+            SELECT 1 FROM subscriber_tags t
+            WHERE t.subscriber_id = s.id AND t.tag = $3
+        ))
+        AND ($4::date IS NULL OR s.subscribed_at >= $4)
+        AND ($5::date IS NULL OR s.subscribed_at < $5)
+        ORDER BY s.subscribed_at, s.id
+        "#,
+        filter.status,
+        filter.list,
+        filter.tag,
+        filter.subscribed_since,
+        filter.subscribed_before,
+    )
+    .fetch(pool);
+
+    let mut buffer = Vec::new();
+    if let ExportFormat::Csv = format {
+        write_csv_record(
+            &mut buffer,
+            [
+                "email",
+                "name",
+                "status",
+                "subscribed_at",
+                "lists",
+                "tags",
+                "attributes",
+            ],
+        )?;
+    }
+    while let Some(row) = rows.try_next().await? {
+        let subscribed_at = row.subscribed_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        match format {
+            ExportFormat::Csv => write_csv_record(
+                &mut buffer,
+                [
+                    row.email.as_str(),
+                    row.name.as_str(),
+                    row.status.as_str(),
+                    subscribed_at.as_str(),
+                    row.lists.join(";").as_str(),
+                    row.tags.join(";").as_str(),
+                    row.attributes.to_string().as_str(),
+                ],
+            )?,
+            ExportFormat::Ndjson => {
+                serde_json::to_writer(
+                    &mut buffer,
+                    &serde_json::json!({
+                        "email": row.email,
+                        "name": row.name,
+                        "status": row.status,
+                        "subscribed_at": subscribed_at,
+                        "lists": row.lists,
+                        "tags": row.tags,
+                        "attributes": row.attributes,
+                    }),
+                )?;
+                buffer.push(b'\n');
+            }
+        }
+        if buffer.len() >= CHUNK_SIZE {
+            let chunk = Bytes::from(std::mem::take(&mut buffer));
+            if sender.send(Ok(chunk)).await.is_err() {
+                // The client went away.
+                return Ok(());
+            }
+        }
+    }
+    if !buffer.is_empty() {
+        let _ = sender.send(Ok(Bytes::from(buffer))).await;
+    }
+    Ok(())
+}
+
+fn write_csv_record<'a>(
+    buffer: &mut Vec<u8>,
+    record: impl IntoIterator<Item = &'a str>,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(buffer);
+    writer.write_record(record)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportFilter, ExportFormat, QueryParams};
+    use claim::{assert_err, assert_ok};
+
+    fn query(status: &str, list: &str, since: &str) -> QueryParams {
+        QueryParams {
+            format: ExportFormat::Csv,
+            status: status.into(),
+            list: list.into(),
+            tag: "".into(),
+            subscribed_since: since.into(),
+            subscribed_before: "".into(),
+        }
+    }
+
+    #[test]
+    fn blank_filters_are_ignored() {
+        let filter = ExportFilter::try_from(&query(" ", "", "")).unwrap();
+        assert!(filter.status.is_none());
+        assert!(filter.list.is_none());
+        assert!(filter.subscribed_since.is_none());
+    }
+
+    #[test]
+    fn unsubscribed_requires_a_list() {
+        assert_err!(ExportFilter::try_from(&query("unsubscribed", "", "")));
+        assert_ok!(ExportFilter::try_from(&query(
+            "unsubscribed",
+            "newsletter",
+            ""
+        )));
+    }
+
+    #[test]
+    fn unknown_statuses_and_invalid_dates_are_rejected() {
+        assert_err!(ExportFilter::try_from(&query("deleted", "", "")));
+        assert_err!(ExportFilter::try_from(&query("", "", "31/01/2022")));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::routes::admin::lists::get_lists;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn export_subscribers_form(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut list_options_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            list_options_html,
+            r#"<option value="{}">{}</option>"#,
+            htmlescape::encode_attribute(&list.slug),
+            htmlescape::encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Export subscribers</title>
+</head>
+<body>
+    <form action="/admin/subscribers/export/download" method="get">
+        <label>Format
+            <select name="format">
+                <option value="csv">CSV</option>
+                <option value="ndjson">Newline-delimited JSON</option>
+            </select>
+        </label>
+        <br>
+        <label>Status
+            <select name="status">
+                <option value="">Any</option>
+                <option value="confirmed">Confirmed</option>
+                <option value="pending_confirmation">Pending confirmation</option>
+                <option value="unsubscribed">Unsubscribed (from the selected list)</option>
+            </select>
+        </label>
+        <br>
+        <label>List
+            <select name="list">
+                <option value="">Any</option>
+                {list_options_html}
+            </select>
+        </label>
+        <br>
+        <label>Tag
+            <input type="text" placeholder="e.g. beta" name="tag">
+        </label>
+        <br>
+        <label>Subscribed since
+            <input type="date" name="subscribed_since">
+        </label>
+        <label>and before
+            <input type="date" name="subscribed_before">
+        </label>
+        <br>
+        <button type="submit">Export</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod download;
+mod get;
+
+pub use download::export_subscribers;
+pub use get::export_subscribers_form;
@@ -0,0 +1,111 @@
+use super::post::{FormData, ImportMode};
+use crate::routes::admin::lists::{get_lists, MailingList};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn import_subscribers_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    Ok(import_page(&msg_html, &lists, &FormData::default(), ""))
+}
+
+/// Render the import form, keeping what the admin submitted so a dry run
+/// can be followed by the actual import.
+pub(super) fn import_page(
+    msg_html: &str,
+    lists: &[MailingList],
+    form: &FormData,
+    report_html: &str,
+) -> HttpResponse {
+    let mut list_options_html = String::new();
+    for list in lists {
+        let selected = if Some(list.list_id) == form.list_id {
+            " selected"
+        } else {
+            ""
+        };
+        writeln!(
+            list_options_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            list.list_id,
+            selected,
+            htmlescape::encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    let (pending_checked, confirmed_checked) = match form.mode {
+        ImportMode::Pending => (" checked", ""),
+        ImportMode::Confirmed => ("", " checked"),
+    };
+    let csv = htmlescape::encode_minimal(&form.csv);
+    let consent_source = htmlescape::encode_attribute(&form.consent_source);
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <p>
+        Upload or paste a CSV with an <code>email</code> column and, optionally,
+        <code>name</code> or <code>first name</code>/<code>last name</code> columns.
+        Mailchimp and Substack exports are recognised as they are.
+    </p>
+    <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+        <label>CSV file
+            <input type="file" name="csv_file" accept=".csv,text/csv">
+        </label>
+        <br>
+        <label>Or CSV text, kept here after a dry run:<br>
+            <textarea id="csv" name="csv" rows="15" cols="80">{csv}</textarea>
+        </label>
+        <br>
+        <label>List:
+            <select name="list_id">
+                {list_options_html}
+            </select>
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="mode" value="pending"{pending_checked}>
+            Pending: queue a confirmation email to every imported subscriber
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="mode" value="confirmed"{confirmed_checked}>
+            Confirmed: consent was already collected
+        </label>
+        <br>
+        <label>Consent source (required for confirmed imports):
+            <input
+                type="text"
+                placeholder="e.g. Mailchimp double opt-in, exported 2022-11-28"
+                name="consent_source"
+                value="{consent_source}"
+            >
+        </label>
+        <br>
+        <button type="submit" name="action" value="dry_run">Dry run</button>
+        <button type="submit" name="action" value="import">Import</button>
+    </form>
+    {report_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        ))
+}
@@ -0,0 +1,356 @@
+use super::get::import_page;
+use super::report::{ImportReport, SkippedRow};
+use crate::authentication::UserId;
+use crate::domain::{EmailPolicy, NewSubscriber};
+use crate::routes::admin::lists::{get_list_id_or_default, get_lists, MailingList};
+use crate::routes::{
+    generate_subscription_token, get_subscriber_id, insert_membership, insert_subscriber,
+    store_token,
+};
+use crate::utils::{e400, e500, see_other};
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use uuid::Uuid;
+
+// Only the first rows of each kind are listed in the report.
+const MAX_REPORTED_ROWS: usize = 100;
+
+// The whole form, uploaded file included, is read into memory.
+const MAX_FORM_BYTES: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    /// Imported subscribers must confirm, as if they had used the form.
+    #[default]
+    Pending,
+    /// Consent was collected elsewhere, e.g. by the platform we migrate from.
+    Confirmed,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub enum ImportAction {
+    #[default]
+    DryRun,
+    Import,
+}
+
+#[derive(Default)]
+pub struct FormData {
+    pub(super) csv: String,
+    pub(super) list_id: Option<Uuid>,
+    pub(super) mode: ImportMode,
+    pub(super) consent_source: String,
+    action: ImportAction,
+}
+
+impl FormData {
+    /// Read the form, sent as `multipart/form-data`. The CSV is either the
+    /// uploaded `csv_file` or, as after a dry run, the `csv` text field. The
+    /// inner error is a message for the admin.
+    async fn read(mut payload: Multipart) -> Result<Result<FormData, String>, actix_web::Error> {
+        let mut form = FormData::default();
+        let mut uploaded_csv = String::new();
+        let mut n_bytes = 0;
+        while let Some(mut field) = payload.try_next().await? {
+            let name = field.name().unwrap_or_default().to_string();
+            let mut value = Vec::new();
+            while let Some(chunk) = field.try_next().await? {
+                n_bytes += chunk.len();
+                if n_bytes > MAX_FORM_BYTES {
+                    return Ok(Err(format!(
+                        "The CSV is larger than {} MB; split it into smaller files.",
+                        MAX_FORM_BYTES / 1024 / 1024
+                    )));
+                }
+                value.extend_from_slice(&chunk);
+            }
+            let value = match String::from_utf8(value) {
+                Ok(value) => value,
+                Err(_) => return Ok(Err(format!("The {} field is not valid UTF-8.", name))),
+            };
+            match name.as_str() {
+                "csv_file" => uploaded_csv = value,
+                "csv" => form.csv = value,
+                "list_id" => form.list_id = Some(value.parse::<Uuid>().map_err(e400)?),
+                "mode" => {
+                    form.mode = match value.as_str() {
+                        "pending" => ImportMode::Pending,
+                        "confirmed" => ImportMode::Confirmed,
+                        _ => return Err(e400(format!("Unknown import mode: {}", value))),
+                    }
+                }
+                "consent_source" => form.consent_source = value,
+                "action" => {
+                    form.action = match value.as_str() {
+                        "dry_run" => ImportAction::DryRun,
+                        "import" => ImportAction::Import,
+                        _ => return Err(e400(format!("Unknown import action: {}", value))),
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !uploaded_csv.trim().is_empty() {
+            form.csv = uploaded_csv;
+        }
+        Ok(Ok(form))
+    }
+}
+
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(payload, pool, email_policy, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn import_subscribers(
+    payload: Multipart,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let form = match FormData::read(payload).await? {
+        Ok(form) => form,
+        Err(e) => return Ok(error_page(&lists, &FormData::default(), &e)),
+    };
+    let list_id = match get_list_id_or_default(&pool, form.list_id)
+        .await
+        .context("Failed to look up the mailing list")
+        .map_err(e500)?
+    {
+        Some(list_id) => list_id,
+        None => {
+            return Ok(error_page(
+                &lists,
+                &form,
+                "The selected mailing list does not exist.",
+            ))
+        }
+    };
+    let denied_domains = get_denied_domains(&pool).await.map_err(e500)?;
+    let mut report = match ImportReport::parse(&form.csv, &email_policy, &denied_domains) {
+        Ok(report) => report,
+        Err(e) => return Ok(error_page(&lists, &form, &e)),
+    };
+    let memberships = get_memberships(&pool, list_id).await.map_err(e500)?;
+    report.mark_duplicates(|s| {
+        match memberships
+            .get(&s.email.normalised().to_lowercase())
+            .map(String::as_str)
+        {
+            Some("unsubscribed") => {
+                Some("The subscriber left this list and will not be added back.".into())
+            }
+            Some(_) => Some("The subscriber is already on this list.".into()),
+            None => None,
+        }
+    });
+
+    let consent_source = form.consent_source.trim();
+    if form.mode == ImportMode::Confirmed && consent_source.is_empty() {
+        return Ok(error_page(
+            &lists,
+            &form,
+            "Record where consent was collected to import subscribers as confirmed.",
+        ));
+    }
+    if form.action == ImportAction::DryRun {
+        return Ok(import_page(
+            "",
+            &lists,
+            &FormData {
+                list_id: Some(list_id),
+                ..form
+            },
+            &report_html(&report),
+        ));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    for row in &report.valid {
+        import_subscriber(
+            &mut transaction,
+            list_id,
+            &row.new_subscriber,
+            form.mode,
+            consent_source,
+        )
+        .await
+        .with_context(|| format!("Failed to import the subscriber on line {}.", row.line))
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers.")
+        .map_err(e500)?;
+
+    let outcome = match form.mode {
+        ImportMode::Pending => "as pending; confirmation emails have been queued",
+        ImportMode::Confirmed => "as confirmed",
+    };
+    FlashMessage::info(format!(
+        "Imported {} subscribers {}. {} invalid and {} duplicate rows were skipped.",
+        report.valid.len(),
+        outcome,
+        report.invalid.len(),
+        report.duplicates.len(),
+    ))
+    .send();
+    Ok(see_other("/admin/subscribers/import"))
+}
+
+fn error_page(lists: &[MailingList], form: &FormData, error: &str) -> HttpResponse {
+    let msg_html = format!("<p><i>{}</i></p>", htmlescape::encode_minimal(error));
+    import_page(&msg_html, lists, form, "")
+}
+
+fn report_html(report: &ImportReport) -> String {
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<h2>Dry run</h2>\n<p>Detected format: {}. {} valid, {} invalid and {} duplicate rows.</p>",
+        report.layout,
+        report.valid.len(),
+        report.invalid.len(),
+        report.duplicates.len(),
+    )
+    .unwrap();
+    for (title, rows) in [
+        ("Invalid rows", &report.invalid),
+        ("Duplicate rows", &report.duplicates),
+    ] {
+        if rows.is_empty() {
+            continue;
+        }
+        writeln!(html, "<h3>{}</h3>\n<ul>", title).unwrap();
+        for SkippedRow {
+            line,
+            email,
+            reason,
+        } in rows.iter().take(MAX_REPORTED_ROWS)
+        {
+            writeln!(
+                html,
+                "<li>Line {}: {} - {}</li>",
+                line,
+                htmlescape::encode_minimal(email),
+                htmlescape::encode_minimal(reason),
+            )
+            .unwrap();
+        }
+        if rows.len() > MAX_REPORTED_ROWS {
+            writeln!(
+                html,
+                "<li>... and {} more</li>",
+                rows.len() - MAX_REPORTED_ROWS
+            )
+            .unwrap();
+        }
+        writeln!(html, "</ul>").unwrap();
+    }
+    html
+}
+
+#[tracing::instrument(name = "Get denied email domains", skip(pool))]
+async fn get_denied_domains(pool: &PgPool) -> Result<HashSet<String>, anyhow::Error> {
+    let rows = sqlx::query!(r#"SELECT domain FROM denied_email_domains"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to perform a query to retrieve denied email domains.")?;
+    Ok(rows.into_iter().map(|r| r.domain).collect())
+}
+
+/// The membership status on `list_id`, keyed by lowercase normalised email.
+#[tracing::instrument(name = "Get list memberships", skip(pool))]
+async fn get_memberships(
+    pool: &PgPool,
+    list_id: Uuid,
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT lower(s.normalised_email) AS "email!", m.status
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE m.list_id = $1
+        "#,
+        list_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve list memberships.")?;
+    Ok(rows.into_iter().map(|r| (r.email, r.status)).collect())
+}
+
+#[tracing::instrument(
+    name = "Import a subscriber",
+    skip(transaction, new_subscriber, consent_source),
+    fields(subscriber_email=%new_subscriber.email.as_ref())
+)]
+async fn import_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    new_subscriber: &NewSubscriber,
+    mode: ImportMode,
+    consent_source: &str,
+) -> Result<(), anyhow::Error> {
+    let subscriber_id = match get_subscriber_id(transaction, &new_subscriber.email).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => insert_subscriber(transaction, new_subscriber).await?,
+    };
+    match mode {
+        ImportMode::Confirmed => {
+            sqlx::query!(
+                r#"
+                INSERT INTO list_memberships (
+                    list_id, subscriber_id, status, subscribed_at, consent_source, confirmed_at
+                )
+                VALUES ($1, $2, 'confirmed', now(), $3, now())
+                ON CONFLICT (list_id, subscriber_id) DO UPDATE
+                    SET
+                        status = 'confirmed',
+                        consent_source = EXCLUDED.consent_source,
+                        confirmed_at = now()
+                    WHERE list_memberships.status = 'pending_confirmation'
+                "#,
+                list_id,
+                subscriber_id,
+                consent_source,
+            )
+            .execute(&mut *transaction)
+            .await?;
+            sqlx::query!(
+                r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+                subscriber_id,
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+        ImportMode::Pending => {
+            if insert_membership(transaction, subscriber_id, list_id).await? {
+                let subscription_token = generate_subscription_token();
+                store_token(transaction, subscriber_id, list_id, &subscription_token).await?;
+                sqlx::query!(
+                    r#"
+                    INSERT INTO confirmation_email_queue (subscription_token, enqueued_at)
+                    VALUES ($1, now())
+                    "#,
+                    subscription_token,
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
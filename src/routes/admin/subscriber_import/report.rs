@@ -0,0 +1,302 @@
+use crate::domain::{
+    domain_rejection, domain_suffixes, EmailPolicy, NewSubscriber, SubscriberAttributes,
+    SubscriberEmail, SubscriberName,
+};
+use std::collections::{HashMap, HashSet};
+
+const MAX_ROWS: usize = 50_000;
+
+/// The export format a CSV file was recognised as, from its header row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportLayout {
+    Generic,
+    Mailchimp,
+    Substack,
+}
+
+impl std::fmt::Display for ImportLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportLayout::Generic => write!(f, "CSV with email and name columns"),
+            ImportLayout::Mailchimp => write!(f, "Mailchimp export"),
+            ImportLayout::Substack => write!(f, "Substack export"),
+        }
+    }
+}
+
+pub struct ValidRow {
+    pub line: usize,
+    pub new_subscriber: NewSubscriber,
+}
+
+/// A row that will not be imported, and why.
+pub struct SkippedRow {
+    pub line: usize,
+    pub email: String,
+    pub reason: String,
+}
+
+pub struct ImportReport {
+    pub layout: ImportLayout,
+    pub valid: Vec<ValidRow>,
+    pub invalid: Vec<SkippedRow>,
+    pub duplicates: Vec<SkippedRow>,
+}
+
+struct Columns {
+    email: usize,
+    name: Option<usize>,
+    first_name: Option<usize>,
+    last_name: Option<usize>,
+    email_disabled: Option<usize>,
+}
+
+impl ImportReport {
+    /// Validate every row of `csv`, flagging rows whose address appears
+    /// earlier in the file as duplicates. Fails if the file itself cannot be
+    /// read, e.g. because it has no email column.
+    pub fn parse(
+        csv: &str,
+        email_policy: &EmailPolicy,
+        denied_domains: &HashSet<String>,
+    ) -> Result<ImportReport, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(csv.trim_start_matches('\u{feff}').as_bytes());
+        let headers: Vec<String> = reader
+            .headers()
+            .map_err(|e| format!("The CSV header could not be read: {}", e))?
+            .iter()
+            .map(|h| h.to_lowercase())
+            .collect();
+        let (layout, columns) = detect_layout(&headers)?;
+
+        let mut report = ImportReport {
+            layout,
+            valid: Vec::new(),
+            invalid: Vec::new(),
+            duplicates: Vec::new(),
+        };
+        let mut first_seen_on: HashMap<String, usize> = HashMap::new();
+        for (i, record) in reader.records().enumerate() {
+            if i >= MAX_ROWS {
+                return Err(format!(
+                    "The CSV has more than {} rows; split it into smaller files.",
+                    MAX_ROWS
+                ));
+            }
+            // The header is line 1.
+            let line = i + 2;
+            let record = record.map_err(|e| format!("Line {} could not be read: {}", line, e))?;
+            let field = |column: Option<usize>| column.and_then(|c| record.get(c)).unwrap_or("");
+            let email = field(Some(columns.email)).to_string();
+            let skip = |reason: String| SkippedRow {
+                line,
+                email: email.clone(),
+                reason,
+            };
+
+            if matches!(field(columns.email_disabled), "true" | "TRUE" | "1") {
+                report.invalid.push(skip(
+                    "Email delivery is disabled for this subscriber in Substack.".into(),
+                ));
+                continue;
+            }
+            let new_subscriber = match parse_row(&email, &columns, &field, email_policy) {
+                Ok(new_subscriber) => new_subscriber,
+                Err(e) => {
+                    report.invalid.push(skip(e));
+                    continue;
+                }
+            };
+            let domain = new_subscriber.email.domain();
+            if domain_suffixes(domain).any(|d| denied_domains.contains(d)) {
+                report.invalid.push(skip(domain_rejection(domain)));
+                continue;
+            }
+            let key = new_subscriber.email.normalised().to_lowercase();
+            if let Some(first_line) = first_seen_on.get(&key) {
+                report.duplicates.push(skip(format!(
+                    "The same address appears on line {}.",
+                    first_line
+                )));
+                continue;
+            }
+            first_seen_on.insert(key, line);
+            report.valid.push(ValidRow {
+                line,
+                new_subscriber,
+            });
+        }
+        Ok(report)
+    }
+
+    /// Move valid rows matching `reason_for` to the duplicates, e.g. because
+    /// the address is already on the target list.
+    pub fn mark_duplicates(&mut self, reason_for: impl Fn(&NewSubscriber) -> Option<String>) {
+        let (valid, duplicates): (Vec<_>, Vec<_>) = std::mem::take(&mut self.valid)
+            .into_iter()
+            .map(|row| (reason_for(&row.new_subscriber), row))
+            .partition(|(reason, _)| reason.is_none());
+        self.valid = valid.into_iter().map(|(_, row)| row).collect();
+        self.duplicates
+            .extend(duplicates.into_iter().map(|(reason, row)| SkippedRow {
+                line: row.line,
+                email: row.new_subscriber.email.as_ref().to_string(),
+                reason: reason.unwrap_or_default(),
+            }));
+        self.duplicates.sort_by_key(|row| row.line);
+    }
+}
+
+fn detect_layout(headers: &[String]) -> Result<(ImportLayout, Columns), String> {
+    let find = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+    let layout = if find(&["email address"]).is_some() {
+        ImportLayout::Mailchimp
+    } else if find(&["active_subscription", "email_disabled"]).is_some() {
+        ImportLayout::Substack
+    } else {
+        ImportLayout::Generic
+    };
+    let email = find(&["email", "email address", "e-mail"])
+        .ok_or("The CSV has no `email` or `Email Address` column.")?;
+    Ok((
+        layout,
+        Columns {
+            email,
+            name: find(&["name", "full name"]),
+            first_name: find(&["first name", "first_name", "fname"]),
+            last_name: find(&["last name", "last_name", "lname"]),
+            email_disabled: find(&["email_disabled"]),
+        },
+    ))
+}
+
+fn parse_row<'a>(
+    email: &str,
+    columns: &Columns,
+    field: &impl Fn(Option<usize>) -> &'a str,
+    email_policy: &EmailPolicy,
+) -> Result<NewSubscriber, String> {
+    let email = SubscriberEmail::parse_with_policy(email.to_string(), email_policy)?;
+    let name = match field(columns.name) {
+        "" => [field(columns.first_name), field(columns.last_name)]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+        name => name.to_string(),
+    };
+    // Exports often lack names; the local part is the best we can do.
+    let name = if name.is_empty() {
+        email.as_ref().rsplit_once('@').unwrap().0.to_string()
+    } else {
+        name
+    };
+    let name = SubscriberName::parse(name)?;
+    Ok(NewSubscriber {
+        email,
+        name,
+        attributes: SubscriberAttributes::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportLayout, ImportReport};
+    use crate::domain::EmailPolicy;
+    use std::collections::HashSet;
+
+    fn parse(csv: &str) -> ImportReport {
+        ImportReport::parse(csv, &EmailPolicy::default(), &HashSet::new()).unwrap()
+    }
+
+    #[test]
+    fn generic_csv_files_are_imported() {
+        let report = parse("email,name\nursula@domain.com,Ursula Le Guin\n");
+        assert_eq!(report.layout, ImportLayout::Generic);
+        assert_eq!(report.valid.len(), 1);
+        assert_eq!(report.valid[0].line, 2);
+        assert_eq!(
+            report.valid[0].new_subscriber.name.as_ref(),
+            "Ursula Le Guin"
+        );
+    }
+
+    #[test]
+    fn mailchimp_exports_are_recognised() {
+        let csv = "\u{feff}Email Address,First Name,Last Name,MEMBER_RATING\n\
+            ursula@domain.com,Ursula,Le Guin,2\n";
+        let report = parse(csv);
+        assert_eq!(report.layout, ImportLayout::Mailchimp);
+        assert_eq!(
+            report.valid[0].new_subscriber.name.as_ref(),
+            "Ursula Le Guin"
+        );
+    }
+
+    #[test]
+    fn substack_exports_are_recognised() {
+        let csv = "email,active_subscription,expiry,plan,email_disabled,created_at\n\
+            ursula@domain.com,false,,free,false,2022-01-01T00:00:00.000Z\n\
+            octavia@domain.com,false,,free,true,2022-01-01T00:00:00.000Z\n";
+        let report = parse(csv);
+        assert_eq!(report.layout, ImportLayout::Substack);
+        assert_eq!(report.valid.len(), 1);
+        // Substack does not export names.
+        assert_eq!(report.valid[0].new_subscriber.name.as_ref(), "ursula");
+        assert_eq!(report.invalid.len(), 1);
+        assert_eq!(report.invalid[0].email, "octavia@domain.com");
+    }
+
+    #[test]
+    fn invalid_rows_are_reported_with_their_line() {
+        let report = parse("email,name\nnot-an-email,Ursula\nursula@domain.com,Ursula{}\n");
+        assert!(report.valid.is_empty());
+        let lines: Vec<usize> = report.invalid.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![2, 3]);
+    }
+
+    #[test]
+    fn denied_domains_are_invalid() {
+        let denied = HashSet::from(["example.com".to_string()]);
+        let report = ImportReport::parse(
+            "email\nursula@mail.example.com\n",
+            &EmailPolicy::default(),
+            &denied,
+        )
+        .unwrap();
+        assert_eq!(report.invalid.len(), 1);
+    }
+
+    #[test]
+    fn repeated_addresses_are_duplicates_of_the_first_occurrence() {
+        let report = parse("email\nursula@domain.com\nURSULA@Domain.com\n");
+        assert_eq!(report.valid.len(), 1);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].line, 3);
+        assert_eq!(
+            report.duplicates[0].reason,
+            "The same address appears on line 2."
+        );
+    }
+
+    #[test]
+    fn rows_can_be_marked_as_duplicates_later() {
+        let mut report = parse("email\nursula@domain.com\noctavia@domain.com\n");
+        report.mark_duplicates(|s| {
+            (s.email.as_ref() == "octavia@domain.com").then(|| "Already on the list.".into())
+        });
+        assert_eq!(report.valid.len(), 1);
+        assert_eq!(report.duplicates[0].email, "octavia@domain.com");
+    }
+
+    #[test]
+    fn files_without_an_email_column_are_rejected() {
+        assert!(
+            ImportReport::parse("name\nUrsula\n", &EmailPolicy::default(), &HashSet::new())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use super::post::SubscriberAction;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    bounced_at: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+}
+
+struct Membership {
+    list_name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    consent_source: Option<String>,
+}
+
+struct AuditLogEntry {
+    action: String,
+    performed_by: Option<String>,
+    performed_at: DateTime<Utc>,
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let memberships = get_memberships(&pool, subscriber_id).await.map_err(e500)?;
+    let audit_log = get_audit_log(&pool, subscriber_id).await.map_err(e500)?;
+
+    let mut memberships_html = String::new();
+    for m in &memberships {
+        writeln!(
+            memberships_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&m.list_name),
+            m.status,
+            m.subscribed_at.format("%Y-%m-%d %H:%M"),
+            htmlescape::encode_minimal(m.consent_source.as_deref().unwrap_or("")),
+        )
+        .unwrap();
+    }
+    let mut audit_log_html = String::new();
+    for entry in &audit_log {
+        writeln!(
+            audit_log_html,
+            "<li>{}: {} by {}</li>",
+            entry.performed_at.format("%Y-%m-%d %H:%M"),
+            SubscriberAction::describe(&entry.action),
+            htmlescape::encode_minimal(entry.performed_by.as_deref().unwrap_or("a deleted user")),
+        )
+        .unwrap();
+    }
+    if audit_log.is_empty() {
+        audit_log_html.push_str("<li>No admin has changed this subscriber.</li>");
+    }
+    let mut actions_html = String::new();
+    for (action, label) in [
+        ("confirm", "Confirm"),
+        ("unsubscribe", "Unsubscribe from all lists"),
+        ("resend_confirmation", "Resend the confirmation email"),
+        ("delete", "Delete"),
+    ] {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{}/{}" method="post"><button type="submit">{}</button></form>"#,
+            subscriber_id, action, label,
+        )
+        .unwrap();
+    }
+    let email = htmlescape::encode_minimal(&subscriber.email);
+    let email_query = urlencoding::encode(&subscriber.email);
+    let name = htmlescape::encode_minimal(&subscriber.name);
+    let status = subscriber.status;
+    let subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M");
+    let bounced_at = subscriber
+        .bounced_at
+        .map(|b| b.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "never".into());
+    let tags = htmlescape::encode_minimal(&subscriber.tags.join(", "));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{email}</h1>
+    <ul>
+        <li>Name: {name}</li>
+        <li>Address status: {status}</li>
+        <li>Subscribed at: {subscribed_at}</li>
+        <li>Bounced at: {bounced_at}</li>
+        <li>Tags: {tags}</li>
+    </ul>
+    <p><a href="/admin/subscribers/attributes?email={email_query}">Edit attributes and tags</a></p>
+    <h2>Lists</h2>
+    <table>
+        <tr><th>List</th><th>Status</th><th>Subscribed at</th><th>Consent</th></tr>
+        {memberships_html}
+    </table>
+    <h2>History</h2>
+    <ul>
+    {audit_log_html}
+    </ul>
+    <h2>Actions</h2>
+    {actions_html}
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            s.bounced_at,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS "tags!"
+        FROM subscriptions s
+        WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a subscriber.")?;
+    Ok(subscriber)
+}
+
+#[tracing::instrument(name = "Get subscriber memberships", skip(pool))]
+async fn get_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Membership>, anyhow::Error> {
+    let memberships = sqlx::query_as!(
+        Membership,
+        r#"
+        SELECT l.name AS list_name, m.status, m.subscribed_at, m.consent_source
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at, l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve list memberships.")?;
+    Ok(memberships)
+}
+
+#[tracing::instrument(name = "Get subscriber audit log", skip(pool))]
+async fn get_audit_log(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<AuditLogEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT a.action, u.username AS "performed_by?", a.performed_at
+        FROM subscriber_audit_log a
+        LEFT JOIN users u ON u.id = a.performed_by
+        WHERE a.subscriber_id = $1
+        ORDER BY a.performed_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the subscriber audit log.")?;
+    Ok(entries)
+}
@@ -0,0 +1,220 @@
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+// As computed by the `subscriber_statuses` view.
+const STATUSES: [(&str, &str); 3] = [
+    ("confirmed", "Confirmed"),
+    ("pending_confirmation", "Pending confirmation"),
+    ("unsubscribed", "Unsubscribed"),
+];
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    status: String,
+    page: Option<i64>,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+pub async fn subscribers_page(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let QueryParams {
+        search,
+        status,
+        page,
+    } = query.0;
+    let search = search.trim();
+    let status = status.trim();
+    if !status.is_empty() && !STATUSES.iter().any(|(s, _)| *s == status) {
+        return Err(e400(format!(
+            "{} is not a valid subscriber status.",
+            status
+        )));
+    }
+    let page = page.unwrap_or(1).max(1);
+    let (subscribers, total) = search_subscribers(&pool, search, status, page)
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for s in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            s.id,
+            htmlescape::encode_minimal(&s.email),
+            htmlescape::encode_minimal(&s.name),
+            s.status,
+            s.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+    let mut status_options_html = String::new();
+    for (value, label) in STATUSES {
+        writeln!(
+            status_options_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            value,
+            if value == status { " selected" } else { "" },
+            label,
+        )
+        .unwrap();
+    }
+    let page_link = |page: i64| {
+        format!(
+            "/admin/subscribers?search={}&amp;status={}&amp;page={}",
+            urlencoding::encode(search),
+            urlencoding::encode(status),
+            page
+        )
+    };
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="{}">Previous</a> "#,
+            page_link(page - 1)
+        )
+        .unwrap();
+    }
+    if page * PAGE_SIZE < total {
+        write!(
+            pagination_html,
+            r#"<a href="{}">Next</a>"#,
+            page_link(page + 1)
+        )
+        .unwrap();
+    }
+    let first = ((page - 1) * PAGE_SIZE + 1).min(total);
+    let last = (page - 1) * PAGE_SIZE + subscribers.len() as i64;
+    let search = htmlescape::encode_attribute(search);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Search
+            <input
+                type="text"
+                placeholder="Email or name"
+                name="search"
+                value="{search}"
+            >
+        </label>
+        <label>Status
+            <select name="status">
+                <option value="">Any</option>
+                {status_options_html}
+            </select>
+        </label>
+        <button type="submit">Search</button>
+    </form>
+    <p>Showing {first}-{last} of {total} subscribers.</p>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+        {rows_html}
+    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// Escape the wildcards of a `LIKE` pattern, to match `search` literally.
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[tracing::instrument(name = "Search subscribers", skip(pool))]
+async fn search_subscribers(
+    pool: &PgPool,
+    search: &str,
+    status: &str,
+    page: i64,
+) -> Result<(Vec<SubscriberRow>, i64), anyhow::Error> {
+    let search = (!search.is_empty()).then(|| like_pattern(search));
+    let status = (!status.is_empty()).then_some(status);
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM subscriber_statuses s
+        WHERE ($1::text IS NULL OR s.email ILIKE $1 OR s.name ILIKE $1)
+        AND ($2::text IS NULL OR s.status = $2)
+        "#,
+        search,
+        status,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers.")?;
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT s.id AS "id!", s.email AS "email!", s.name AS "name!",
+            s.status AS "status!", s.subscribed_at AS "subscribed_at!"
+        FROM subscriber_statuses s
+        WHERE ($1::text IS NULL OR s.email ILIKE $1 OR s.name ILIKE $1)
+        AND ($2::text IS NULL OR s.status = $2)
+        ORDER BY s.subscribed_at DESC, s.id
+        LIMIT $3 OFFSET $4
+        "#,
+        search,
+        status,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?;
+    Ok((subscribers, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::like_pattern;
+
+    #[test]
+    fn like_wildcards_are_matched_literally() {
+        assert_eq!(like_pattern("ursula"), "%ursula%");
+        assert_eq!(like_pattern(r"50%_\"), r"%50\%\_\\%");
+    }
+}
@@ -0,0 +1,7 @@
+mod details;
+mod get;
+mod post;
+
+pub use details::subscriber_details;
+pub use get::subscribers_page;
+pub use post::manage_subscriber;
@@ -0,0 +1,247 @@
+use crate::authentication::UserId;
+use crate::routes::{generate_subscription_token, store_token};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberAction {
+    Confirm,
+    Unsubscribe,
+    ResendConfirmation,
+    Delete,
+}
+
+impl SubscriberAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberAction::Confirm => "confirm",
+            SubscriberAction::Unsubscribe => "unsubscribe",
+            SubscriberAction::ResendConfirmation => "resend_confirmation",
+            SubscriberAction::Delete => "delete",
+        }
+    }
+
+    /// How an action recorded in the audit log reads in the history.
+    pub fn describe(action: &str) -> &str {
+        match action {
+            "confirm" => "Confirmed",
+            "unsubscribe" => "Unsubscribed from all lists",
+            "resend_confirmation" => "Confirmation email resent",
+            "delete" => "Deleted",
+            action => action,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Manage a subscriber",
+    skip(path, pool, user_id),
+    fields(user_id=%*user_id, subscriber_id=%path.0, action=?path.1)
+)]
+pub async fn manage_subscriber(
+    path: web::Path<(Uuid, SubscriberAction)>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (subscriber_id, action) = path.into_inner();
+    let location = format!("/admin/subscribers/{}", subscriber_id);
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Locking the subscriber serialises concurrent actions on it.
+    let email = match sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")
+    .map_err(e500)?
+    {
+        Some(r) => r.email,
+        None => {
+            FlashMessage::error("There is no such subscriber.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    let outcome = match action {
+        SubscriberAction::Confirm => confirm(&mut transaction, subscriber_id).await,
+        SubscriberAction::Unsubscribe => unsubscribe(&mut transaction, subscriber_id).await,
+        SubscriberAction::ResendConfirmation => {
+            resend_confirmation(&mut transaction, subscriber_id).await
+        }
+        SubscriberAction::Delete => delete(&mut transaction, subscriber_id).await,
+    }
+    .with_context(|| format!("Failed to {} the subscriber.", action.as_str()))
+    .map_err(e500)?;
+    let email_html = htmlescape::encode_minimal(&email);
+    if let Err(e) = outcome {
+        FlashMessage::error(format!("{} {}.", email_html, e)).send();
+        return Ok(see_other(&location));
+    }
+    record_audit_log(&mut transaction, subscriber_id, &email, action, **user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to manage a subscriber.")
+        .map_err(e500)?;
+
+    let location = location.as_str();
+    let (message, location) = match action {
+        SubscriberAction::Confirm => ("has been confirmed", location),
+        SubscriberAction::Unsubscribe => ("has been unsubscribed from all lists", location),
+        SubscriberAction::ResendConfirmation => ("will receive a new confirmation email", location),
+        SubscriberAction::Delete => ("has been deleted", "/admin/subscribers"),
+    };
+    FlashMessage::info(format!("{} {}.", email_html, message)).send();
+    Ok(see_other(location))
+}
+
+// The inner result is the reason an action had nothing to do.
+type ActionOutcome = Result<Result<(), &'static str>, anyhow::Error>;
+
+async fn confirm(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> ActionOutcome {
+    let n_confirmed_memberships = sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'confirmed', confirmed_at = now()
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    let n_confirmed_addresses = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_confirmed_memberships + n_confirmed_addresses == 0 {
+        return Ok(Err("has nothing pending confirmation"));
+    }
+    Ok(Ok(()))
+}
+
+async fn unsubscribe(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> ActionOutcome {
+    let n_unsubscribed = sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND status <> 'unsubscribed'
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_unsubscribed == 0 {
+        return Ok(Err("is not subscribed to any list"));
+    }
+    Ok(Ok(()))
+}
+
+async fn resend_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> ActionOutcome {
+    let pending_lists = sqlx::query!(
+        r#"
+        SELECT list_id FROM list_memberships
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    if pending_lists.is_empty() {
+        return Ok(Err("has no subscription pending confirmation"));
+    }
+    for r in pending_lists {
+        let subscription_token = generate_subscription_token();
+        store_token(transaction, subscriber_id, r.list_id, &subscription_token).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO confirmation_email_queue (subscription_token, enqueued_at)
+            VALUES ($1, now())
+            "#,
+            subscription_token,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(Ok(()))
+}
+
+async fn delete(transaction: &mut Transaction<'_, Postgres>, subscriber_id: Uuid) -> ActionOutcome {
+    // Memberships and tags are deleted along with the subscriber.
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // The queue is keyed on the address: emails still queued for it would
+    // otherwise go out to a subscriber that no longer exists.
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue q
+        USING subscriptions s
+        WHERE s.id = $1 AND q.subscriber_email = s.email
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(Ok(()))
+}
+
+#[tracing::instrument(name = "Record subscriber audit log", skip(transaction, email))]
+async fn record_audit_log(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+    action: SubscriberAction,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_audit_log (
+            audit_log_id, subscriber_id, subscriber_email, action, performed_by, performed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        email,
+        action.as_str(),
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record the subscriber audit log.")?;
+    Ok(())
+}
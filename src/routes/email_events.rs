@@ -0,0 +1,105 @@
+use crate::startup::EmailEventsToken;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct EmailEventsParameters {
+    token: String,
+}
+
+/// What the email provider reports about an email after accepting it. The
+/// provider posts them one at a time or in batches.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum EmailEvents {
+    One(EmailEvent),
+    Many(Vec<EmailEvent>),
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailEvent {
+    event: String,
+    email: String,
+    // Seconds since the epoch.
+    time: i64,
+    #[serde(default)]
+    hard_bounce: bool,
+}
+
+impl EmailEvent {
+    fn happened_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.time, 0)
+            .single()
+            .unwrap_or_else(Utc::now)
+    }
+}
+
+// Comparing digests keeps the time taken unrelated to the configured token.
+fn is_valid_token(expected: &EmailEventsToken, token: &str) -> bool {
+    Sha256::digest(expected.0.expose_secret().as_bytes()) == Sha256::digest(token.as_bytes())
+}
+
+/// Record the hard bounces reported by the email provider. Other events are
+/// acknowledged and ignored.
+#[tracing::instrument(name = "Record email events", skip_all)]
+pub async fn record_email_events(
+    parameters: web::Query<EmailEventsParameters>,
+    events: web::Json<EmailEvents>,
+    pool: web::Data<PgPool>,
+    events_token: web::Data<EmailEventsToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_valid_token(&events_token, &parameters.token) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let events = match events.into_inner() {
+        EmailEvents::One(event) => vec![event],
+        EmailEvents::Many(events) => events,
+    };
+    for event in events {
+        if event.event == "bounce" && event.hard_bounce {
+            record_bounce(&pool, &event).await.map_err(e500)?;
+        }
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(skip(pool, event), fields(subscriber_email=%event.email))]
+async fn record_bounce(pool: &PgPool, event: &EmailEvent) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET bounced_at = $2
+        WHERE lower(email) = lower($1) AND bounced_at IS NULL
+        "#,
+        event.email,
+        event.happened_at(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a bounce")?;
+    // The bounce is taken to be about the latest issue delivered to the
+    // address, for staged sends to account for it.
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries SET bounced_at = $2
+        WHERE (newsletter_issue_id, subscriber_email) IN (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_deliveries
+            WHERE lower(subscriber_email) = lower($1) AND outcome = 'delivered'
+            ORDER BY attempted_at DESC
+            LIMIT 1
+        )
+        AND bounced_at IS NULL
+        "#,
+        event.email,
+        event.happened_at(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the bounce of an issue")?;
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use super::issues::{issue_url, render_for_web, web_html};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::{
+    ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const FEED_TITLE: &str = "Newsletter archive";
+const MAX_FEED_ISSUES: i64 = 50;
+
+struct FeedEntry {
+    // Feed readers tell entries apart by their id, which must not change
+    // when the title, and so the slug, is edited.
+    guid: String,
+    url: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+struct Feed {
+    entries: Vec<FeedEntry>,
+    // When an issue was last published, hidden or shown.
+    updated_at: DateTime<Utc>,
+}
+
+fn issue_guid(issue_id: Uuid) -> String {
+    format!("urn:uuid:{}", issue_id)
+}
+
+fn xml_escape(s: &str) -> String {
+    htmlescape::encode_minimal(s)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_feed(pool: &PgPool, base_url: &str) -> Result<Feed, anyhow::Error> {
+    let issues = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issue_id,
+            slug AS "slug!",
+            title,
+            text_content,
+            html_content,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+            status = 'published'
+            AND slug IS NOT NULL
+            AND published_at IS NOT NULL
+            AND NOT hidden_from_archive
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        MAX_FEED_ISSUES,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the published issues")?;
+    let updated_at = sqlx::query!(
+        r#"
+        SELECT max(updated_at) AS updated_at
+        FROM newsletter_issues
+        WHERE status = 'published'
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve when the archive was last updated")?
+    .updated_at
+    .unwrap_or_else(|| Utc.timestamp_opt(0, 0).unwrap());
+
+    let entries = issues
+        .into_iter()
+        .map(|issue| {
+            let text_content =
+                render_for_web(&issue.text_content, false).unwrap_or(issue.text_content);
+            FeedEntry {
+                guid: issue_guid(issue.newsletter_issue_id),
+                url: issue_url(base_url, &issue.slug),
+                title: render_for_web(&issue.title, false).unwrap_or(issue.title),
+                html_content: web_html(&issue.html_content, &text_content),
+                published_at: issue.published_at,
+            }
+        })
+        .collect();
+    Ok(Feed {
+        entries,
+        updated_at,
+    })
+}
+
+/// Answer with `body`, or with `304 Not Modified` if the reader already has
+/// it according to the conditional headers of the request.
+fn feed_response(
+    content_type: &str,
+    body: String,
+    updated_at: DateTime<Utc>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+) -> HttpResponse {
+    let etag = EntityTag::new_strong(hex::encode(&Sha256::digest(body.as_bytes())[..16]));
+    // HTTP dates have a precision of a second.
+    let last_modified: HttpDate =
+        (SystemTime::UNIX_EPOCH + Duration::from_secs(updated_at.timestamp().max(0) as u64)).into();
+    // A missing If-None-Match header is parsed as an empty list of tags.
+    let if_none_match = if_none_match
+        .map(web::Header::into_inner)
+        .filter(|h| !matches!(h, IfNoneMatch::Items(etags) if etags.is_empty()));
+    // If-Modified-Since is only looked at without If-None-Match.
+    let not_modified = match (if_none_match, if_modified_since) {
+        (Some(IfNoneMatch::Any), _) => true,
+        (Some(IfNoneMatch::Items(etags)), _) => etags.iter().any(|e| e.weak_eq(&etag)),
+        (None, Some(if_modified_since)) => last_modified <= if_modified_since.into_inner().0,
+        (None, None) => false,
+    };
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified));
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(content_type).body(body)
+    }
+}
+
+/// Published issues as an RSS 2.0 feed.
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let feed = get_feed(&pool, base_url).await.map_err(e500)?;
+    let mut items = String::new();
+    for entry in &feed.entries {
+        writeln!(
+            items,
+            r#"    <item>
+      <title>{}</title>
+      <link>{}</link>
+      <guid isPermaLink="false">{}</guid>
+      <pubDate>{}</pubDate>
+      <description>{}</description>
+    </item>"#,
+            xml_escape(&entry.title),
+            xml_escape(&entry.url),
+            entry.guid,
+            entry.published_at.to_rfc2822(),
+            xml_escape(&entry.html_content),
+        )
+        .unwrap();
+    }
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>{title}</title>
+    <link>{base_url}/issues</link>
+    <description>Every issue of the newsletter.</description>
+    <atom:link href="{base_url}/feed.xml" rel="self" type="application/rss+xml"/>
+    <lastBuildDate>{updated_at}</lastBuildDate>
+{items}  </channel>
+</rss>
+"#,
+        title = FEED_TITLE,
+        base_url = xml_escape(base_url),
+        updated_at = feed.updated_at.to_rfc2822(),
+    );
+    Ok(feed_response(
+        "application/rss+xml; charset=utf-8",
+        body,
+        feed.updated_at,
+        if_none_match,
+        if_modified_since,
+    ))
+}
+
+/// Published issues as an Atom feed.
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let feed = get_feed(&pool, base_url).await.map_err(e500)?;
+    let mut entries = String::new();
+    for entry in &feed.entries {
+        writeln!(
+            entries,
+            r#"  <entry>
+    <title>{}</title>
+    <link href="{}"/>
+    <id>{}</id>
+    <published>{}</published>
+    <updated>{}</updated>
+    <content type="html">{}</content>
+  </entry>"#,
+            xml_escape(&entry.title),
+            xml_escape(&entry.url),
+            entry.guid,
+            entry.published_at.to_rfc3339(),
+            entry.published_at.to_rfc3339(),
+            xml_escape(&entry.html_content),
+        )
+        .unwrap();
+    }
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{title}</title>
+  <id>{base_url}/issues</id>
+  <link href="{base_url}/issues"/>
+  <link href="{base_url}/atom.xml" rel="self"/>
+  <updated>{updated_at}</updated>
+  <author><name>{title}</name></author>
+{entries}</feed>
+"#,
+        title = FEED_TITLE,
+        base_url = xml_escape(base_url),
+        updated_at = feed.updated_at.to_rfc3339(),
+    );
+    Ok(feed_response(
+        "application/atom+xml; charset=utf-8",
+        body,
+        feed.updated_at,
+        if_none_match,
+        if_modified_since,
+    ))
+}
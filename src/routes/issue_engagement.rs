@@ -0,0 +1,191 @@
+use crate::startup::HmacSecret;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// A transparent 1x1 GIF.
+const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(serde::Deserialize)]
+pub struct OpenParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ClickParameters {
+    subscriber_id: Uuid,
+    url: String,
+    token: String,
+}
+
+/// Build the URL of the image telling that a subscriber opened an issue. As
+/// for unsubscribe links, the token is an HMAC of what the URL is about.
+pub fn open_tracking_url(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    format!(
+        "{}/issues/{}/opened?subscriber_id={}&token={}",
+        base_url,
+        issue_id,
+        subscriber_id,
+        hex::encode(
+            engagement_mac(hmac_secret, issue_id, subscriber_id, None)
+                .finalize()
+                .into_bytes()
+        )
+    )
+}
+
+/// Build the URL that records a subscriber following the link to `url` in an
+/// issue before taking them there. The token covers `url`, so the redirect
+/// cannot be pointed elsewhere.
+pub fn click_tracking_url(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: &str,
+) -> String {
+    format!(
+        "{}/issues/{}/clicked?subscriber_id={}&url={}&token={}",
+        base_url,
+        issue_id,
+        subscriber_id,
+        urlencoding::encode(url),
+        hex::encode(
+            engagement_mac(hmac_secret, issue_id, subscriber_id, Some(url))
+                .finalize()
+                .into_bytes()
+        )
+    )
+}
+
+fn engagement_mac(
+    hmac_secret: &Secret<String>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: Option<&str>,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes()).unwrap();
+    match url {
+        Some(url) => {
+            mac.update(b"clicked:");
+            mac.update(issue_id.as_bytes());
+            mac.update(subscriber_id.as_bytes());
+            mac.update(url.as_bytes());
+        }
+        None => {
+            mac.update(b"opened:");
+            mac.update(issue_id.as_bytes());
+            mac.update(subscriber_id.as_bytes());
+        }
+    }
+    mac
+}
+
+fn is_valid_token(mac: Hmac<Sha256>, token: &str) -> bool {
+    hex::decode(token).is_ok_and(|token| mac.verify_slice(&token).is_ok())
+}
+
+#[tracing::instrument(
+    name = "Record an issue being opened",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id=%parameters.subscriber_id)
+)]
+pub async fn track_open(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<OpenParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mac = engagement_mac(&hmac_secret.0, issue_id, parameters.subscriber_id, None);
+    if !is_valid_token(mac, &parameters.token) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_engagement(&pool, issue_id, parameters.subscriber_id, &["open"])
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(TRACKING_PIXEL))
+}
+
+#[tracing::instrument(
+    name = "Record a link of an issue being followed",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id=%parameters.subscriber_id)
+)]
+pub async fn track_click(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<ClickParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mac = engagement_mac(
+        &hmac_secret.0,
+        issue_id,
+        parameters.subscriber_id,
+        Some(&parameters.url),
+    );
+    if !is_valid_token(mac, &parameters.token) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    // Following a link means the issue was opened, even if its images were
+    // blocked.
+    record_engagement(
+        &pool,
+        issue_id,
+        parameters.subscriber_id,
+        &["open", "click"],
+    )
+    .await
+    .map_err(e500)?;
+    Ok(see_other(&parameters.url))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn record_engagement(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    kinds: &[&str],
+) -> Result<(), anyhow::Error> {
+    let kinds: Vec<String> = kinds.iter().map(|kind| kind.to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_engagements (
+            newsletter_issue_id,
+            subscriber_email,
+            kind,
+            engaged_at
+        )
+        SELECT $1, s.email, kind, now()
+        FROM subscriptions s, unnest($3::text[]) kind
+        WHERE s.id = $2
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        subscriber_id,
+        &kinds[..],
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the engagement")?;
+    Ok(())
+}
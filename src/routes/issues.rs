@@ -0,0 +1,340 @@
+use crate::domain::SubscriberAttributes;
+use crate::newsletter_content::{prepare_html_body, MergeData, Template};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+
+const MAX_SLUG_LENGTH: usize = 60;
+const MAX_DESCRIPTION_LENGTH: usize = 200;
+
+/// Where a published issue can be read online, under `/issues/`: its title
+/// made URL-friendly, followed by the start of its id to tell apart issues
+/// with the same title.
+pub fn issue_slug(title: &str, issue_id: Uuid) -> String {
+    let mut slug = String::new();
+    // Accented letters are decomposed, so their accent can be dropped.
+    for c in title.nfkd().filter(|c| !is_combining_mark(*c)) {
+        if slug.len() >= MAX_SLUG_LENGTH {
+            break;
+        }
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let id = issue_id.to_string();
+    if slug.is_empty() {
+        id[..8].to_string()
+    } else {
+        format!("{}-{}", slug, &id[..8])
+    }
+}
+
+pub fn issue_url(base_url: &str, slug: &str) -> String {
+    format!("{}/issues/{}", base_url, slug)
+}
+
+/// Issues are read online by anyone, so merge tags fall back to their
+/// defaults.
+pub(super) fn render_for_web(content: &str, html: bool) -> Option<String> {
+    let attributes = SubscriberAttributes::default();
+    let merge_data = MergeData {
+        name: "",
+        email: "",
+        attributes: &attributes,
+        unsubscribe_url: "#",
+    };
+    let template = Template::parse(content).ok()?;
+    Some(if html {
+        template.render_html(&merge_data)
+    } else {
+        template.render_text(&merge_data)
+    })
+}
+
+fn published_on(published_at: Option<DateTime<Utc>>) -> String {
+    published_at.map_or_else(String::new, |published_at| {
+        published_at.format("%Y-%m-%d").to_string()
+    })
+}
+
+/// The HTML part of an issue as shown on the web.
+pub(super) fn web_html(html_content: &str, text_content: &str) -> String {
+    // Issues published before their HTML was checked may not pass, and are
+    // shown as plain text instead.
+    prepare_html_body(html_content)
+        .ok()
+        .and_then(|html_content| render_for_web(&html_content, true))
+        .unwrap_or_else(|| {
+            format!(
+                r#"<pre style="white-space: pre-wrap;">{}</pre>"#,
+                htmlescape::encode_minimal(text_content)
+            )
+        })
+}
+
+// Separate the highlighted words of a snippet from the rest of the text.
+const SNIPPET_START: char = '\u{2}';
+const SNIPPET_STOP: char = '\u{3}';
+const MAX_SEARCH_RESULTS: i64 = 50;
+
+pub struct IssueMatch {
+    pub newsletter_issue_id: Uuid,
+    pub slug: String,
+    pub title: String,
+    pub hidden_from_archive: bool,
+    pub published_at: Option<DateTime<Utc>>,
+    snippet: String,
+}
+
+impl IssueMatch {
+    /// The part of the text content matching the search, as HTML with the
+    /// matching words in `<mark>`.
+    pub fn snippet_html(&self) -> String {
+        snippet_html(&self.snippet)
+    }
+}
+
+/// The options of `ts_headline` for snippets passed to `snippet_html`.
+pub fn snippet_options() -> String {
+    format!(
+        "StartSel={}, StopSel={}, MaxFragments=2, MaxWords=20, MinWords=5",
+        SNIPPET_START, SNIPPET_STOP
+    )
+}
+
+pub fn snippet_html(snippet: &str) -> String {
+    htmlescape::encode_minimal(snippet)
+        .replace(SNIPPET_START, "<mark>")
+        .replace(SNIPPET_STOP, "</mark>")
+}
+
+/// Search published issues, best matches first. Issues hidden from the
+/// archive are only included for admins.
+#[tracing::instrument(name = "Search issues", skip(pool))]
+pub async fn search_issues(
+    pool: &PgPool,
+    search: &str,
+    include_hidden: bool,
+) -> Result<Vec<IssueMatch>, anyhow::Error> {
+    let options = snippet_options();
+    sqlx::query_as!(
+        IssueMatch,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            slug AS "slug!",
+            title,
+            hidden_from_archive,
+            published_at,
+            ts_headline('english', text_content, query, $3) AS "snippet!"
+        FROM newsletter_issues, websearch_to_tsquery('english', $1) query
+        WHERE
+            search_vector @@ query
+            AND status = 'published'
+            AND slug IS NOT NULL
+            AND ($2 OR NOT hidden_from_archive)
+        ORDER BY ts_rank(search_vector, query) DESC, published_at DESC
+        LIMIT $4
+        "#,
+        search,
+        include_hidden,
+        options,
+        MAX_SEARCH_RESULTS,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to search the issues")
+}
+
+#[derive(serde::Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    search: String,
+}
+
+pub async fn issues_index(
+    query: web::Query<SearchParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let search = query.search.trim();
+    let mut issues_html = String::new();
+    if search.is_empty() {
+        let issues = sqlx::query!(
+            r#"
+            SELECT slug AS "slug!", title, published_at
+            FROM newsletter_issues
+            WHERE status = 'published' AND slug IS NOT NULL AND NOT hidden_from_archive
+            ORDER BY published_at DESC
+            "#
+        )
+        .fetch_all(pool.get_ref())
+        .await
+        .context("Failed to retrieve the published issues")
+        .map_err(e500)?;
+        for issue in &issues {
+            let title = render_for_web(&issue.title, false).unwrap_or_else(|| issue.title.clone());
+            writeln!(
+                issues_html,
+                r#"<li><a href="/issues/{}">{}</a> - {}</li>"#,
+                issue.slug,
+                htmlescape::encode_minimal(&title),
+                published_on(issue.published_at),
+            )
+            .unwrap();
+        }
+        if issues.is_empty() {
+            issues_html.push_str("<li>No issues have been published yet.</li>");
+        }
+    } else {
+        let issues = search_issues(&pool, search, false).await.map_err(e500)?;
+        for issue in &issues {
+            let title = render_for_web(&issue.title, false).unwrap_or_else(|| issue.title.clone());
+            writeln!(
+                issues_html,
+                r#"<li><a href="/issues/{}">{}</a> - {}<br>{}</li>"#,
+                issue.slug,
+                htmlescape::encode_minimal(&title),
+                published_on(issue.published_at),
+                issue.snippet_html(),
+            )
+            .unwrap();
+        }
+        if issues.is_empty() {
+            issues_html.push_str("<li>No issues match your search.</li>");
+        }
+    }
+    let search = htmlescape::encode_attribute(search);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter archive</title>
+</head>
+<body>
+    <h1>Newsletter archive</h1>
+    <form action="/issues" method="get">
+        <input type="search" name="search" value="{search}" aria-label="Search issues">
+        <button type="submit">Search</button>
+    </form>
+    <ul>
+    {issues_html}
+    </ul>
+</body>
+</html>"#,
+        )))
+}
+
+/// The web version of a published issue, which emails link to.
+pub async fn issue_page(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let slug = slug.into_inner();
+    let issue = match sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content, published_at
+        FROM newsletter_issues
+        WHERE slug = $1 AND status = 'published' AND NOT hidden_from_archive
+        "#,
+        slug,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?
+    {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let title = render_for_web(&issue.title, false).unwrap_or(issue.title);
+    let text_content = render_for_web(&issue.text_content, false).unwrap_or(issue.text_content);
+    let html_content = web_html(&issue.html_content, &text_content);
+    let mut description = text_content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let Some((cut, _)) = description.char_indices().nth(MAX_DESCRIPTION_LENGTH) {
+        description.truncate(cut);
+        description.push('…');
+    }
+    let url = issue_url(&base_url.0, &slug);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+    <meta name="description" content="{description}">
+    <link rel="canonical" href="{url}">
+    <meta property="og:type" content="article">
+    <meta property="og:title" content="{title_attribute}">
+    <meta property="og:description" content="{description}">
+    <meta property="og:url" content="{url}">
+</head>
+<body>
+    <p><a href="/issues">&lt;- All issues</a></p>
+    <h1>{title}</h1>
+    <p>Published on {published_on}</p>
+    {html_content}
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&title),
+            title_attribute = htmlescape::encode_attribute(&title),
+            description = htmlescape::encode_attribute(&description),
+            url = htmlescape::encode_attribute(&url),
+            published_on = published_on(issue.published_at),
+        )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_slug, IssueMatch};
+    use uuid::Uuid;
+
+    #[test]
+    fn slugs_are_made_of_the_title_and_the_start_of_the_id() {
+        let issue_id = Uuid::parse_str("6f1c2d3e-0000-4000-8000-000000000000").unwrap();
+        assert_eq!(
+            issue_slug("Café news: December's issue!", issue_id),
+            "cafe-news-december-s-issue-6f1c2d3e"
+        );
+        assert_eq!(issue_slug("¡!", issue_id), "6f1c2d3e");
+        assert!(issue_slug(&"a ".repeat(100), issue_id).len() <= super::MAX_SLUG_LENGTH + 9);
+    }
+
+    #[test]
+    fn snippets_are_escaped_and_their_matches_marked() {
+        let issue = IssueMatch {
+            newsletter_issue_id: Uuid::new_v4(),
+            slug: "news-6f1c2d3e".into(),
+            title: "News".into(),
+            hidden_from_archive: false,
+            published_at: None,
+            snippet: "Hi <there>, our \u{2}garden\u{3} is \u{2}growing\u{3}".into(),
+        };
+        assert_eq!(
+            issue.snippet_html(),
+            "Hi &lt;there&gt;, our <mark>garden</mark> is <mark>growing</mark>"
+        );
+    }
+}
@@ -1,13 +1,23 @@
 mod admin;
+mod email_events;
+mod feeds;
 mod health_check;
 mod home;
+mod issue_engagement;
+mod issues;
 mod login;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
+pub use email_events::*;
+pub use feeds::*;
 pub use health_check::*;
 pub use home::*;
+pub use issue_engagement::*;
+pub use issues::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
@@ -1,6 +1,10 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::domain::{
+    domain_rejection, domain_suffixes, EmailPolicy, NewSubscriber, SubscriberAttributes,
+    SubscriberEmail, SubscriberName, DEFAULT_LIST_SLUG,
+};
 use crate::email_client::EmailClient;
 use crate::startup::ApplicationBaseUrl;
+use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
@@ -14,15 +18,27 @@ use uuid::Uuid;
 pub struct SubscriptionRequest {
     email: String,
     name: String,
+    #[serde(default)]
+    attributes: serde_json::Value,
+    // The slug of the mailing list to join, the default list if omitted.
+    #[serde(default)]
+    list: Option<String>,
+    // Skip the "did you mean" check when the subscriber insists on the
+    // address they typed.
+    #[serde(default)]
+    keep_as_typed: bool,
 }
 
-impl TryFrom<web::Json<SubscriptionRequest>> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(value: web::Json<SubscriptionRequest>) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name.clone())?;
-        let email = SubscriberEmail::parse(value.email.clone())?;
-        Ok(Self { email, name })
+impl SubscriptionRequest {
+    fn parse(&self, email_policy: &EmailPolicy) -> Result<NewSubscriber, String> {
+        let name = SubscriberName::parse(self.name.clone())?;
+        let email = SubscriberEmail::parse_with_policy(self.email.clone(), email_policy)?;
+        let attributes = SubscriberAttributes::parse(self.attributes.clone())?;
+        Ok(NewSubscriber {
+            email,
+            name,
+            attributes,
+        })
     }
 }
 
@@ -30,6 +46,8 @@ impl TryFrom<web::Json<SubscriptionRequest>> for NewSubscriber {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("Did you mean {0}?")]
+    DidYouMean(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -44,14 +62,29 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::DidYouMean(_) => StatusCode::UNPROCESSABLE_ENTITY,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::DidYouMean(suggestion) => {
+                HttpResponse::build(self.status_code()).json(serde_json::json!({
+                    "message": self.to_string(),
+                    "suggestion": suggestion,
+                }))
+            }
+            _ => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+        }
+    }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(request, pool, email_client, base_url),
+    skip(request, pool, email_client, email_policy, base_url),
     fields(
         subscriber_email = %request.email,
         subscriber_name = %request.name,
@@ -61,29 +94,74 @@ pub async fn subscribe(
     request: web::Json<SubscriptionRequest>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    email_policy: web::Data<EmailPolicy>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
     let new_subscriber = request
-        .try_into()
+        .parse(&email_policy)
         .map_err(SubscribeError::ValidationError)?;
+    if !request.keep_as_typed {
+        if let Some(suggestion) = new_subscriber.email.suggested_correction() {
+            return Err(SubscribeError::DidYouMean(suggestion));
+        }
+    }
+    if is_denied_domain(&pool, new_subscriber.email.domain())
+        .await
+        .context("Failed to check the subscriber's domain against the deny list.")?
+    {
+        return Err(SubscribeError::ValidationError(domain_rejection(
+            new_subscriber.email.domain(),
+        )));
+    }
+    let list_slug = request.list.as_deref().unwrap_or(DEFAULT_LIST_SLUG);
+    let list_id = get_list_id(&pool, list_slug)
+        .await
+        .context("Failed to look up the requested mailing list.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!(
+                "There is no mailing list called {}.",
+                list_slug
+            ))
+        })?;
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let subscriber_id = match get_subscriber_id(&mut transaction, &new_subscriber.email)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .context("Failed to look up an existing subscriber.")?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to insert new subscriber in the database.")?,
+    };
+    if !insert_membership(&mut transaction, subscriber_id, list_id)
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to add the subscriber to the mailing list.")?
+    {
+        return Err(SubscribeError::ValidationError(format!(
+            "{} is already subscribed to {}.",
+            new_subscriber.email.as_ref(),
+            list_slug
+        )));
+    }
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber.")?;
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
     send_confirmation_email(
         &email_client,
-        new_subscriber,
+        &new_subscriber.email,
         &base_url.0,
         &subscription_token,
     )
@@ -92,13 +170,72 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[tracing::instrument(name = "Check domain against the deny list", skip(pool))]
+pub async fn is_denied_domain(pool: &PgPool, domain: &str) -> Result<bool, sqlx::Error> {
+    let suffixes: Vec<String> = domain_suffixes(domain).map(str::to_string).collect();
+    let denied = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM denied_email_domains WHERE domain = ANY($1)) AS "denied!""#,
+        &suffixes[..],
+    )
+    .fetch_one(pool)
+    .await?
+    .denied;
+    Ok(denied)
+}
+
+#[tracing::instrument(name = "Get list_id from slug", skip(pool))]
+pub async fn get_list_id(pool: &PgPool, slug: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(r#"SELECT list_id FROM lists WHERE slug = $1"#, slug)
+        .fetch_optional(pool)
+        .await?;
+    Ok(result.map(|r| r.list_id))
+}
+
+#[tracing::instrument(name = "Get subscriber_id from email", skip(transaction, email))]
+pub async fn get_subscriber_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(normalised_email) = lower($1)"#,
+        email.normalised(),
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(result.map(|r| r.id))
+}
+
+/// Add a pending membership, or re-open one the subscriber left earlier.
+/// Returns `false` if the subscriber is already a (pending) member.
+#[tracing::instrument(name = "Add subscriber to a mailing list", skip(transaction))]
+pub async fn insert_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+            SET status = 'pending_confirmation', subscribed_at = EXCLUDED.subscribed_at
+            WHERE list_memberships.status = 'unsubscribed'
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(email_client, recipient, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+    recipient: &SubscriberEmail,
     base_url: &str,
     subscription_token: &str,
 ) -> Result<(), reqwest::Error> {
@@ -116,7 +253,7 @@ pub async fn send_confirmation_email(
         confirmation_link
     );
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .send_email(recipient, "Welcome!", &html_body, &plain_body)
         .await
 }
 
@@ -131,12 +268,16 @@ pub async fn insert_subscriber(
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id, email, normalised_email, name, attributes, subscribed_at, status
+        )
+        VALUES($1, $2, $3, $4, $5, $6, 'pending_confirmation')
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.normalised(),
         new_subscriber.name.as_ref(),
+        serde_json::Value::from(new_subscriber.attributes.clone()),
         Utc::now(),
     )
     .execute(transaction)
@@ -148,7 +289,7 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -163,17 +304,19 @@ fn generate_subscription_token() -> String {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     )
     .execute(transaction)
     .await
-    .map_err(|e| StoreTokenError(e))?;
+    .map_err(StoreTokenError)?;
     Ok(())
 }
 
@@ -38,37 +38,63 @@ pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, ConfirmationError> {
-    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
-        .await
-        .context("Failed to retrieve the subscriber id associated with the provided token.")?
-        .ok_or(ConfirmationError::UnknownToken)?;
-    confirm_subscriber(&pool, subscriber_id)
+    let (subscriber_id, list_id) =
+        get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+            .await
+            .context("Failed to retrieve the subscriber id associated with the provided token.")?
+            .ok_or(ConfirmationError::UnknownToken)?;
+    confirm_subscriber(&pool, subscriber_id, list_id)
         .await
         .context("Failed to update the subscriber status to `confirmed`.")?;
     Ok(HttpResponse::Ok().finish())
 }
 
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+/// Confirm the subscriber's address and their membership of the list the
+/// confirmation email was sent for.
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
     sqlx::query!(
         r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
         subscriber_id,
     )
-    .execute(pool)
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET
+            status = 'confirmed',
+            -- Following the link again does not confirm the membership anew.
+            confirmed_at = CASE WHEN status = 'confirmed' THEN confirmed_at ELSE now() END
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(())
+    transaction.commit().await
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"SELECT subscriber_id, list_id FROM subscription_tokens WHERE subscription_token = $1"#,
         subscription_token,
     )
     .fetch_optional(pool)
@@ -77,5 +103,5 @@ pub async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.list_id)))
 }
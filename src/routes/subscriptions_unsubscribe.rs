@@ -0,0 +1,172 @@
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Build the link a subscriber can follow to leave the list an issue was
+/// sent to. The token is an HMAC of the subscriber and list ids, so it
+/// cannot be forged for someone else and does not need to be stored.
+pub fn unsubscribe_url(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&list_id={}&token={}",
+        base_url,
+        subscriber_id,
+        list_id,
+        hex::encode(
+            unsubscribe_mac(hmac_secret, subscriber_id, list_id)
+                .finalize()
+                .into_bytes()
+        )
+    )
+}
+
+fn unsubscribe_mac(
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes()).unwrap();
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac.update(list_id.as_bytes());
+    mac
+}
+
+impl UnsubscribeParameters {
+    fn verify(&self, hmac_secret: &Secret<String>) -> Result<(), UnsubscribeError> {
+        let token = hex::decode(&self.token).map_err(|_| UnsubscribeError::InvalidToken)?;
+        unsubscribe_mac(hmac_secret, self.subscriber_id, self.list_id)
+            .verify_slice(&token)
+            .map_err(|_| UnsubscribeError::InvalidToken)
+    }
+}
+
+/// Ask the subscriber to confirm. Following the link must not unsubscribe
+/// anyone by itself: mail scanners and link previews fetch it too.
+#[tracing::instrument(
+    name = "Show the unsubscribe confirmation",
+    skip(parameters, hmac_secret),
+    fields(subscriber_id=%parameters.subscriber_id, list_id=%parameters.list_id)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    parameters.verify(&hmac_secret.0)?;
+    let action = format!(
+        "/subscriptions/unsubscribe?subscriber_id={}&list_id={}&token={}",
+        parameters.subscriber_id,
+        parameters.list_id,
+        urlencoding::encode(&parameters.token)
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving issues of this list?</p>
+    <form action="{}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            htmlescape::encode_minimal(&action)
+        )))
+}
+
+/// Unsubscribe from the confirmation page or, as the target of the
+/// `List-Unsubscribe` header, straight from the mail client (RFC 8058).
+/// Either way the parameters are in the query string.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id=%parameters.subscriber_id, list_id=%parameters.list_id)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    parameters.verify(&hmac_secret.0)?;
+    mark_as_unsubscribed(&pool, parameters.subscriber_id, parameters.list_id)
+        .await
+        .context("Failed to update the subscriber status to `unsubscribed`.")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed and will not receive further issues of this list.</p>
+</body>
+</html>"#,
+    ))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+pub async fn mark_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
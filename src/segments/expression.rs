@@ -0,0 +1,573 @@
+use crate::domain::{ListSlug, SubscriberTag};
+use chrono::NaiveDate;
+
+// Keep generated queries (and the parser's recursion) bounded.
+// Predicate names and values count as one term each.
+const MAX_TERMS: usize = 64;
+const MAX_NESTING: usize = 16;
+// A hundred years: longer periods are out of range for Postgres timestamps.
+const MAX_WITHIN_DAYS: i32 = 36500;
+
+/// A subset of subscribers, written as predicates combined with `and`, `or`,
+/// `not` and parentheses, e.g.
+/// `status:confirmed and tag:beta and subscribed_within:90d and not bounced`.
+///
+/// Supported predicates:
+/// - `status:confirmed`, `status:pending_confirmation`
+/// - `tag:<tag>`
+/// - `list:<slug>`, a confirmed member of the list
+/// - `subscribed_within:<n>d`
+/// - `subscribed_since:<yyyy-mm-dd>`, `subscribed_before:<yyyy-mm-dd>`
+/// - `bounced`, the email provider reported a hard bounce for the address
+/// - `attributes.<key>:<value>`, values with spaces must be double-quoted
+#[derive(Debug, Clone)]
+pub struct Segment {
+    source: String,
+    expression: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Predicate(Predicate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Status(String),
+    Tag(SubscriberTag),
+    List(String),
+    SubscribedWithinDays(i32),
+    SubscribedSince(NaiveDate),
+    SubscribedBefore(NaiveDate),
+    Bounced,
+    Attribute { key: String, value: String },
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SegmentError {
+    #[error("The segment is empty.")]
+    Empty,
+    #[error("The segment ends unexpectedly.")]
+    UnexpectedEnd,
+    #[error("Unexpected `{0}` in the segment.")]
+    UnexpectedToken(String),
+    #[error("The quoted value starting at character {0} is never closed.")]
+    UnclosedString(usize),
+    #[error("`{0}` is not a known segment predicate.")]
+    UnknownPredicate(String),
+    #[error("`{0}` is not a valid value for `{1}`.")]
+    InvalidValue(String, String),
+    #[error("`{0}` requires a value, e.g. `{0}:...`.")]
+    MissingValue(String),
+    #[error("The segment is too complex.")]
+    TooComplex,
+}
+
+/// A value bound to a placeholder of the SQL generated for a segment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Text(String),
+    Int(i32),
+    Date(NaiveDate),
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, SegmentError> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err(SegmentError::Empty);
+        }
+        let terms = tokens
+            .iter()
+            .filter(|t| {
+                matches!(t, Token::Word(w) if !is_keyword(w)) || matches!(t, Token::Quoted(_))
+            })
+            .count();
+        if terms > MAX_TERMS {
+            return Err(SegmentError::TooComplex);
+        }
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            depth: 0,
+        };
+        let expression = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(SegmentError::UnexpectedToken(token.to_string()));
+        }
+        Ok(Self {
+            source: s.trim().to_string(),
+            expression,
+        })
+    }
+
+    /// Compile the segment to a boolean SQL expression over `subscriptions`
+    /// aliased as `s`. Values are never inlined: they are returned in
+    /// placeholder order, starting at `$first_placeholder`.
+    pub fn to_sql(&self, first_placeholder: usize) -> (String, Vec<SqlValue>) {
+        let mut builder = SqlBuilder {
+            sql: String::new(),
+            values: Vec::new(),
+            first_placeholder,
+        };
+        builder.push_expression(&self.expression);
+        (builder.sql, builder.values)
+    }
+}
+
+impl AsRef<str> for Segment {
+    fn as_ref(&self) -> &str {
+        &self.source
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    Colon,
+    Word(String),
+    Quoted(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::Colon => write!(f, ":"),
+            Token::Word(w) => write!(f, "{}", w),
+            Token::Quoted(q) => write!(f, "\"{}\"", q),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+fn is_keyword(word: &str) -> bool {
+    ["and", "or", "not"]
+        .iter()
+        .any(|k| k.eq_ignore_ascii_case(word))
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, SegmentError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            ':' => tokens.push(Token::Colon),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => return Err(SegmentError::UnclosedString(i)),
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => return Err(SegmentError::UnclosedString(i)),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => return Err(SegmentError::UnexpectedToken(c.to_string())),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&'a Token, SegmentError> {
+        let token = self.peek().ok_or(SegmentError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, SegmentError> {
+        let mut expression = self.parse_and()?;
+        while self.next_is_keyword("or") {
+            self.position += 1;
+            let rhs = self.parse_and()?;
+            expression = Expression::Or(Box::new(expression), Box::new(rhs));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, SegmentError> {
+        let mut expression = self.parse_not()?;
+        while self.next_is_keyword("and") {
+            self.position += 1;
+            let rhs = self.parse_not()?;
+            expression = Expression::And(Box::new(expression), Box::new(rhs));
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, SegmentError> {
+        if self.next_is_keyword("not") {
+            self.position += 1;
+            self.nested(|p| Ok(Expression::Not(Box::new(p.parse_not()?))))
+        } else {
+            self.parse_atom()
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Expression, SegmentError> {
+        match self.next()? {
+            Token::LeftParen => {
+                let expression = self.nested(Self::parse_or)?;
+                match self.next()? {
+                    Token::RightParen => Ok(expression),
+                    token => Err(SegmentError::UnexpectedToken(token.to_string())),
+                }
+            }
+            Token::Word(name) if !is_keyword(name) => {
+                let value = if self.peek() == Some(&Token::Colon) {
+                    self.position += 1;
+                    match self.next()? {
+                        Token::Word(v) | Token::Quoted(v) => Some(v.as_str()),
+                        token => return Err(SegmentError::UnexpectedToken(token.to_string())),
+                    }
+                } else {
+                    None
+                };
+                Ok(Expression::Predicate(Predicate::parse(name, value)?))
+            }
+            token => Err(SegmentError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<Expression, SegmentError>,
+    ) -> Result<Expression, SegmentError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(SegmentError::TooComplex);
+        }
+        let expression = f(self)?;
+        self.depth -= 1;
+        Ok(expression)
+    }
+}
+
+impl Predicate {
+    fn parse(name: &str, value: Option<&str>) -> Result<Predicate, SegmentError> {
+        let invalid = |v: &str| SegmentError::InvalidValue(v.to_string(), name.to_string());
+        if name == "bounced" {
+            return match value {
+                None => Ok(Predicate::Bounced),
+                Some(v) => Err(invalid(v)),
+            };
+        }
+        let known = matches!(
+            name,
+            "status"
+                | "tag"
+                | "list"
+                | "subscribed_within"
+                | "subscribed_since"
+                | "subscribed_before"
+        ) || name.starts_with("attributes.");
+        if !known {
+            return Err(SegmentError::UnknownPredicate(name.to_string()));
+        }
+        let value = value.ok_or_else(|| SegmentError::MissingValue(name.to_string()))?;
+        let predicate = match name {
+            "status" => match value {
+                "confirmed" | "pending_confirmation" => Predicate::Status(value.to_string()),
+                v => return Err(invalid(v)),
+            },
+            "tag" => Predicate::Tag(SubscriberTag::parse(value).map_err(|_| invalid(value))?),
+            "list" => Predicate::List(
+                ListSlug::parse(value.to_string())
+                    .map_err(|_| invalid(value))?
+                    .as_ref()
+                    .to_string(),
+            ),
+            "subscribed_within" => Predicate::SubscribedWithinDays(
+                value
+                    .strip_suffix('d')
+                    .and_then(|days| days.parse().ok())
+                    .filter(|days| (1..=MAX_WITHIN_DAYS).contains(days))
+                    .ok_or_else(|| invalid(value))?,
+            ),
+            "subscribed_since" | "subscribed_before" => {
+                let date =
+                    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid(value))?;
+                if name == "subscribed_since" {
+                    Predicate::SubscribedSince(date)
+                } else {
+                    Predicate::SubscribedBefore(date)
+                }
+            }
+            attribute => {
+                let key = attribute.trim_start_matches("attributes.");
+                if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(SegmentError::UnknownPredicate(name.to_string()));
+                }
+                Predicate::Attribute {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                }
+            }
+        };
+        Ok(predicate)
+    }
+}
+
+struct SqlBuilder {
+    sql: String,
+    values: Vec<SqlValue>,
+    first_placeholder: usize,
+}
+
+impl SqlBuilder {
+    fn placeholder(&mut self, value: SqlValue) -> String {
+        self.values.push(value);
+        format!("${}", self.first_placeholder + self.values.len() - 1)
+    }
+
+    fn push_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::And(lhs, rhs) => self.push_binary(lhs, "AND", rhs),
+            Expression::Or(lhs, rhs) => self.push_binary(lhs, "OR", rhs),
+            Expression::Not(e) => {
+                self.sql.push_str("NOT (");
+                self.push_expression(e);
+                self.sql.push(')');
+            }
+            Expression::Predicate(p) => self.push_predicate(p),
+        }
+    }
+
+    fn push_binary(&mut self, lhs: &Expression, operator: &str, rhs: &Expression) {
+        self.sql.push('(');
+        self.push_expression(lhs);
+        self.sql.push_str(&format!(" {} ", operator));
+        self.push_expression(rhs);
+        self.sql.push(')');
+    }
+
+    fn push_predicate(&mut self, predicate: &Predicate) {
+        let sql = match predicate {
+            Predicate::Status(status) => {
+                format!("s.status = {}", self.placeholder(text(status)))
+            }
+            Predicate::Tag(tag) => format!(
+                "EXISTS (SELECT 1 FROM subscriber_tags t \
+                WHERE t.subscriber_id = s.id AND t.tag = {})",
+                self.placeholder(text(tag.as_ref()))
+            ),
+            Predicate::List(slug) => format!(
+                "EXISTS (SELECT 1 FROM list_memberships lm \
+                JOIN lists l ON l.list_id = lm.list_id \
+                WHERE lm.subscriber_id = s.id AND lm.status = 'confirmed' AND l.slug = {})",
+                self.placeholder(text(slug))
+            ),
+            Predicate::SubscribedWithinDays(days) => format!(
+                "s.subscribed_at >= now() - make_interval(days => {})",
+                self.placeholder(SqlValue::Int(*days))
+            ),
+            Predicate::SubscribedSince(date) => format!(
+                "s.subscribed_at >= {}",
+                self.placeholder(SqlValue::Date(*date))
+            ),
+            Predicate::SubscribedBefore(date) => format!(
+                "s.subscribed_at < {}",
+                self.placeholder(SqlValue::Date(*date))
+            ),
+            Predicate::Bounced => "s.bounced_at IS NOT NULL".to_string(),
+            Predicate::Attribute { key, value } => format!(
+                "s.attributes ->> {} = {}",
+                self.placeholder(text(key)),
+                self.placeholder(text(value))
+            ),
+        };
+        self.sql.push_str(&sql);
+    }
+}
+
+fn text(s: &str) -> SqlValue {
+    SqlValue::Text(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Segment, SegmentError, SqlValue};
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok};
+
+    fn sql(s: &str) -> (String, Vec<SqlValue>) {
+        Segment::parse(s).unwrap().to_sql(1)
+    }
+
+    #[test]
+    fn a_single_predicate_is_compiled_with_a_placeholder() {
+        assert_eq!(
+            sql("status:confirmed"),
+            (
+                "s.status = $1".to_string(),
+                vec![SqlValue::Text("confirmed".into())]
+            )
+        );
+    }
+
+    #[test]
+    fn placeholders_start_at_the_requested_offset() {
+        let (sql, values) = Segment::parse("tag:beta and attributes.plan:pro")
+            .unwrap()
+            .to_sql(3);
+        assert!(sql.contains("t.tag = $3"));
+        assert!(sql.contains("s.attributes ->> $4 = $5"));
+        assert_eq!(values.len(), 3);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            sql("bounced or status:confirmed and not bounced").0,
+            "(s.bounced_at IS NOT NULL OR (s.status = $1 AND NOT (s.bounced_at IS NOT NULL)))"
+        );
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(
+            sql("(bounced or status:confirmed) and not bounced").0,
+            "((s.bounced_at IS NOT NULL OR s.status = $1) AND NOT (s.bounced_at IS NOT NULL))"
+        );
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        assert_ok!(Segment::parse("tag:beta AND NOT bounced"));
+    }
+
+    #[test]
+    fn the_example_segment_is_supported() {
+        let (_, values) =
+            sql("status:confirmed and tag:beta and subscribed_within:90d and not bounced");
+        assert_eq!(
+            values,
+            vec![
+                SqlValue::Text("confirmed".into()),
+                SqlValue::Text("beta".into()),
+                SqlValue::Int(90),
+            ]
+        );
+    }
+
+    #[test]
+    fn dates_and_quoted_values_are_parsed() {
+        let (_, values) =
+            sql(r#"subscribed_since:2022-01-31 and attributes.company:"Acme \"Co\"""#);
+        assert_eq!(
+            values,
+            vec![
+                SqlValue::Date(NaiveDate::from_ymd_opt(2022, 1, 31).unwrap()),
+                SqlValue::Text("company".into()),
+                SqlValue::Text(r#"Acme "Co""#.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn values_are_never_inlined_in_the_sql() {
+        let (sql, _) = sql(r#"tag:x or attributes.a:"'; DROP TABLE subscriptions; --""#);
+        assert!(!sql.contains("DROP"));
+        assert!(!sql.contains('\''));
+    }
+
+    #[test]
+    fn unknown_predicates_are_rejected() {
+        assert_eq!(
+            Segment::parse("opened:7d").unwrap_err(),
+            SegmentError::UnknownPredicate("opened".into())
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for segment in [
+            "status:deleted",
+            "subscribed_within:90",
+            "subscribed_within:0d",
+            "subscribed_since:yesterday",
+            "tag:\"early access\"",
+            "bounced:yes",
+        ] {
+            assert_err!(Segment::parse(segment), "{}", segment);
+        }
+    }
+
+    #[test]
+    fn periods_are_capped_at_a_hundred_years() {
+        assert_ok!(Segment::parse("subscribed_within:36500d"));
+        for segment in ["subscribed_within:36501d", "subscribed_within:2000000000d"] {
+            assert_err!(Segment::parse(segment), "{}", segment);
+        }
+    }
+
+    #[test]
+    fn malformed_segments_are_rejected() {
+        for segment in [
+            "",
+            "tag:beta and",
+            "(tag:beta",
+            "tag:beta)",
+            "tag:beta tag:vip",
+            "and tag:beta",
+            "tag:",
+            "tag",
+            "tag:\"beta",
+            "tag:beta; DROP",
+        ] {
+            assert_err!(Segment::parse(segment), "{}", segment);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let segment = format!("{}bounced{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(
+            Segment::parse(&segment).unwrap_err(),
+            SegmentError::TooComplex
+        );
+        let segment = vec!["bounced"; 100].join(" or ");
+        assert_eq!(
+            Segment::parse(&segment).unwrap_err(),
+            SegmentError::TooComplex
+        );
+    }
+}
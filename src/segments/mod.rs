@@ -0,0 +1,8 @@
+mod expression;
+mod persistence;
+
+pub use expression::{Segment, SegmentError, SqlValue};
+pub use persistence::{
+    bind_segment_values, count_subscribers, get_saved_segment, get_saved_segments, save_segment,
+    SavedSegment,
+};
@@ -0,0 +1,80 @@
+use super::{Segment, SqlValue};
+use sqlx::postgres::PgArguments;
+use sqlx::query::Query;
+use sqlx::{PgPool, Postgres, Row};
+use uuid::Uuid;
+
+pub struct SavedSegment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub expression: String,
+}
+
+/// Bind the values produced by `Segment::to_sql`, in placeholder order.
+pub fn bind_segment_values<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    values: &'q [SqlValue],
+) -> Query<'q, Postgres, PgArguments> {
+    for value in values {
+        query = match value {
+            SqlValue::Text(s) => query.bind(s.as_str()),
+            SqlValue::Int(i) => query.bind(*i),
+            SqlValue::Date(d) => query.bind(*d),
+        };
+    }
+    query
+}
+
+#[tracing::instrument(name = "Count subscribers in a segment", skip(pool, segment), fields(segment=%segment.as_ref()))]
+pub async fn count_subscribers(pool: &PgPool, segment: &Segment) -> Result<i64, sqlx::Error> {
+    let (condition, values) = segment.to_sql(1);
+    let sql = format!("SELECT count(*) FROM subscriptions s WHERE {}", condition);
+    let row = bind_segment_values(sqlx::query(&sql), &values)
+        .fetch_one(pool)
+        .await?;
+    row.try_get(0)
+}
+
+/// Save `segment` under `name`, replacing the expression of an existing
+/// segment with the same name.
+#[tracing::instrument(name = "Save a segment", skip(pool, segment))]
+pub async fn save_segment(pool: &PgPool, name: &str, segment: &Segment) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, expression, created_at, updated_at)
+        VALUES ($1, $2, $3, now(), now())
+        ON CONFLICT (name) DO UPDATE
+            SET expression = EXCLUDED.expression, updated_at = now()
+        "#,
+        Uuid::new_v4(),
+        name,
+        segment.as_ref(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get saved segments", skip(pool))]
+pub async fn get_saved_segments(pool: &PgPool) -> Result<Vec<SavedSegment>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedSegment,
+        r#"SELECT segment_id, name, expression FROM segments ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get a saved segment", skip(pool))]
+pub async fn get_saved_segment(
+    pool: &PgPool,
+    name: &str,
+) -> Result<Option<SavedSegment>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedSegment,
+        r#"SELECT segment_id, name, expression FROM segments WHERE name = $1"#,
+        name
+    )
+    .fetch_optional(pool)
+    .await
+}
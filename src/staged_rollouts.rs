@@ -0,0 +1,380 @@
+use crate::configuration::RolloutConfiguration;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::ExecutionOutcome;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+/// Start sending an issue in stages if that was picked for it, once its
+/// deliveries are queued.
+#[tracing::instrument(skip(transaction, rollout))]
+pub async fn start_issue_rollout(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    rollout: &RolloutConfiguration,
+) -> Result<(), anyhow::Error> {
+    let issue = sqlx::query!(
+        "SELECT staged_rollout FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve the rollout of the issue")?;
+    if issue.staged_rollout {
+        start_staged_rollout(transaction, newsletter_issue_id, rollout)
+            .await
+            .context("Failed to start the staged rollout")?;
+    }
+    Ok(())
+}
+
+/// Store the stages of an issue whose deliveries are queued, and spread the
+/// queue across them at random. Only the first stage is sent to at first.
+async fn start_staged_rollout(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    rollout: &RolloutConfiguration,
+) -> Result<(), sqlx::Error> {
+    let stages: Vec<i16> = rollout.stages.iter().copied().map(i16::from).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_rollouts (
+            newsletter_issue_id, stages, max_failure_rate, settle_minutes
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        newsletter_issue_id,
+        &stages[..],
+        rollout.max_failure_rate,
+        i32::from(rollout.settle_minutes),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Each stage gets at least one subscriber, as long as there are enough.
+    sqlx::query!(
+        r#"
+        WITH shuffled AS (
+            SELECT
+                subscriber_email,
+                row_number() OVER (ORDER BY random()) - 1 AS position,
+                count(*) OVER () AS total
+            FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+        )
+        UPDATE issue_delivery_queue q
+        SET stage = (
+            SELECT min(s.stage - 1)
+            FROM unnest($2::smallint[]) WITH ORDINALITY AS s(percentage, stage)
+            WHERE shuffled.position < greatest(s.stage, ceil(shuffled.total * s.percentage / 100.0))
+        )
+        FROM shuffled
+        WHERE q.newsletter_issue_id = $1 AND q.subscriber_email = shuffled.subscriber_email
+        "#,
+        newsletter_issue_id,
+        &stages[..],
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Check the stage of one issue whose emails have all been sent, and settled
+/// long enough for bounces to be reported, moving on to the next stage or,
+/// if too many failed or bounced, pausing the issue and telling the admins.
+/// An issue resumed after being paused moves on without a check.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_advance_rollout(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let rollout = sqlx::query!(
+        r#"
+        SELECT
+            r.newsletter_issue_id,
+            r.current_stage,
+            r.max_failure_rate,
+            r.halted_at IS NOT NULL AS "halted!",
+            i.title
+        FROM issue_rollouts r
+        JOIN newsletter_issues i ON i.newsletter_issue_id = r.newsletter_issue_id
+        WHERE i.status = 'published' AND i.paused_at IS NULL
+        AND EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = r.newsletter_issue_id AND q.stage > r.current_stage
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = r.newsletter_issue_id AND q.stage <= r.current_stage
+        )
+        AND (
+            r.halted_at IS NOT NULL
+            OR NOT EXISTS (
+                SELECT 1 FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = r.newsletter_issue_id
+                AND d.stage = r.current_stage
+                AND d.attempted_at > now() - make_interval(mins => r.settle_minutes)
+            )
+        )
+        FOR UPDATE OF r SKIP LOCKED
+        FOR UPDATE OF i SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let rollout = match rollout {
+        Some(rollout) => rollout,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("newsletter_issue_id", display(rollout.newsletter_issue_id));
+
+    let stage = sqlx::query!(
+        r#"
+        SELECT
+            count(*) AS "attempted!",
+            count(*) FILTER (WHERE outcome = 'failed') AS "failed!",
+            count(*) FILTER (WHERE bounced_at IS NOT NULL) AS "bounced!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1 AND stage = $2
+        "#,
+        rollout.newsletter_issue_id,
+        rollout.current_stage,
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let failure_rate = if stage.attempted == 0 {
+        0.0
+    } else {
+        (stage.failed + stage.bounced) as f64 * 100.0 / stage.attempted as f64
+    };
+    if rollout.halted || failure_rate <= rollout.max_failure_rate {
+        sqlx::query!(
+            r#"
+            UPDATE issue_rollouts
+            SET current_stage = current_stage + 1, halted_at = NULL, halted_failure_rate = NULL
+            WHERE newsletter_issue_id = $1
+            "#,
+            rollout.newsletter_issue_id,
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_rollouts
+        SET halted_at = now(), halted_failure_rate = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+        rollout.newsletter_issue_id,
+        failure_rate,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET paused_at = now() WHERE newsletter_issue_id = $1",
+        rollout.newsletter_issue_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::warn!(
+        failure_rate,
+        stage = rollout.current_stage,
+        "Paused an issue sent in stages: too many emails of the stage failed or bounced",
+    );
+    let notification = HaltNotification {
+        title: &rollout.title,
+        url: &format!("{}/admin/issues/{}", base_url, rollout.newsletter_issue_id),
+        stage: rollout.current_stage,
+        failed: stage.failed,
+        bounced: stage.bounced,
+        attempted: stage.attempted,
+        failure_rate,
+        max_failure_rate: rollout.max_failure_rate,
+    };
+    // The issue stays paused whether or not the admins could be told.
+    notify_admins(pool, email_client, &notification).await;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct HaltNotification<'a> {
+    title: &'a str,
+    url: &'a str,
+    stage: i16,
+    failed: i64,
+    bounced: i64,
+    attempted: i64,
+    failure_rate: f64,
+    max_failure_rate: f64,
+}
+
+impl HaltNotification<'_> {
+    fn subject(&self) -> String {
+        format!("Sending \"{}\" was paused", self.title)
+    }
+
+    fn text(&self) -> String {
+        format!(
+            "{} of the {} emails of stage {} of \"{}\" failed and {} bounced \
+            ({:.1}%, over the limit of {}%).\n\
+            No more emails will go out until the issue is resumed: {}",
+            self.failed,
+            self.attempted,
+            self.stage + 1,
+            self.title,
+            self.bounced,
+            self.failure_rate,
+            self.max_failure_rate,
+            self.url,
+        )
+    }
+
+    fn html(&self) -> String {
+        format!(
+            r#"<p>{} of the {} emails of stage {} of "{}" failed and {} bounced ({:.1}%, over the limit of {}%).</p><p>No more emails will go out until the issue is <a href="{}">resumed</a>.</p>"#,
+            self.failed,
+            self.attempted,
+            self.stage + 1,
+            htmlescape::encode_minimal(self.title),
+            self.bounced,
+            self.failure_rate,
+            self.max_failure_rate,
+            htmlescape::encode_minimal(self.url),
+        )
+    }
+}
+
+/// Email every admin with an address. Failures are logged, not retried.
+#[tracing::instrument(skip_all)]
+async fn notify_admins(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    notification: &HaltNotification<'_>,
+) {
+    let admins = match sqlx::query!("SELECT email AS \"email!\" FROM users WHERE email IS NOT NULL")
+        .fetch_all(pool)
+        .await
+    {
+        Ok(admins) => admins,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to retrieve the email addresses of the admins",
+            );
+            return;
+        }
+    };
+    for admin in admins {
+        let email = match SubscriberEmail::parse(admin.email) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::warn!(error.message = %e, "Skipping an admin with an invalid email");
+                continue;
+            }
+        };
+        if let Err(e) = email_client
+            .send_email(
+                &email,
+                &notification.subject(),
+                &notification.html(),
+                &notification.text(),
+            )
+            .await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to tell an admin that an issue was paused",
+            );
+        }
+    }
+}
+
+/// The stages of a send, once the issue is published, with how the emails
+/// of each went.
+pub struct RolloutProgress {
+    pub current_stage: i16,
+    pub halted_failure_rate: Option<f64>,
+    pub max_failure_rate: f64,
+    pub settle_minutes: i32,
+    pub stages: Vec<StageProgress>,
+}
+
+pub struct StageProgress {
+    pub percentage: i16,
+    pub queued: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    // Delivered, then reported as a hard bounce.
+    pub bounced: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_rollout_progress(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<RolloutProgress>, anyhow::Error> {
+    let rollout = sqlx::query!(
+        r#"
+        SELECT current_stage, halted_failure_rate, max_failure_rate, settle_minutes
+        FROM issue_rollouts
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the stages of the issue")?;
+    let rollout = match rollout {
+        Some(rollout) => rollout,
+        None => return Ok(None),
+    };
+    let stages = sqlx::query_as!(
+        StageProgress,
+        r#"
+        SELECT
+            s.percentage AS "percentage!",
+            (
+                SELECT count(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = r.newsletter_issue_id AND q.stage = s.stage - 1
+            ) AS "queued!",
+            (
+                SELECT count(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = r.newsletter_issue_id
+                AND d.stage = s.stage - 1 AND d.outcome = 'delivered'
+            ) AS "delivered!",
+            (
+                SELECT count(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = r.newsletter_issue_id
+                AND d.stage = s.stage - 1 AND d.outcome = 'failed'
+            ) AS "failed!",
+            (
+                SELECT count(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = r.newsletter_issue_id
+                AND d.stage = s.stage - 1 AND d.bounced_at IS NOT NULL
+            ) AS "bounced!"
+        FROM issue_rollouts r, unnest(r.stages) WITH ORDINALITY AS s(percentage, stage)
+        WHERE r.newsletter_issue_id = $1
+        ORDER BY s.stage
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the progress of the stages")?;
+    Ok(Some(RolloutProgress {
+        current_stage: rollout.current_stage,
+        halted_failure_rate: rollout.halted_failure_rate,
+        max_failure_rate: rollout.max_failure_rate,
+        settle_minutes: rollout.settle_minutes,
+        stages,
+    }))
+}
@@ -1,9 +1,21 @@
 use crate::authentication::reject_anonymous_users;
-use crate::configuration::{Configuration, DatabaseConfiguration};
+use crate::configuration::{Configuration, DatabaseConfiguration, RolloutConfiguration};
+use crate::domain::EmailPolicy;
 use crate::email_client::EmailClient;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    add_denied_domain, admin_dashboard, archive_page, atom_feed, autosave_draft, cancel_issue,
+    change_password, change_password_form, confirm, create_draft, create_layout, create_list,
+    delete_draft, delete_layout, drafts_page, edit_draft_form, edit_layout_form,
+    email_domains_form, export_subscribers, export_subscribers_form, health_check, hide_issue,
+    home, import_subscribers, import_subscribers_form, issue_details, issue_page, issues_index,
+    issues_page, issues_progress, layouts_page, lint_newsletter, lists_form, log_out, login,
+    login_form, manage_subscriber, pause_issue, preview_newsletter, publish_draft,
+    publish_newsletter, publish_newsletter_form, record_email_events, remove_denied_domain,
+    resume_issue, rss_feed, save_draft, save_layout, save_segment, schedule_draft, segments_form,
+    send_test_newsletter, send_to_new_subscribers, show_issue, subscribe,
+    subscriber_attributes_form, subscriber_details, subscribers_page, track_click, track_open,
+    unschedule_issue, unsubscribe, unsubscribe_form, update_subscriber_attributes,
+    update_subscriber_tags,
 };
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
@@ -28,7 +40,15 @@ pub struct Application {
 impl Application {
     pub async fn build(configuration: Configuration) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
+        let email_policy = configuration
+            .email_policy
+            .policy(configuration.email_client.supports_smtputf8);
+        let email_events_token = configuration.email_client.events_token.clone();
         let email_client = configuration.email_client.client();
+        configuration
+            .rollout
+            .validate()
+            .map_err(anyhow::Error::msg)?;
 
         let address = format!(
             "{}:{}",
@@ -40,9 +60,12 @@ impl Application {
             listener,
             connection_pool,
             email_client,
+            email_policy,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            email_events_token,
             configuration.redis_uri,
+            configuration.rollout,
         )
         .await?;
 
@@ -66,18 +89,24 @@ pub fn get_connection_pool(configuration: &DatabaseConfiguration) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
+    email_policy: EmailPolicy,
     base_url: String,
     hmac_secret: Secret<String>,
+    email_events_token: Secret<String>,
     redis_uri: Secret<String>,
+    rollout: RolloutConfiguration,
 ) -> Result<Server, anyhow::Error> {
     // Wrap the connection in a smart pointer
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
+    let email_policy = web::Data::new(email_policy);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let rollout = web::Data::new(rollout);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
@@ -99,18 +128,123 @@ pub async fn run(
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out))
                     .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/email_events", web::post().to(record_email_events))
+                    .route("/newsletters/preview", web::post().to(preview_newsletter))
+                    .route("/newsletters/lint", web::post().to(lint_newsletter))
+                    .route("/newsletters/test", web::post().to(send_test_newsletter))
+                    .route("/newsletters/drafts", web::get().to(drafts_page))
+                    .route("/newsletters/drafts", web::post().to(create_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route("/newsletters/drafts/{draft_id}", web::post().to(save_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}/autosave",
+                        web::post().to(autosave_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/publish",
+                        web::post().to(publish_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/delete",
+                        web::post().to(delete_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/schedule",
+                        web::post().to(schedule_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/unschedule",
+                        web::post().to(unschedule_issue),
+                    )
+                    .route("/layouts", web::get().to(layouts_page))
+                    .route("/layouts", web::post().to(create_layout))
+                    .route("/layouts/{layout_id}", web::get().to(edit_layout_form))
+                    .route("/layouts/{layout_id}", web::post().to(save_layout))
+                    .route("/layouts/{layout_id}/delete", web::post().to(delete_layout))
+                    .route("/issues", web::get().to(issues_page))
+                    .route("/issues/progress", web::get().to(issues_progress))
+                    .route("/issues/{issue_id}", web::get().to(issue_details))
+                    .route("/issues/{issue_id}/pause", web::post().to(pause_issue))
+                    .route("/issues/{issue_id}/resume", web::post().to(resume_issue))
+                    .route("/issues/{issue_id}/cancel", web::post().to(cancel_issue))
+                    .route(
+                        "/issues/{issue_id}/send_to_new_subscribers",
+                        web::post().to(send_to_new_subscribers),
+                    )
+                    .route("/archive", web::get().to(archive_page))
+                    .route("/archive/{issue_id}/hide", web::post().to(hide_issue))
+                    .route("/archive/{issue_id}/show", web::post().to(show_issue))
+                    .route("/lists", web::get().to(lists_form))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/email_domains", web::get().to(email_domains_form))
+                    .route("/email_domains", web::post().to(add_denied_domain))
+                    .route(
+                        "/email_domains/remove",
+                        web::post().to(remove_denied_domain),
+                    )
+                    .route(
+                        "/subscribers/attributes",
+                        web::get().to(subscriber_attributes_form),
+                    )
+                    .route(
+                        "/subscribers/attributes",
+                        web::post().to(update_subscriber_attributes),
+                    )
+                    .route(
+                        "/subscribers/export",
+                        web::get().to(export_subscribers_form),
+                    )
+                    .route(
+                        "/subscribers/export/download",
+                        web::get().to(export_subscribers),
+                    )
+                    .route("/subscribers/tags", web::post().to(update_subscriber_tags))
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers", web::get().to(subscribers_page))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/{action}",
+                        web::post().to(manage_subscriber),
+                    )
+                    .route("/segments", web::get().to(segments_form))
+                    .route("/segments", web::post().to(save_segment)),
             )
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
+            .route("/issues", web::get().to(issues_index))
+            .route("/feed.xml", web::get().to(rss_feed))
+            .route("/atom.xml", web::get().to(atom_feed))
+            .route("/issues/{slug}", web::get().to(issue_page))
+            .route("/issues/{issue_id}/opened", web::get().to(track_open))
+            .route("/issues/{issue_id}/clicked", web::get().to(track_click))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/email_events", web::post().to(record_email_events))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(email_policy.clone())
             .app_data(base_url.clone())
+            .app_data(rollout.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(web::Data::new(EmailEventsToken(email_events_token.clone())))
     })
     .listen(listener)?
     .run();
@@ -120,3 +254,5 @@ pub async fn run(
 
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
+
+pub struct EmailEventsToken(pub Secret<String>);
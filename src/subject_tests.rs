@@ -0,0 +1,447 @@
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::newsletter_content::Template;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+pub const MAX_SUBJECT_VARIANTS: usize = 4;
+pub const DEFAULT_VARIANT_SHARE: u8 = 10;
+pub const DEFAULT_WAIT_HOURS: u16 = 4;
+const MAX_VARIANT_SHARE: u8 = 25;
+const MAX_WAIT_HOURS: u16 = 7 * 24;
+
+/// What makes a subject win.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestMetric {
+    Opens,
+    Clicks,
+}
+
+impl TestMetric {
+    pub fn parse(s: &str) -> Result<TestMetric, String> {
+        match s {
+            "opens" => Ok(TestMetric::Opens),
+            "clicks" => Ok(TestMetric::Clicks),
+            _ => Err(format!(
+                "{} is not a way to pick the winner of a subject test.",
+                htmlescape::encode_minimal(s)
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestMetric::Opens => "opens",
+            TestMetric::Clicks => "clicks",
+        }
+    }
+}
+
+/// The subject test fields of the newsletter and draft forms, stored with the
+/// issue as they were entered.
+pub struct SubjectTestFields<'a> {
+    pub alternatives: [&'a str; 3],
+    pub variant_share: Option<u8>,
+    pub wait_hours: Option<u16>,
+    pub metric: &'a str,
+}
+
+impl SubjectTestFields<'_> {
+    pub fn test_subjects(&self) -> Vec<String> {
+        self.alternatives.map(String::from).to_vec()
+    }
+
+    pub fn test_share(&self) -> Option<i16> {
+        self.variant_share.map(i16::from)
+    }
+
+    pub fn test_wait_hours(&self) -> Option<i16> {
+        // Out of range values are rejected when the test is started.
+        self.wait_hours
+            .map(|hours| i16::try_from(hours).unwrap_or(i16::MAX))
+    }
+
+    pub fn test_metric(&self) -> Option<&str> {
+        Some(self.metric).filter(|metric| !metric.is_empty())
+    }
+}
+
+/// Subjects to try on a share of the audience of an issue before sending the
+/// best one to the rest.
+#[derive(Debug)]
+pub struct SubjectTest {
+    // The title of the issue comes first.
+    subjects: Vec<String>,
+    // The percentage of the audience each subject is sent to.
+    variant_share: u8,
+    wait_hours: u16,
+    metric: TestMetric,
+}
+
+impl SubjectTest {
+    /// The test of `title` against the non-empty `alternatives`, if any. The
+    /// error is an HTML message for the admin.
+    pub fn parse(
+        title: &str,
+        alternatives: &[&str],
+        variant_share: u8,
+        wait_hours: u16,
+        metric: &str,
+    ) -> Result<Option<SubjectTest>, String> {
+        let alternatives: Vec<_> = alternatives
+            .iter()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if alternatives.is_empty() {
+            return Ok(None);
+        }
+        if alternatives.len() >= MAX_SUBJECT_VARIANTS {
+            return Err(format!(
+                "At most {} subjects can be tested at once.",
+                MAX_SUBJECT_VARIANTS
+            ));
+        }
+        if !(1..=MAX_VARIANT_SHARE).contains(&variant_share) {
+            return Err(format!(
+                "Each subject can be tested on 1% to {}% of the audience.",
+                MAX_VARIANT_SHARE
+            ));
+        }
+        // The winner is sent to the rest of the audience.
+        let n_subjects = alternatives.len() + 1;
+        if n_subjects * usize::from(variant_share) >= 100 {
+            return Err(format!(
+                "{} subjects tested on {}% of the audience each leave nobody for the winner.",
+                n_subjects, variant_share
+            ));
+        }
+        if !(1..=MAX_WAIT_HOURS).contains(&wait_hours) {
+            return Err(format!(
+                "The winner of a subject test can be picked after 1 to {} hours.",
+                MAX_WAIT_HOURS
+            ));
+        }
+        let metric = TestMetric::parse(metric)?;
+        for subject in &alternatives {
+            if let Err(e) = Template::parse(subject) {
+                return Err(format!(
+                    "The subject {} could not be published: {}",
+                    htmlescape::encode_minimal(subject),
+                    htmlescape::encode_minimal(&e.to_string())
+                ));
+            }
+        }
+        Ok(Some(SubjectTest {
+            subjects: std::iter::once(title)
+                .chain(alternatives)
+                .map(String::from)
+                .collect(),
+            variant_share,
+            wait_hours,
+            metric,
+        }))
+    }
+
+    /// The test set up with an issue, as stored in `newsletter_issues`, the
+    /// form defaults standing in for missing settings. The error is an HTML
+    /// message for the admin.
+    pub fn from_issue(
+        title: &str,
+        alternatives: &[String],
+        variant_share: Option<i16>,
+        wait_hours: Option<i16>,
+        metric: Option<&str>,
+    ) -> Result<Option<SubjectTest>, String> {
+        let alternatives: Vec<_> = alternatives.iter().map(String::as_str).collect();
+        // Out of range values are rejected by `parse`.
+        Self::parse(
+            title,
+            &alternatives,
+            variant_share.map_or(DEFAULT_VARIANT_SHARE, |share| {
+                u8::try_from(share).unwrap_or(0)
+            }),
+            wait_hours.map_or(DEFAULT_WAIT_HOURS, |hours| {
+                u16::try_from(hours).unwrap_or(0)
+            }),
+            metric.unwrap_or_default(),
+        )
+    }
+}
+
+/// Start the subject test set up with an issue, if any, once its deliveries
+/// are queued.
+#[tracing::instrument(skip(transaction))]
+pub async fn start_issue_subject_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, test_subjects, test_share, test_wait_hours, test_metric
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subject test of the issue")?;
+    let test = SubjectTest::from_issue(
+        &issue.title,
+        &issue.test_subjects,
+        issue.test_share,
+        issue.test_wait_hours,
+        issue.test_metric.as_deref(),
+    )
+    .map_err(|e| anyhow::anyhow!(e))
+    .context("The subject test of the issue is invalid")?;
+    if let Some(test) = test {
+        start_subject_test(transaction, newsletter_issue_id, &test)
+            .await
+            .context("Failed to start the subject test")?;
+    }
+    Ok(())
+}
+
+/// Store the test of the subject of an issue whose deliveries are queued, and
+/// assign its variants to a random share of the queue. Everyone else waits
+/// for the winner.
+async fn start_subject_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    test: &SubjectTest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_tests (newsletter_issue_id, variant_share, metric, decide_at)
+        VALUES ($1, $2, $3, now() + make_interval(hours => $4))
+        "#,
+        newsletter_issue_id,
+        i16::from(test.variant_share),
+        test.metric.as_str(),
+        i32::from(test.wait_hours),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    for (variant, subject) in test.subjects.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO subject_variants (newsletter_issue_id, variant, subject)
+            VALUES ($1, $2, $3)
+            "#,
+            newsletter_issue_id,
+            variant as i16,
+            subject,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    // Each variant goes to the same number of subscribers, at least one.
+    sqlx::query!(
+        r#"
+        WITH shuffled AS (
+            SELECT
+                subscriber_email,
+                row_number() OVER (ORDER BY random()) - 1 AS position,
+                count(*) OVER () AS total
+            FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+        )
+        UPDATE issue_delivery_queue q
+        SET variant = s.position % $2
+        FROM shuffled s
+        WHERE q.newsletter_issue_id = $1
+        AND q.subscriber_email = s.subscriber_email
+        AND s.position < $2 * greatest(1, s.total * $3 / 100)
+        "#,
+        newsletter_issue_id,
+        test.subjects.len() as i64,
+        i64::from(test.variant_share),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Pick the winner of one subject test whose wait is over and whose variants
+/// have all been sent, releasing the rest of the audience.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_pick_subject_test_winner(
+    pool: &PgPool,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let test = sqlx::query!(
+        r#"
+        SELECT t.newsletter_issue_id, t.metric
+        FROM subject_tests t
+        JOIN newsletter_issues i ON i.newsletter_issue_id = t.newsletter_issue_id
+        WHERE t.winning_variant IS NULL AND t.decide_at <= now() AND i.status = 'published'
+        AND NOT EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = t.newsletter_issue_id AND q.variant IS NOT NULL
+        )
+        FOR UPDATE OF t
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let test = match test {
+        Some(test) => test,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("newsletter_issue_id", display(test.newsletter_issue_id));
+
+    let metric = TestMetric::parse(&test.metric)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("A subject test has an invalid metric")?;
+    // Ties go to the earliest variant, the title of the issue first.
+    sqlx::query!(
+        r#"
+        UPDATE subject_tests
+        SET decided_at = now(), winning_variant = (
+            SELECT variant
+            FROM subject_variant_results
+            WHERE newsletter_issue_id = $1
+            ORDER BY
+                CASE WHEN $2 = 'clicks' THEN clicks ELSE opens END::float8
+                    / greatest(delivered, 1) DESC,
+                variant
+            LIMIT 1
+        )
+        WHERE newsletter_issue_id = $1
+        "#,
+        test.newsletter_issue_id,
+        metric.as_str(),
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+pub struct SubjectTestResults {
+    pub metric: String,
+    pub variant_share: i16,
+    pub decide_at: DateTime<Utc>,
+    pub winning_variant: Option<i16>,
+    pub variants: Vec<SubjectVariantResults>,
+}
+
+pub struct SubjectVariantResults {
+    pub variant: i16,
+    pub subject: String,
+    pub assigned: i64,
+    pub delivered: i64,
+    pub opens: i64,
+    pub clicks: i64,
+}
+
+impl SubjectVariantResults {
+    /// The share of the emails delivered that were opened, in %.
+    pub fn open_rate(&self) -> f64 {
+        rate(self.opens, self.delivered)
+    }
+
+    /// The share of the emails delivered whose links were followed, in %.
+    pub fn click_rate(&self) -> f64 {
+        rate(self.clicks, self.delivered)
+    }
+}
+
+fn rate(count: i64, delivered: i64) -> f64 {
+    if delivered == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / delivered as f64
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_subject_test_results(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<SubjectTestResults>, anyhow::Error> {
+    let test = sqlx::query!(
+        r#"
+        SELECT metric, variant_share, decide_at, winning_variant
+        FROM subject_tests
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subject test")?;
+    let test = match test {
+        Some(test) => test,
+        None => return Ok(None),
+    };
+    let variants = sqlx::query_as!(
+        SubjectVariantResults,
+        r#"
+        SELECT
+            variant AS "variant!",
+            subject AS "subject!",
+            assigned AS "assigned!",
+            delivered AS "delivered!",
+            opens AS "opens!",
+            clicks AS "clicks!"
+        FROM subject_variant_results
+        WHERE newsletter_issue_id = $1
+        ORDER BY variant
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the results of the subject test")?;
+    Ok(Some(SubjectTestResults {
+        metric: test.metric,
+        variant_share: test.variant_share,
+        decide_at: test.decide_at,
+        winning_variant: test.winning_variant,
+        variants,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubjectTest, TestMetric};
+
+    #[test]
+    fn empty_alternatives_mean_no_test() {
+        let test = SubjectTest::parse("Title", &["", "  "], 10, 4, "").unwrap();
+        assert!(test.is_none());
+    }
+
+    #[test]
+    fn the_title_is_the_first_variant() {
+        let test = SubjectTest::parse("Title", &["", " Other "], 10, 4, "clicks")
+            .unwrap()
+            .unwrap();
+        assert_eq!(test.subjects, ["Title", "Other"]);
+        assert_eq!(test.metric, TestMetric::Clicks);
+    }
+
+    #[test]
+    fn out_of_range_settings_are_rejected() {
+        for (share, wait_hours, metric) in [
+            (0, 4, "opens"),
+            (26, 4, "opens"),
+            (10, 0, "opens"),
+            (10, 169, "opens"),
+            (10, 4, "replies"),
+        ] {
+            assert!(SubjectTest::parse("Title", &["Other"], share, wait_hours, metric).is_err());
+        }
+        assert!(SubjectTest::parse("Title", &["B", "C", "D", "E"], 10, 4, "opens").is_err());
+        // Nobody would be left for the winner.
+        assert!(SubjectTest::parse("Title", &["B", "C", "D"], 25, 4, "opens").is_err());
+        assert!(SubjectTest::parse("Title", &["B", "C", "D"], 24, 4, "opens").is_ok());
+    }
+}
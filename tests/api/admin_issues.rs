@@ -0,0 +1,481 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, body_string_contains};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_issue_list() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let page_response = app.get_admin_issues().await;
+    let progress_response = app.get_issues_progress().await;
+
+    // Assert
+    assert_is_redirect_to(&page_response, "/login");
+    assert_is_redirect_to(&progress_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn drafts_are_listed_with_their_status() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": "Listed draft",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+
+    // Act
+    let html = app.get_admin_issues_html().await;
+
+    // Assert
+    assert!(html.contains(&format!(
+        r#"<tr id="issue-{0}" data-status="draft"><td><a href="/admin/newsletters/drafts/{0}">Listed draft</a></td><td data-field="status">draft</td>"#,
+        draft_id
+    )));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn delivery_progress_is_reported_while_an_issue_is_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\n\
+            ursula_le_guin_issues1@gmail.com,Ursula Le Guin\n\
+            ursula_le_guin_issues2@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Issue list test",
+        "action": "import",
+    }))
+    .await;
+    Mock::given(body_string_contains("ursula_le_guin_issues2@gmail.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let title = format!("Progress {}", Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act - Part 1 - Before the emails go out
+    let progress = app.issue_progress(issue_id).await;
+
+    // Assert - Part 1
+    assert_eq!(progress["status"], "sending");
+    assert_eq!(progress["queued"], 2);
+    assert_eq!(progress["delivered"], 0);
+    assert_eq!(progress["failed"], 0);
+
+    // Act - Part 2 - Once they are sent
+    app.dispatch_all_pending_emails().await;
+    let progress = app.issue_progress(issue_id).await;
+    let html = app.get_admin_issues_html().await;
+
+    // Assert - Part 2
+    assert_eq!(progress["status"], "sent");
+    assert_eq!(progress["queued"], 0);
+    assert_eq!(progress["delivered"], 1);
+    assert_eq!(progress["failed"], 1);
+    assert!(html.contains(&format!(
+        r#"<tr id="issue-{}" data-status="sent">"#,
+        issue_id
+    )));
+    assert!(html.contains(r#"<td data-field="delivered">1</td><td data-field="failed">1</td>"#));
+    let error = sqlx::query!(
+        "SELECT error FROM issue_deliveries WHERE newsletter_issue_id = $1 AND outcome = 'failed'",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .error
+    .unwrap();
+    assert!(error.starts_with("Failed to send the email"));
+
+    for email in [
+        "ursula_le_guin_issues1@gmail.com",
+        "ursula_le_guin_issues2@gmail.com",
+    ] {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_control_a_send() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_issue_action(Uuid::new_v4(), "pause").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn a_send_can_be_paused_resumed_and_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\n\
+            ursula_le_guin_issues3@gmail.com,Ursula Le Guin\n\
+            ursula_le_guin_issues4@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Issue list test",
+        "action": "import",
+    }))
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let title = format!("Paused {}", Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act - Part 1 - Pause the send
+    let response = app.post_issue_action(issue_id, "pause").await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    let html = app.get_admin_issues_html().await;
+    assert!(html.contains(
+        "<p><i>The issue is paused: no more emails will go out until it is resumed.</i></p>"
+    ));
+    assert!(html.contains(&format!("/admin/issues/{}/resume", issue_id)));
+    let progress = app.issue_progress(issue_id).await;
+    assert_eq!(progress["status"], "paused");
+    assert_eq!(progress["queued"], 2);
+
+    // Act - Part 2 - Resume it for a single email
+    app.post_issue_action(issue_id, "resume").await;
+    let html = app.get_admin_issues_html().await;
+    app.dispatch_pending_email().await;
+
+    // Assert - Part 2
+    assert!(html.contains("<p><i>The issue is being sent again.</i></p>"));
+    let progress = app.issue_progress(issue_id).await;
+    assert_eq!(progress["status"], "sending");
+    assert_eq!(progress["queued"], 1);
+    assert_eq!(progress["delivered"], 1);
+
+    // Act - Part 3 - Cancel it
+    app.post_issue_action(issue_id, "cancel").await;
+    let html = app.get_admin_issues_html().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 3
+    assert!(html.contains("<p><i>The issue is cancelled: 1 emails will not be sent.</i></p>"));
+    let progress = app.issue_progress(issue_id).await;
+    assert_eq!(progress["status"], "cancelled");
+    assert_eq!(progress["queued"], 0);
+    assert_eq!(progress["delivered"], 1);
+
+    // Act - Part 4 - Nothing is left to control
+    for (action, error) in [
+        ("pause", "Only issues being sent can be paused."),
+        ("resume", "Only paused issues can be resumed."),
+        ("cancel", "Only issues being sent can be cancelled."),
+    ] {
+        app.post_issue_action(issue_id, action).await;
+        let html = app.get_admin_issues_html().await;
+
+        // Assert - Part 4
+        assert!(html.contains(&format!("<p><i>{}</i></p>", error)));
+    }
+
+    for email in [
+        "ursula_le_guin_issues3@gmail.com",
+        "ursula_le_guin_issues4@gmail.com",
+    ] {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+/// The addresses queued for the issue called `title`, sorted.
+async fn queued_recipients(app: &TestApp, title: &str) -> Vec<String> {
+    let mut recipients: Vec<_> = sqlx::query!(
+        r#"
+        SELECT q.subscriber_email
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE i.title = $1
+        "#,
+        title
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.subscriber_email)
+    .collect();
+    recipients.sort();
+    recipients
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn a_correction_is_only_sent_to_the_recipients_of_the_original_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\n\
+            ursula_le_guin_issues5@gmail.com,Ursula Le Guin\n\
+            ursula_le_guin_issues6@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Issue list test",
+        "action": "import",
+    }))
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(6)
+        .mount(&app.email_server)
+        .await;
+    let title = format!("Original {}", Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let original_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+    app.dispatch_all_pending_emails().await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\nursula_le_guin_issues7@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Issue list test",
+        "action": "import",
+    }))
+    .await;
+
+    // Act - Part 1 - The original issue is offered as an audience
+    let html = app.get_publish_newsletter_html().await;
+
+    // Assert - Part 1
+    assert!(html.contains(&format!(
+        r#"<option value="{}">Recipients of "{}" (2 delivered)</option>"#,
+        original_id, title
+    )));
+
+    // Act - Part 2 - Publish the correction
+    let correction_title = format!("Correction {}", Uuid::new_v4());
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": correction_title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "recipients_of": original_id.to_string(),
+        }))
+        .await;
+
+    // Assert - Part 2
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(
+        queued_recipients(&app, &correction_title).await,
+        [
+            "ursula_le_guin_issues5@gmail.com",
+            "ursula_le_guin_issues6@gmail.com"
+        ]
+    );
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Part 3 - A correction saved as a draft, then published
+    let draft_title = format!("Drafted correction {}", Uuid::new_v4());
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": draft_title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "recipients_of": original_id.to_string(),
+        }))
+        .await;
+    let draft_html = app.get_edit_draft_html(draft_id).await;
+    let response = app
+        .post_draft_action(
+            draft_id,
+            "publish",
+            &serde_json::json!({"idempotency_key": Uuid::new_v4().to_string()}),
+        )
+        .await;
+
+    // Assert - Part 3
+    assert!(draft_html.contains(&format!(
+        r#"<option value="{}" selected>Recipients of "{}" (2 delivered)</option>"#,
+        original_id, title
+    )));
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    assert_eq!(
+        queued_recipients(&app, &draft_title).await,
+        [
+            "ursula_le_guin_issues5@gmail.com",
+            "ursula_le_guin_issues6@gmail.com"
+        ]
+    );
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Part 4 - A correction is not sent to those who joined since
+    let response = app
+        .post_issue_action(draft_id, "send_to_new_subscribers")
+        .await;
+
+    // Assert - Part 4
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html = app.get_admin_issues_html().await;
+    assert!(html.contains(
+        "<p><i>The issue was only sent to the recipients of another one: it cannot be sent to new subscribers.</i></p>"
+    ));
+    assert!(queued_recipients(&app, &draft_title).await.is_empty());
+
+    for email in [
+        "ursula_le_guin_issues5@gmail.com",
+        "ursula_le_guin_issues6@gmail.com",
+        "ursula_le_guin_issues7@gmail.com",
+    ] {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn an_issue_can_be_sent_to_those_who_subscribed_since_it_was_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\nursula_le_guin_issues8@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Issue list test",
+        "action": "import",
+    }))
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let title = format!("Catch up {}", Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+    app.dispatch_all_pending_emails().await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\nursula_le_guin_issues9@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Issue list test",
+        "action": "import",
+    }))
+    .await;
+
+    // Act - Part 1 - Send it to the new subscriber
+    let html = app.get_admin_issues_html().await;
+    assert!(html.contains(&format!(
+        "/admin/issues/{}/send_to_new_subscribers",
+        issue_id
+    )));
+    let response = app
+        .post_issue_action(issue_id, "send_to_new_subscribers")
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html = app.get_admin_issues_html().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    assert!(html.contains("<p><i>The issue is being sent to 1 new subscribers.</i></p>"));
+    let recipients = sqlx::query!(
+        "SELECT subscriber_email FROM issue_deliveries WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(recipients
+        .iter()
+        .any(|r| r.subscriber_email == "ursula_le_guin_issues9@gmail.com"));
+
+    // Act - Part 2 - Nobody is left to send it to
+    app.post_issue_action(issue_id, "send_to_new_subscribers")
+        .await;
+    let html = app.get_admin_issues_html().await;
+
+    // Assert - Part 2
+    assert!(html.contains("<p><i>Nobody has subscribed since the issue was published.</i></p>"));
+
+    for email in [
+        "ursula_le_guin_issues8@gmail.com",
+        "ursula_le_guin_issues9@gmail.com",
+    ] {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
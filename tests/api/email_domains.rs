@@ -0,0 +1,101 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn subscribing_with_a_disposable_address_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin", "email":"ursula_le_guin@mailinator.com" }
+    "#;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Email addresses at mailinator.com cannot be used to subscribe."
+    );
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_denied_domains() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let form_response = app.get_email_domains().await;
+    let deny_response = app.post_deny_email_domain("example.com").await;
+
+    // Assert
+    assert_is_redirect_to(&form_response, "/login");
+    assert_is_redirect_to(&deny_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn subscribing_at_a_domain_denied_by_an_admin_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let body = r#"
+        { "name":"le guin", "email":"ursula_le_guin@mail.denied-by-admin.com" }
+    "#;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Deny the domain
+    let response = app.post_deny_email_domain(" Denied-By-Admin.com ").await;
+    assert_is_redirect_to(&response, "/admin/email_domains");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_email_domains_html().await;
+    assert!(
+        html_page.contains("<p><i>denied-by-admin.com has been added to the deny list.</i></p>")
+    );
+
+    // Act - Part 3 - Subscribe from a subdomain
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Email addresses at mail.denied-by-admin.com cannot be used to subscribe."
+    );
+
+    let response = app.post_remove_email_domain("denied-by-admin.com").await;
+    assert_is_redirect_to(&response, "/admin/email_domains");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn invalid_domains_are_not_denied() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_deny_email_domain("localhost").await;
+    assert_is_redirect_to(&response, "/admin/email_domains");
+
+    // Assert
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<p><i>localhost is not a valid domain.</i></p>"));
+    app.cleanup_user().await;
+}
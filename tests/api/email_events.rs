@@ -0,0 +1,83 @@
+use crate::helpers::spawn_app;
+use uuid::Uuid;
+
+const EMAILS: [&str; 2] = [
+    "ursula_le_guin_bounced1@gmail.com",
+    "ursula_le_guin_bounced2@gmail.com",
+];
+
+#[tokio::test]
+async fn email_events_with_an_invalid_token_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/email_events?token=not-the-token", app.address))
+        .json(&serde_json::json!([]))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn hard_bounces_mark_the_subscriber_as_bounced() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let tag = format!("bounces-{}", Uuid::new_v4());
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": format!("email,name\n{}\n", EMAILS.map(|e| format!("{},Ursula Le Guin", e)).join("\n")),
+        "mode": "confirmed",
+        "consent_source": "Bounces",
+        "action": "import",
+    }))
+    .await;
+    for email in EMAILS {
+        app.post_subscriber_tags(email, &tag).await;
+    }
+
+    // Act
+    let response = app
+        .post_email_events(&serde_json::json!([
+            {
+                "event": "bounce",
+                "time": 1670000000,
+                "email": EMAILS[0].to_uppercase(),
+                "hard_bounce": true,
+            },
+            {
+                "event": "bounce",
+                "time": 1670000000,
+                "email": EMAILS[1],
+                "hard_bounce": false,
+            },
+            { "event": "open", "time": 1670000000, "email": EMAILS[1] },
+        ]))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let bounced_html = app
+        .get_segments_html(Some(&format!("tag:{} and bounced", tag)))
+        .await;
+    let saved = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE email = ANY($1) AND bounced_at IS NOT NULL",
+        &EMAILS.map(String::from)[..]
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    assert!(bounced_html.contains("<p>1 subscribers match"));
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, EMAILS[0]);
+    app.cleanup_user().await;
+}
@@ -0,0 +1,99 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Publish an issue with a unique title, returning it and its id.
+async fn publish_issue(app: &TestApp) -> (String, Uuid) {
+    let title = format!("Feed issue {}", Uuid::new_v4());
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Feed body as plain text",
+            "html_content": "<p>Feed body as <b>HTML</b></p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+    (title, issue_id)
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn published_issues_are_in_the_rss_and_atom_feeds() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (title, issue_id) = publish_issue(&app).await;
+
+    // Act
+    let rss_response = app.get_feed("/feed.xml", &[]).await;
+    let atom_response = app.get_feed("/atom.xml", &[]).await;
+
+    // Assert
+    assert_eq!(rss_response.status().as_u16(), 200);
+    assert_eq!(
+        rss_response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let rss = rss_response.text().await.unwrap();
+    assert!(rss.contains(&format!("<title>{}</title>", title)));
+    assert!(rss.contains(&format!(
+        r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#,
+        issue_id
+    )));
+    assert!(rss.contains("<description>&lt;p&gt;Feed body as &lt;b&gt;HTML&lt;/b&gt;&lt;/p&gt;"));
+    assert!(rss.contains("<pubDate>"));
+
+    assert_eq!(atom_response.status().as_u16(), 200);
+    assert_eq!(
+        atom_response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let atom = atom_response.text().await.unwrap();
+    assert!(atom.contains(&format!("<id>urn:uuid:{}</id>", issue_id)));
+    assert!(atom.contains(r#"<content type="html">&lt;p&gt;Feed body"#));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn feeds_support_conditional_requests() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app).await;
+    let response = app.get_feed("/feed.xml", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+    let last_modified = response.headers()["Last-Modified"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // Act - Part 1 - Ask again for the same feed
+    let etag_response = app.get_feed("/feed.xml", &[("If-None-Match", &etag)]).await;
+    let date_response = app
+        .get_feed("/feed.xml", &[("If-Modified-Since", &last_modified)])
+        .await;
+
+    // Assert - Part 1
+    assert_eq!(etag_response.status().as_u16(), 304);
+    assert_eq!(etag_response.headers()["ETag"], etag.as_str());
+    assert_eq!(date_response.status().as_u16(), 304);
+
+    // Act - Part 2 - Publish another issue
+    let (title, _) = publish_issue(&app).await;
+    let response = app.get_feed("/feed.xml", &[("If-None-Match", &etag)]).await;
+
+    // Assert - Part 2
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"], etag.as_str());
+    assert!(response.text().await.unwrap().contains(&title));
+    app.cleanup_user().await;
+}
@@ -1,14 +1,17 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseConfiguration};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{get_configuration, DatabaseConfiguration, RolloutConfiguration};
 use zero2prod::email_client::EmailClient;
 use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::{confirmation_email_worker, issue_scheduler, staged_rollouts, subject_tests};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let subscriber_name = "test".to_string();
@@ -77,6 +80,10 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub email_events_token: Secret<String>,
+    pub rollout: RolloutConfiguration,
 }
 
 pub struct ConfirmationLinks {
@@ -87,7 +94,7 @@ pub struct ConfirmationLinks {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/json")
             .body(body)
             .send()
@@ -95,12 +102,26 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Post events as the email provider would.
+    pub async fn post_email_events(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/email_events?token={}",
+                &self.address,
+                self.email_events_token.expose_secret()
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             // This `reqwest` method makes sure that the body is URL-encoded
             // and the `Content-Type` header is set accordingly.
             .form(body)
@@ -111,7 +132,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -122,7 +143,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -134,7 +155,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -149,7 +170,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -158,7 +179,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -166,7 +187,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -181,13 +202,573 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preview_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/preview", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_lint_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/lint", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_test_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/test", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create a draft, returning its id.
+    pub async fn create_draft<Body>(&self, body: &Body) -> Uuid
+    where
+        Body: serde::Serialize,
+    {
+        let response = self.post_create_draft(body).await;
+        assert_eq!(response.status().as_u16(), 303);
+        let location = response.headers()["Location"].to_str().unwrap();
+        location
+            .strip_prefix("/admin/newsletters/drafts/")
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    pub async fn get_edit_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_edit_draft_html(&self, draft_id: Uuid) -> String {
+        self.get_edit_draft(draft_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_draft_action<Body>(
+        &self,
+        draft_id: Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let url = if action == "save" {
+            format!("{}/admin/newsletters/drafts/{}", &self.address, draft_id)
+        } else {
+            format!(
+                "{}/admin/newsletters/drafts/{}/{}",
+                &self.address, draft_id, action
+            )
+        };
+        self.api_client
+            .post(url)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_layouts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/layouts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_layout<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/layouts", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn create_layout<Body>(&self, body: &Body) -> Uuid
+    where
+        Body: serde::Serialize,
+    {
+        let response = self.post_create_layout(body).await;
+        assert_eq!(response.status().as_u16(), 303);
+        let location = response.headers()["Location"].to_str().unwrap();
+        location
+            .strip_prefix("/admin/layouts/")
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    pub async fn get_edit_layout(&self, layout_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/layouts/{}", &self.address, layout_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_edit_layout_html(&self, layout_id: Uuid) -> String {
+        self.get_edit_layout(layout_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_layout_action<Body>(
+        &self,
+        layout_id: Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let url = if action == "save" {
+            format!("{}/admin/layouts/{}", &self.address, layout_id)
+        } else {
+            format!("{}/admin/layouts/{}/{}", &self.address, layout_id, action)
+        };
+        self.api_client
+            .post(url)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_issues_search_html(&self, search: &str) -> String {
+        self.api_client
+            .get(format!("{}/issues", &self.address))
+            .query(&[("search", search)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_issue_page(&self, slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_feed(&self, path: &str, headers: &[(&str, &str)]) -> reqwest::Response {
+        let mut request = self.api_client.get(format!("{}{}", &self.address, path));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues_html(&self) -> String {
+        self.get_admin_issues().await.text().await.unwrap()
+    }
+
+    pub async fn get_issues_progress(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/progress", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The delivery progress of an issue, as reported to the issue list.
+    pub async fn issue_progress(&self, issue_id: Uuid) -> serde_json::Value {
+        let progress: Vec<serde_json::Value> =
+            self.get_issues_progress().await.json().await.unwrap();
+        progress
+            .into_iter()
+            .find(|p| p["newsletter_issue_id"] == issue_id.to_string())
+            .expect("The issue has no progress")
+    }
+
+    pub async fn get_issue_details(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issue_details_html(&self, issue_id: Uuid) -> String {
+        self.get_issue_details(issue_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_issue_action(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/archive", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_html(&self) -> String {
+        self.get_archive().await.text().await.unwrap()
+    }
+
+    pub async fn get_archive_search_html(&self, search: &str) -> String {
+        self.api_client
+            .get(format!("{}/admin/archive", &self.address))
+            .query(&[("search", search)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_archive_action(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/archive/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.get_lists().await.text().await.unwrap()
+    }
+
+    pub async fn post_create_list(&self, name: &str, slug: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(&serde_json::json!({ "name": name, "slug": slug }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create a mailing list with a unique slug, returning its id and slug.
+    pub async fn create_list(&self) -> (uuid::Uuid, String) {
+        let slug = format!("list-{}", uuid::Uuid::new_v4());
+        let list_id = uuid::Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO lists (list_id, slug, name, created_at) VALUES ($1, $2, $3, now())",
+            list_id,
+            slug,
+            "Test list"
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to create list.");
+        (list_id, slug)
+    }
+
+    pub async fn get_segments(&self, expression: Option<&str>) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/segments", &self.address))
+            .query(&[("expression", expression.unwrap_or_default())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_segments_html(&self, expression: Option<&str>) -> String {
+        self.get_segments(expression).await.text().await.unwrap()
+    }
+
+    pub async fn post_save_segment(&self, name: &str, expression: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/segments", &self.address))
+            .form(&serde_json::json!({ "name": name, "expression": expression }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_tags(&self, email: &str, tags: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers/tags", &self.address))
+            .form(&serde_json::json!({ "email": email, "tags": tags }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_import_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/import", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_import_subscribers_html(&self) -> String {
+        self.get_import_subscribers().await.text().await.unwrap()
+    }
+
+    /// Post the import form as a browser would, `csv_file` being uploaded
+    /// as a file.
+    pub async fn post_import_subscribers(&self, body: &serde_json::Value) -> reqwest::Response {
+        let mut form = reqwest::multipart::Form::new();
+        for (name, value) in body.as_object().unwrap() {
+            let value = value.as_str().unwrap().to_string();
+            form = if name == "csv_file" {
+                let file = reqwest::multipart::Part::text(value)
+                    .file_name("subscribers.csv")
+                    .mime_str("text/csv")
+                    .unwrap();
+                form.part(name.clone(), file)
+            } else {
+                form.text(name.clone(), value)
+            };
+        }
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_export_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/export", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_export_subscribers_download(
+        &self,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/export/download",
+                &self.address
+            ))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &[(&str, &str)]) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber_details(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_details_html(&self, subscriber_id: Uuid) -> String {
+        self.get_subscriber_details(subscriber_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_email_domains(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/email_domains", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_email_domains_html(&self) -> String {
+        self.get_email_domains().await.text().await.unwrap()
+    }
+
+    pub async fn post_deny_email_domain(&self, domain: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/email_domains", &self.address))
+            .form(&serde_json::json!({ "domain": domain }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_remove_email_domain(&self, domain: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/email_domains/remove", &self.address))
+            .form(&serde_json::json!({ "domain": domain }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn create_unconfirmed_subscriber(&self, email: &str) -> ConfirmationLinks {
+        let body = serde_json::json!({
+            "name": "le guin",
+            "email": email,
+        });
+
+        let _mock_guard = Mock::given(path("/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body.to_string())
+            .await
+            .error_for_status()
+            .unwrap();
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_links(email_request)
+    }
+
+    pub async fn create_confirmed_subscriber(&self, email: &str) {
+        let confirmation_link = self.create_unconfirmed_subscriber(email).await;
+        reqwest::get(confirmation_link.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    /// Delete the subscriptions of `emails`, then the test user.
+    pub async fn cleanup(&self, emails: &[&str]) {
+        for email in emails {
+            self.cleanup_subscriptinos(email.to_string()).await;
+        }
+        self.cleanup_user().await;
+    }
+
     pub async fn cleanup_subscriptinos(&self, email: String) {
         sqlx::query!("delete from subscription_tokens st using subscriptions s where st.subscriber_id = s.id and s.email = $1", email)
             .execute(&self.db_pool)
@@ -242,15 +823,85 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["Html-part"].as_str().unwrap());
-        let plain_text = get_link(&body["Text-part"].as_str().unwrap());
+        let html = get_link(body["Html-part"].as_str().unwrap());
+        let plain_text = get_link(body["Text-part"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
+    pub async fn dispatch_all_pending_confirmation_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = confirmation_email_worker::try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Send a single pending email, if any.
+    pub async fn dispatch_pending_email(&self) -> ExecutionOutcome {
+        try_execute_task(
+            &self.db_pool,
+            &self.email_client,
+            &self.base_url,
+            &self.hmac_secret,
+        )
+        .await
+        .unwrap()
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn pick_subject_test_winners(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
+                subject_tests::try_pick_subject_test_winner(&self.db_pool)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn advance_rollouts(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = staged_rollouts::try_advance_rollout(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn publish_due_issues(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                issue_scheduler::try_publish_due_issue(&self.db_pool, &self.rollout)
                     .await
                     .unwrap()
             {
@@ -278,6 +929,8 @@ pub async fn spawn_app() -> TestApp {
         // Use a random OS port
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        // Stages are checked as soon as they are sent.
+        c.rollout.settle_minutes = 0;
         c
     };
 
@@ -288,7 +941,7 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application.");
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application.port());
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -304,7 +957,11 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
+        email_events_token: configuration.email_client.events_token.clone(),
+        rollout: configuration.rollout.clone(),
         email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url,
+        hmac_secret: configuration.application.hmac_secret,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -341,3 +998,16 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+/// A tag no other test uses.
+pub fn unique_tag() -> String {
+    format!("tag-{}", Uuid::new_v4())
+}
+
+pub fn draft_body(title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
@@ -0,0 +1,226 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue with a unique title, returning its id and slug.
+async fn publish_issue(app: &TestApp, title: &str) -> (Uuid, String) {
+    publish_issue_with_content(
+        app,
+        title,
+        "Hi {{ name }}, here is our news.",
+        "<p>Hi {{ name | default: \"there\" }}, here is our news.</p>",
+    )
+    .await
+}
+
+async fn publish_issue_with_content(
+    app: &TestApp,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> (Uuid, String) {
+    let title = format!("{} {}", title, Uuid::new_v4());
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": text_content,
+            "html_content": html_content,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue = sqlx::query!(
+        r#"SELECT newsletter_issue_id, slug AS "slug!" FROM newsletter_issues WHERE title = $1"#,
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    (issue.newsletter_issue_id, issue.slug)
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn published_issues_can_be_read_online() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let (_, slug) = publish_issue(&app, "Archive & news").await;
+    let index_html = app.get_issues_html().await;
+    let response = app.get_issue_page(&slug).await;
+
+    // Assert
+    assert!(slug.starts_with("archive-news-"));
+    assert!(index_html.contains(&format!(r#"<a href="/issues/{}">Archive &amp; news"#, slug)));
+    assert_eq!(response.status().as_u16(), 200);
+    let page_html = response.text().await.unwrap();
+    assert!(page_html.contains("<title>Archive &amp; news"));
+    assert!(page_html.contains(r#"<meta property="og:type" content="article">"#));
+    assert!(page_html.contains(r#"<meta property="og:title" content="Archive"#));
+    assert!(page_html.contains(r#"<meta property="og:description" content="Hi"#));
+    assert!(page_html.contains(&format!(
+        r#"<meta property="og:url" content="{}">"#,
+        htmlescape::encode_attribute(&format!("{}/issues/{}", app.base_url, slug))
+    )));
+    assert!(page_html.contains("<p>Hi there, here is our news.</p>"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn unknown_issues_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issue_page("no-such-issue-00000000").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn hidden_issues_are_left_out_of_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (issue_id, slug) = publish_issue(&app, "Hidden issue").await;
+
+    // Act - Part 1 - Hide the issue
+    let response = app.post_archive_action(issue_id, "hide").await;
+    assert_is_redirect_to(&response, "/admin/archive");
+
+    // Assert - Part 1
+    let archive_html = app.get_archive_html().await;
+    assert!(archive_html.contains("<p><i>The issue is hidden from the archive.</i></p>"));
+    assert!(archive_html.contains(&format!("/admin/archive/{}/show", issue_id)));
+    assert!(!app
+        .get_issues_html()
+        .await
+        .contains(&format!("/issues/{}", slug)));
+    assert_eq!(app.get_issue_page(&slug).await.status().as_u16(), 404);
+
+    // Act - Part 2 - Show it again
+    let response = app.post_archive_action(issue_id, "show").await;
+    assert_is_redirect_to(&response, "/admin/archive");
+
+    // Assert - Part 2
+    let archive_html = app.get_archive_html().await;
+    assert!(archive_html.contains("<p><i>The issue is shown in the archive again.</i></p>"));
+    assert_eq!(app.get_issue_page(&slug).await.status().as_u16(), 200);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn emails_link_to_the_online_version_of_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\nursula_le_guin_archive1@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Archive test",
+        "action": "import",
+    }))
+    .await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let (_, slug) = publish_issue(&app, "Linked issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let url = format!("{}/issues/{}", app.base_url, slug);
+    assert!(email["Text-part"]
+        .as_str()
+        .unwrap()
+        .ends_with(&format!("\n\nView in browser: {}", url)));
+    assert!(email["Html-part"]
+        .as_str()
+        .unwrap()
+        .contains(&format!(r#"<a href="{}">View in browser</a>"#, url)));
+    app.cleanup_subscriptinos("ursula_le_guin_archive1@gmail.com".into())
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let page_response = app.get_archive().await;
+    let hide_response = app.post_archive_action(Uuid::new_v4(), "hide").await;
+
+    // Assert
+    assert_is_redirect_to(&page_response, "/login");
+    assert_is_redirect_to(&hide_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn the_archive_can_be_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // A made-up word only these issues contain.
+    let word = format!("zq{}", Uuid::new_v4().simple());
+    let (_, mentioned_slug) = publish_issue_with_content(
+        &app,
+        "Gardening",
+        &format!("Our marigolds & {} are blooming.", word),
+        "<p>Our marigolds are blooming.</p>",
+    )
+    .await;
+    let (hidden_id, titled_slug) = publish_issue_with_content(
+        &app,
+        &word,
+        "Nothing else here.",
+        "<p>Nothing else here.</p>",
+    )
+    .await;
+
+    // Act - Part 1 - Search the public archive
+    let html = app.get_issues_search_html(&word).await;
+
+    // Assert - Part 1
+    let titled = html.find(&format!("/issues/{}", titled_slug)).unwrap();
+    let mentioned = html.find(&format!("/issues/{}", mentioned_slug)).unwrap();
+    assert!(titled < mentioned, "Matches in the title should rank first");
+    assert!(html.contains(&format!(
+        "marigolds &amp; <mark>{}</mark> are blooming",
+        word
+    )));
+    assert!(html.contains(&format!(r#"name="search" value="{}""#, word)));
+
+    // Act - Part 2 - Hide one of the issues
+    app.post_archive_action(hidden_id, "hide").await;
+    let public_html = app.get_issues_search_html(&word).await;
+    let admin_html = app.get_archive_search_html(&word).await;
+
+    // Assert - Part 2
+    assert!(!public_html.contains(&titled_slug));
+    assert!(public_html.contains(&mentioned_slug));
+    assert!(admin_html.contains(&titled_slug));
+    assert!(admin_html.contains("</a> (hidden)"));
+    assert!(admin_html.contains(&mentioned_slug));
+    assert!(app
+        .get_issues_search_html(&format!("{}x", word))
+        .await
+        .contains("<li>No issues match your search.</li>"));
+    app.cleanup_user().await;
+}
@@ -0,0 +1,205 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn layout_body(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "html_template": "<header>Branding</header>{{ content }}<footer><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a></footer>",
+        "text_template": "BRANDING\n\n{{ content }}\n\nUnsubscribe: {{ unsubscribe_url }}",
+    })
+}
+
+fn unique_name() -> String {
+    format!("Layout {}", Uuid::new_v4())
+}
+
+async fn delete_layout(app: &TestApp, layout_id: Uuid) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET layout_id = NULL WHERE layout_id = $1",
+        layout_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "DELETE FROM newsletter_layouts WHERE layout_id = $1",
+        layout_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_layouts() {
+    // Arrange
+    let app = spawn_app().await;
+    let layout_id = Uuid::new_v4();
+
+    // Act
+    let create_response = app.post_create_layout(&layout_body("Layout")).await;
+    let edit_response = app.get_edit_layout(layout_id).await;
+    let delete_response = app
+        .post_layout_action(layout_id, "delete", &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&create_response, "/login");
+    assert_is_redirect_to(&edit_response, "/login");
+    assert_is_redirect_to(&delete_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn layouts_can_be_created_edited_and_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let name = unique_name();
+
+    // Act - Part 1 - Create
+    let layout_id = app.create_layout(&layout_body(&name)).await;
+    assert!(app.get_layouts_html().await.contains(&name));
+
+    // Act - Part 2 - Edit
+    let mut body = layout_body(&name);
+    body["text_template"] = "NEW BRANDING\n\n{{ content }}".into();
+    let response = app.post_layout_action(layout_id, "save", &body).await;
+    assert_is_redirect_to(&response, &format!("/admin/layouts/{}", layout_id));
+    let html_page = app.get_edit_layout_html(layout_id).await;
+    assert!(html_page.contains("The layout has been saved."));
+    assert!(html_page.contains("NEW BRANDING"));
+
+    // Act - Part 3 - Delete
+    let response = app
+        .post_layout_action(layout_id, "delete", &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let html_page = app.get_layouts_html().await;
+    assert!(html_page.contains("The layout has been deleted."));
+    assert!(!html_page.contains(&name));
+    assert_eq!(app.get_edit_layout(layout_id).await.status().as_u16(), 404);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn layouts_need_a_content_slot_and_a_unique_name() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let name = unique_name();
+    let layout_id = app.create_layout(&layout_body(&name)).await;
+
+    // Act
+    let mut body = layout_body(&unique_name());
+    body["html_template"] = "<p>No slot</p>".into();
+    let missing_slot_response = app.post_create_layout(&body).await;
+    let missing_slot_page = app.get_layouts_html().await;
+    let duplicate_response = app.post_create_layout(&layout_body(&name)).await;
+    let duplicate_page = app.get_layouts_html().await;
+
+    // Assert
+    assert_is_redirect_to(&missing_slot_response, "/admin/layouts");
+    assert!(missing_slot_page.contains(&htmlescape::encode_minimal(
+        "The HTML layout is invalid: it has no `{{ content }}` slot."
+    )));
+    assert_is_redirect_to(&duplicate_response, "/admin/layouts");
+    assert!(duplicate_page.contains("There already is a layout called"));
+    delete_layout(&app, layout_id).await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_preview_shows_the_issue_in_its_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let name = unique_name();
+    let layout_id = app.create_layout(&layout_body(&name)).await;
+
+    // Act
+    let response = app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "Title",
+            "text_content": "Hi {{ name }}",
+            "html_content": "<p>Hi {{ name }}</p>",
+            "layout": name,
+        }))
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("BRANDING\n\nHi Ada Lovelace\n\nUnsubscribe: "));
+    assert!(html_page.contains(&htmlescape::encode_attribute(
+        "<header>Branding</header><p>Hi Ada Lovelace</p>"
+    )));
+    delete_layout(&app, layout_id).await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn issues_are_wrapped_in_their_layout_when_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,name\nursula_le_guin_layouts1@gmail.com,Ursula Le Guin\n",
+        "mode": "confirmed",
+        "consent_source": "Layouts test",
+        "action": "import",
+    }))
+    .await;
+    let name = unique_name();
+    let layout_id = app.create_layout(&layout_body(&name)).await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish with the layout
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Laid out title",
+            "text_content": "Hi {{ name }}",
+            "html_content": "<p>Hi {{ name }}</p>",
+            "layout": name,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Change the layout before the issue goes out
+    let mut body = layout_body(&name);
+    body["text_template"] = "NEW BRANDING\n\n{{ content }}".into();
+    app.post_layout_action(layout_id, "save", &body).await;
+    let delete_response = app
+        .post_layout_action(layout_id, "delete", &serde_json::json!({}))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&delete_response, &format!("/admin/layouts/{}", layout_id));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let (text, web_link) = email["Text-part"]
+        .as_str()
+        .unwrap()
+        .split_once("\n\nView in browser: ")
+        .unwrap();
+    assert_eq!(text, "NEW BRANDING\n\nHi Ursula Le Guin");
+    assert!(web_link.contains("/issues/"));
+    let html = email["Html-part"].as_str().unwrap();
+    assert!(html.starts_with("<header>Branding</header><p>Hi Ursula Le Guin</p><footer>"));
+    assert!(html.contains("/subscriptions/unsubscribe?subscriber_id="));
+    app.cleanup_subscriptinos("ursula_le_guin_layouts1@gmail.com".into())
+        .await;
+    delete_layout(&app, layout_id).await;
+    app.cleanup_user().await;
+}
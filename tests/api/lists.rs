@@ -0,0 +1,304 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscribe_and_confirm(app: &TestApp, email: &str, list: Option<&str>) {
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": email,
+        "list": list,
+    });
+    let _mock_guard = Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.to_string())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let form_response = app.get_lists().await;
+    let create_response = app.post_create_list("Product updates", "product").await;
+
+    // Assert
+    assert_is_redirect_to(&form_response, "/login");
+    assert_is_redirect_to(&create_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn admins_can_create_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let slug = format!("updates-{}", uuid::Uuid::new_v4());
+
+    // Act - Part 1 - Create the list
+    let response = app.post_create_list("Product updates", &slug).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The Product updates list has been created.</i></p>"));
+    assert!(html_page.contains(&format!("Product updates (<code>{}</code>)", slug)));
+
+    // Act - Part 3 - Pick it when publishing
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("Product updates (0 confirmed subscribers)"));
+
+    sqlx::query!("DELETE FROM lists WHERE slug = $1", slug)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn lists_with_an_invalid_slug_are_not_created() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_create_list("Product updates", "Product Updates")
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    // Assert
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains(
+        "<p><i>Product Updates is not a valid list slug: \
+        use lowercase letters, digits and dashes.</i></p>"
+    ));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin", "email":"ursula_le_guin_lists1@gmail.com", "list": "no-such-list" }
+    "#;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        response.text().await.unwrap(),
+        "There is no mailing list called no-such-list."
+    );
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn an_existing_subscriber_can_join_another_list() {
+    // Arrange
+    let app = spawn_app().await;
+    let (list_id, slug) = app.create_list().await;
+    subscribe_and_confirm(&app, "ursula_le_guin_lists2@gmail.com", None).await;
+
+    // Act
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin_lists2@gmail.com",
+        "list": slug,
+    });
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_subscriptions(body.to_string()).await;
+
+    // Assert
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1
+        ORDER BY l.slug = 'newsletter' DESC
+        "#,
+        "ursula_le_guin_lists2@gmail.com"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    app.cleanup_subscriptinos("ursula_le_guin_lists2@gmail.com".into())
+        .await;
+    sqlx::query!("DELETE FROM lists WHERE list_id = $1", list_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(memberships.len(), 2);
+    assert_eq!(memberships[0].status, "confirmed");
+    assert_eq!(memberships[1].slug, slug);
+    assert_eq!(memberships[1].status, "pending_confirmation");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn newsletters_are_only_delivered_to_the_selected_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (list_id, slug) = app.create_list().await;
+    subscribe_and_confirm(&app, "ursula_le_guin_lists3@gmail.com", None).await;
+    subscribe_and_confirm(&app, "ursula_le_guin_lists4@gmail.com", Some(&slug)).await;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": list_id.to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Recipients"][0]["email"],
+        "ursula_le_guin_lists4@gmail.com"
+    );
+
+    app.cleanup_subscriptinos("ursula_le_guin_lists3@gmail.com".into())
+        .await;
+    app.cleanup_subscriptinos("ursula_le_guin_lists4@gmail.com".into())
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn a_correction_unsubscribes_from_the_list_of_the_original_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (list_id, slug) = app.create_list().await;
+    subscribe_and_confirm(&app, "ursula_le_guin_lists5@gmail.com", Some(&slug)).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let title = format!("Original {}", uuid::Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": list_id.to_string(),
+    }))
+    .await;
+    let original_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+    app.dispatch_all_pending_emails().await;
+    // The corrections are drafted on the default list.
+    let correction_body = serde_json::json!({
+        "title": "Correction",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "recipients_of": original_id.to_string(),
+    });
+    let published_id = app.create_draft(&correction_body).await;
+    let scheduled_id = app.create_draft(&correction_body).await;
+
+    // Act - Publish one correction and schedule the other
+    app.post_draft_action(
+        published_id,
+        "publish",
+        &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+    )
+    .await;
+    app.post_draft_action(
+        scheduled_id,
+        "schedule",
+        &serde_json::json!({"scheduled_for": "2099-01-01T09:00", "timezone": "UTC"}),
+    )
+    .await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() WHERE newsletter_issue_id = $1",
+        scheduled_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    for issue_id in [published_id, scheduled_id] {
+        let issue = sqlx::query!(
+            "SELECT list_id FROM newsletter_issues WHERE newsletter_issue_id = $1",
+            issue_id,
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(issue.list_id, list_id);
+    }
+    let requests = app.email_server.received_requests().await.unwrap();
+    for email_request in &requests[requests.len() - 2..] {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let list_unsubscribe = body["Headers"]["List-Unsubscribe"].as_str().unwrap();
+        assert!(list_unsubscribe.contains(&format!("&list_id={}&", list_id)));
+    }
+
+    app.cleanup_subscriptinos("ursula_le_guin_lists5@gmail.com".into())
+        .await;
+    app.cleanup_user().await;
+}
@@ -1,8 +1,26 @@
 mod admin_dashboard;
+mod admin_issues;
 mod change_password;
+mod email_domains;
+mod email_events;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues;
+mod layouts;
+mod lists;
 mod login;
 mod newsletter;
+mod newsletter_drafts;
+mod newsletter_lint;
+mod newsletter_preview;
+mod newsletter_schedule;
+mod segments;
+mod staged_rollouts;
+mod subject_tests;
+mod subscriber_export;
+mod subscriber_import;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
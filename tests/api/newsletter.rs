@@ -1,51 +1,16 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{assert_is_redirect_to, spawn_app};
 use std::time::Duration;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-async fn create_unconfirmed_subscriber(app: &TestApp, email: &str) -> ConfirmationLinks {
-    let body = serde_json::json!({
-        "name": "le guin",
-        "email": email,
-    });
-
-    let _mock_guard = Mock::given(path("/send"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-    app.post_subscriptions(body.to_string())
-        .await
-        .error_for_status()
-        .unwrap();
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
-    let confirmation_link = create_unconfirmed_subscriber(app, email).await;
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 #[serial_test::serial]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     // Arrange
     let app = spawn_app().await;
     app.test_user.login(&app).await;
-    create_unconfirmed_subscriber(&app, "ursula_le_guin11@gmail.com").await;
+    app.create_unconfirmed_subscriber("ursula_le_guin11@gmail.com")
+        .await;
 
     Mock::given(any())
         .respond_with(ResponseTemplate::new(200))
@@ -83,7 +48,8 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Arrange
     let app = spawn_app().await;
     app.test_user.login(&app).await;
-    create_confirmed_subscriber(&app, "ursula_le_guin12@gmail.com").await;
+    app.create_confirmed_subscriber("ursula_le_guin12@gmail.com")
+        .await;
 
     Mock::given(path("/send"))
         .and(method("POST"))
@@ -155,7 +121,8 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
 async fn newsletter_creation_is_idempotent() {
     // Arrange
     let app = spawn_app().await;
-    create_confirmed_subscriber(&app, "ursula_le_guin13@gmail.com").await;
+    app.create_confirmed_subscriber("ursula_le_guin13@gmail.com")
+        .await;
     app.test_user.login(&app).await;
 
     Mock::given(path("/send"))
@@ -207,7 +174,8 @@ async fn newsletter_creation_is_idempotent() {
 async fn concurrent_form_submission_is_handled_gracefully() {
     // Arrange
     let app = spawn_app().await;
-    create_confirmed_subscriber(&app, "ursula_le_guin13@gmail.com").await;
+    app.create_confirmed_subscriber("ursula_le_guin13@gmail.com")
+        .await;
     app.test_user.login(&app).await;
 
     Mock::given(path("/send"))
@@ -243,3 +211,252 @@ async fn concurrent_form_submission_is_handled_gracefully() {
         .await;
     app.cleanup_user().await;
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn merge_tags_are_rendered_for_each_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.create_confirmed_subscriber("ursula_le_guin14@gmail.com")
+        .await;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = '{"company": "<Acme>"}' WHERE email = $1"#,
+        "ursula_le_guin14@gmail.com"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{ name }}",
+        "text_content": "Hi {{ attributes.company }} - {{ attributes.plan | default: \"free\" }}",
+        "html_content": "<p>Hi {{ attributes.company }}</p><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "News for le guin");
+    let (text, web_link) = body["Text-part"]
+        .as_str()
+        .unwrap()
+        .split_once("\n\nView in browser: ")
+        .unwrap();
+    assert_eq!(text, "Hi <Acme> - free");
+    assert!(web_link.contains("/issues/news-for-name-"));
+    let html = body["Html-part"].as_str().unwrap();
+    assert!(html.starts_with("<p>Hi &lt;Acme&gt;</p>"));
+    assert!(html.contains("/subscriptions/unsubscribe?subscriber_id="));
+    let list_unsubscribe = body["Headers"]["List-Unsubscribe"].as_str().unwrap();
+    assert!(list_unsubscribe.starts_with(&format!(
+        "<{}/subscriptions/unsubscribe?subscriber_id=",
+        app.base_url
+    )));
+    assert_eq!(
+        body["Headers"]["List-Unsubscribe-Post"],
+        "List-Unsubscribe=One-Click"
+    );
+
+    app.cleanup_subscriptinos("ursula_le_guin14@gmail.com".into())
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn newsletters_with_invalid_merge_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ nmae }}",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The plain text content could not be published: \
+        `{{ nmae }}` is not a known merge tag.</i></p>"
+    ));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn issues_written_in_markdown_are_sent_with_generated_parts() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.create_confirmed_subscriber("ursula_le_guin15@gmail.com")
+        .await;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let markdown_content = "Hi **{{ name }}**,\n\n[Unsubscribe]({{unsubscribe_url}})";
+    let newsletter_request_body = serde_json::json!({
+        "title": "Markdown title",
+        "text_content": "Ignored plain text",
+        "html_content": "<p>Ignored HTML</p>",
+        "markdown_content": markdown_content,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["Text-part"].as_str().unwrap();
+    assert!(text.starts_with("Hi le guin,\n\nUnsubscribe ("));
+    assert!(text.contains("/subscriptions/unsubscribe?subscriber_id="));
+    let html = body["Html-part"].as_str().unwrap();
+    assert!(html.contains("<p>Hi <strong>le guin</strong>,</p>"));
+    assert!(html.contains("/subscriptions/unsubscribe?subscriber_id="));
+    let stored = sqlx::query!(
+        r#"
+        SELECT markdown_content
+        FROM newsletter_issues
+        WHERE title = 'Markdown title'
+        ORDER BY updated_at DESC
+        LIMIT 1
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.markdown_content.as_deref(), Some(markdown_content));
+
+    app.cleanup_subscriptinos("ursula_le_guin15@gmail.com".into())
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn newsletters_with_malformed_html_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<div><p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The HTML content could not be published: \
+        it is not well-formed: the `&lt;div&gt;` element is never closed.</i></p>"
+    ));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn html_content_is_sanitised_and_its_css_inlined() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.create_confirmed_subscriber("ursula_le_guin16@gmail.com")
+        .await;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Sanitised title",
+        "text_content": "Hi {{ name }}",
+        "html_content": "<style>p { color: #333333; }</style>\
+            <p onmouseover=\"track()\">Hi {{ name }}</p>\
+            <script>alert(1)</script>\
+            <a href=\"javascript:alert(1)\">Click</a>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let (html, web_link) = body["Html-part"]
+        .as_str()
+        .unwrap()
+        .split_once("<p style=\"font-size: 12px; text-align: center;\">")
+        .unwrap();
+    assert_eq!(
+        html,
+        "<p style=\"color: #333333;\">Hi le guin</p><a>Click</a>"
+    );
+    assert!(web_link.contains(">View in browser</a></p>"));
+
+    app.cleanup_subscriptinos("ursula_le_guin16@gmail.com".into())
+        .await;
+    app.cleanup_user().await;
+}
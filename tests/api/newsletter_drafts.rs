@@ -0,0 +1,276 @@
+use crate::helpers::{assert_is_redirect_to, draft_body, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn draft_status(app: &TestApp, draft_id: Uuid) -> Option<String> {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        draft_id
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = Uuid::new_v4();
+
+    // Act
+    let create_response = app.post_create_draft(&draft_body("Title")).await;
+    let edit_response = app.get_edit_draft(draft_id).await;
+    let publish_response = app
+        .post_draft_action(
+            draft_id,
+            "publish",
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&create_response, "/login");
+    assert_is_redirect_to(&edit_response, "/login");
+    assert_is_redirect_to(&publish_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn drafts_are_only_delivered_once_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.create_confirmed_subscriber("ursula_le_guin_drafts1@gmail.com")
+        .await;
+
+    // Act - Part 1 - Write the draft over several saves
+    let draft_id = app.create_draft(&draft_body("First title")).await;
+    let response = app
+        .post_draft_action(draft_id, "save", &draft_body("Second title"))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let response = app
+        .post_draft_action(draft_id, "autosave", &draft_body("Final title"))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    let html_page = app.get_edit_draft_html(draft_id).await;
+    assert!(html_page.contains(&htmlescape::encode_attribute("Final title")));
+    assert!(app.get_drafts_html().await.contains("Final title"));
+
+    // Nothing is queued while the issue is a draft.
+    let no_email_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(no_email_guard);
+
+    // Act - Part 2 - Publish, twice with the same idempotency key
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let publish_body = serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() });
+    let response = app
+        .post_draft_action(draft_id, "publish", &publish_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    let response = app
+        .post_draft_action(draft_id, "publish", &publish_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    let html_page = app.get_drafts_html().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    app.cleanup_subscriptinos("ursula_le_guin_drafts1@gmail.com".into())
+        .await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - \
+        emails will go out shortly.</i></p>"
+    ));
+    assert!(!html_page.contains(&draft_id.to_string()));
+    assert_eq!(draft_status(&app, draft_id).await.unwrap(), "published");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Final title");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn published_drafts_can_no_longer_be_edited_published_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let draft_id = app.create_draft(&draft_body("Published title")).await;
+    app.post_draft_action(
+        draft_id,
+        "publish",
+        &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+    )
+    .await;
+
+    // Act
+    let edit_response = app.get_edit_draft(draft_id).await;
+    let autosave_response = app
+        .post_draft_action(draft_id, "autosave", &draft_body("New title"))
+        .await;
+    let publish_response = app
+        .post_draft_action(
+            draft_id,
+            "publish",
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+    let delete_response = app
+        .post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_eq!(edit_response.status().as_u16(), 404);
+    assert_eq!(autosave_response.status().as_u16(), 404);
+    assert_is_redirect_to(&publish_response, "/admin/newsletters/drafts");
+    assert_is_redirect_to(&delete_response, "/admin/newsletters/drafts");
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("This draft has been published or deleted."));
+    assert_eq!(draft_status(&app, draft_id).await.unwrap(), "published");
+    app.dispatch_all_pending_emails().await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = app.create_draft(&draft_body("Deleted title")).await;
+
+    // Act
+    let response = app
+        .post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("The draft has been deleted."));
+    assert_eq!(draft_status(&app, draft_id).await, None);
+    assert_eq!(app.get_edit_draft(draft_id).await.status().as_u16(), 404);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn drafts_with_invalid_merge_tags_are_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = app.create_draft(&draft_body("Hello {{ nickname }}")).await;
+
+    // Act
+    let response = app
+        .post_draft_action(
+            draft_id,
+            "publish",
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_edit_draft_html(draft_id).await;
+    assert!(html_page.contains("The title could not be published"));
+    assert_eq!(draft_status(&app, draft_id).await.unwrap(), "draft");
+    app.post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn drafts_with_malformed_html_are_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = draft_body("Draft title");
+    body["html_content"] = "<p>Broken</div>".into();
+    let draft_id = app.create_draft(&body).await;
+
+    // Act
+    let response = app
+        .post_draft_action(
+            draft_id,
+            "publish",
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_edit_draft_html(draft_id).await;
+    assert!(html_page.contains(
+        "The HTML content could not be published: it is not well-formed: \
+        the `&lt;/div&gt;` end tag does not close any element."
+    ));
+    assert_eq!(draft_status(&app, draft_id).await.unwrap(), "draft");
+    app.post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn drafts_written_in_markdown_keep_their_source() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = draft_body("Markdown draft");
+    body["markdown_content"] = "# Heading\n\nSome *emphasis*.".into();
+
+    // Act
+    let draft_id = app.create_draft(&body).await;
+
+    // Assert
+    let html_page = app.get_edit_draft_html(draft_id).await;
+    assert!(html_page.contains("># Heading\n\nSome *emphasis*.</textarea>"));
+    let draft = sqlx::query!(
+        "SELECT text_content, html_content FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(draft.text_content, "Heading\n=======\n\nSome emphasis.\n");
+    assert!(draft.html_content.contains("<h1>Heading</h1>"));
+    app.post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+    app.cleanup_user().await;
+}
@@ -0,0 +1,139 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn questionable_issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Lint title",
+        "text_content": "Hi {{ name }}, here is our news.",
+        "html_content": "<p>Hi {{ name }}, here is our news.</p>\
+            <a href=\"http://example.com\"><img src=\"https://example.com/logo.png\"></a>",
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_lint_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_lint_newsletter(&questionable_issue()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_lint_endpoint_reports_errors_and_warnings() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut issue = questionable_issue();
+    issue["title"] = "".into();
+
+    // Act
+    let response = app.post_lint_newsletter(&issue).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["errors"], serde_json::json!(["The title is empty."]));
+    assert_eq!(
+        report["warnings"],
+        serde_json::json!([
+            "The plain text part has no `{{ unsubscribe_url }}` link for subscribers to leave.",
+            "The HTML part has no `{{ unsubscribe_url }}` link for subscribers to leave.",
+            "An image has no `alt` text, which is shown when images are blocked.",
+            "http://example.com is not a secure link: use https:// if the site supports it.",
+        ])
+    );
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_lint_endpoint_reports_unknown_layouts() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut issue = questionable_issue();
+    issue["layout"] = "No such layout".into();
+
+    // Act
+    let response = app.post_lint_newsletter(&issue).await;
+
+    // Assert
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        report["errors"],
+        serde_json::json!(["There is no layout called No such layout."])
+    );
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_preview_shows_the_lint_report() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_preview_newsletter(&questionable_issue()).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(
+        "<li>Warning: An image has no `alt` text, which is shown when images are blocked.</li>"
+    ));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn issues_with_lint_errors_are_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let mut issue = questionable_issue();
+    issue["title"] = "a".repeat(151).into();
+    issue["idempotency_key"] = Uuid::new_v4().to_string().into();
+
+    // Act
+    let response = app.post_publish_newsletter(&issue).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page
+        .contains("<p><i>The title is 151 characters long, over the limit of 150.</i></p>"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn issues_with_lint_warnings_are_published_with_the_warnings_shown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut issue = questionable_issue();
+    issue["idempotency_key"] = Uuid::new_v4().to_string().into();
+
+    // Act
+    let response = app.post_publish_newsletter(&issue).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+    assert!(html_page.contains(
+        "<p><i>http://example.com is not a secure link: use https:// if the site supports it.</i></p>"
+    ));
+    app.cleanup_user().await;
+}
@@ -0,0 +1,169 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn content_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Hello {{ name }}",
+        "text_content": "Dear {{ attributes.company }}",
+        "html_content": "<p>Dear {{ attributes.nickname | default: \"reader\" }}</p>",
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_preview_or_test_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let preview_response = app.post_preview_newsletter(&content_body()).await;
+    let test_response = app.post_test_newsletter(&content_body()).await;
+
+    // Assert
+    assert_is_redirect_to(&preview_response, "/login");
+    assert_is_redirect_to(&test_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_preview_renders_the_issue_with_sample_data() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_preview_newsletter(&content_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Hello Ada Lovelace</h1>"));
+    assert!(html_page.contains("Dear Analytical Engines Ltd</pre>"));
+    // The HTML part is only rendered inside a sandboxed frame.
+    assert!(html_page.contains("sandbox"));
+    assert!(html_page.contains(&htmlescape::encode_attribute("<p>Dear reader</p>")));
+    assert!(!html_page.contains("<p>Dear reader</p>"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_preview_reports_invalid_merge_tags() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "Hello {{ nickname }}",
+            "text_content": "Body",
+            "html_content": "<p>Body</p>",
+        }))
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("The title could not be rendered"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn tests_are_sent_to_the_given_addresses_without_queueing_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let mut body = content_body();
+    body["send_to"] = "addresses".into();
+    body["test_recipients"] = "editor@example.com, reviewer@example.com".into();
+
+    // Act
+    let response = app.post_test_newsletter(&body).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("A test has been sent to editor@example.com."));
+    assert!(html_page.contains("A test has been sent to reviewer@example.com."));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "Hello Ada Lovelace");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn tests_sent_to_me_remember_my_address() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut body = content_body();
+    body["send_to"] = "me".into();
+    body["my_email"] = "admin@example.com".into();
+    body["test_recipients"] = "someone.else@example.com".into();
+
+    // Act
+    let response = app.post_test_newsletter(&body).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("A test has been sent to admin@example.com."));
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute("admin@example.com")
+    )));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn tests_are_not_sent_if_an_address_is_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let mut body = content_body();
+    body["send_to"] = "addresses".into();
+    body["test_recipients"] = "editor@example.com; not-an-address".into();
+
+    // Act
+    let response = app.post_test_newsletter(&body).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("not-an-address is not a valid subscriber email."));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_preview_shows_issues_written_in_markdown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = content_body();
+    body["markdown_content"] = "Hello **{{ name }}**".into();
+
+    // Act
+    let response = app.post_preview_newsletter(&body).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Hello Ada Lovelace\n</pre>"));
+    assert!(html_page.contains(&htmlescape::encode_attribute(
+        "<p>Hello <strong>Ada Lovelace</strong></p>"
+    )));
+    app.cleanup_user().await;
+}
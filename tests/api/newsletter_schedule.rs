@@ -0,0 +1,258 @@
+use crate::helpers::{assert_is_redirect_to, draft_body, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Its CSS is inlined when the issue is prepared for sending.
+const HTML_CONTENT: &str = "<style>p { color: red; }</style><p>Newsletter body as HTML</p>";
+
+fn styled_draft_body(title: &str) -> serde_json::Value {
+    let mut body = draft_body(title);
+    body["html_content"] = HTML_CONTENT.into();
+    body
+}
+
+fn schedule_body(scheduled_for: &str) -> serde_json::Value {
+    serde_json::json!({
+        "scheduled_for": scheduled_for,
+        "timezone": "Europe/London",
+    })
+}
+
+async fn issue_status(app: &TestApp, issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+async fn issue_html_content(app: &TestApp, issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT html_content FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .html_content
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn scheduled_issues_are_published_once_when_due() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.create_confirmed_subscriber("ursula_le_guin_schedule1@gmail.com")
+        .await;
+    let draft_id = app
+        .create_draft(&styled_draft_body("Scheduled title"))
+        .await;
+
+    // Act - Part 1 - Schedule the draft for next year
+    let response = app
+        .post_draft_action(draft_id, "schedule", &schedule_body("2099-01-01T09:00"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("The issue will be published on 2099-01-01 09:00 UTC."));
+    assert_eq!(issue_status(&app, draft_id).await, "scheduled");
+    assert_eq!(issue_html_content(&app, draft_id).await, HTML_CONTENT);
+
+    // Nothing is published before the issue is due.
+    let no_email_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    drop(no_email_guard);
+    assert_eq!(issue_status(&app, draft_id).await, "scheduled");
+
+    // Act - Part 2 - The issue becomes due and the scheduler runs twice
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = now() - interval '1 minute'
+        WHERE newsletter_issue_id = $1
+        "#,
+        draft_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.publish_due_issues().await;
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    app.cleanup_subscriptinos("ursula_le_guin_schedule1@gmail.com".into())
+        .await;
+    assert_eq!(issue_status(&app, draft_id).await, "published");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["Html-part"]
+        .as_str()
+        .unwrap()
+        .contains(r#"<p style="color: red;">"#));
+    let response = app
+        .post_draft_action(draft_id, "unschedule", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn an_issue_that_cannot_be_published_does_not_hold_up_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let broken_id = app.create_draft(&styled_draft_body("Broken title")).await;
+    let due_id = app.create_draft(&styled_draft_body("Due title")).await;
+    for draft_id in [broken_id, due_id] {
+        app.post_draft_action(draft_id, "schedule", &schedule_body("2099-01-01T09:00"))
+            .await;
+    }
+    // The earlier issue has a segment the scheduler cannot parse.
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            scheduled_for = now() - interval '2 minutes',
+            segment_expression = 'opened:7d'
+        WHERE newsletter_issue_id = $1
+        "#,
+        broken_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = now() - interval '1 minute'
+        WHERE newsletter_issue_id = $1
+        "#,
+        due_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - The scheduler runs
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    assert_eq!(issue_status(&app, broken_id).await, "scheduled");
+    assert_eq!(issue_status(&app, due_id).await, "published");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("could not be published: A scheduled issue has an invalid segment"));
+
+    // Act - Part 2 - Rescheduling tries again
+    app.post_draft_action(broken_id, "schedule", &schedule_body("2099-01-01T09:00"))
+        .await;
+
+    // Assert - Part 2
+    let html_page = app.get_drafts_html().await;
+    assert!(!html_page.contains("could not be published"));
+
+    app.post_draft_action(broken_id, "unschedule", &serde_json::json!({}))
+        .await;
+    app.post_draft_action(broken_id, "delete", &serde_json::json!({}))
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn scheduled_issues_can_be_rescheduled_and_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = app
+        .create_draft(&styled_draft_body("Rescheduled title"))
+        .await;
+    app.post_draft_action(draft_id, "schedule", &schedule_body("2099-01-01T09:00"))
+        .await;
+
+    // Act - Part 1 - Reschedule, in summer time
+    let response = app
+        .post_draft_action(draft_id, "schedule", &schedule_body("2099-07-01T09:00"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("to be published on 2099-07-01 08:00 UTC"));
+
+    // Scheduled issues cannot be edited.
+    let autosave_response = app
+        .post_draft_action(draft_id, "autosave", &styled_draft_body("New title"))
+        .await;
+    assert_eq!(autosave_response.status().as_u16(), 404);
+
+    // Act - Part 2 - Cancel
+    let response = app
+        .post_draft_action(draft_id, "unschedule", &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_edit_draft_html(draft_id).await;
+    assert!(html_page.contains("The issue is no longer scheduled and is a draft again."));
+    assert_eq!(issue_status(&app, draft_id).await, "draft");
+    assert_eq!(issue_html_content(&app, draft_id).await, HTML_CONTENT);
+    app.post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn drafts_cannot_be_scheduled_in_the_past() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = app.create_draft(&styled_draft_body("Past title")).await;
+
+    // Act
+    let response = app
+        .post_draft_action(draft_id, "schedule", &schedule_body("2020-01-01T09:00"))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_edit_draft_html(draft_id).await;
+    assert!(html_page.contains("The issue must be scheduled in the future."));
+    assert_eq!(issue_status(&app, draft_id).await, "draft");
+    assert_eq!(issue_html_content(&app, draft_id).await, HTML_CONTENT);
+    app.post_draft_action(draft_id, "delete", &serde_json::json!({}))
+        .await;
+    app.cleanup_user().await;
+}
@@ -0,0 +1,216 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, unique_tag};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_segments() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let form_response = app.get_segments(None).await;
+    let save_response = app.post_save_segment("Beta", "tag:beta").await;
+    let tags_response = app
+        .post_subscriber_tags("ursula_le_guin@gmail.com", "beta")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&form_response, "/login");
+    assert_is_redirect_to(&save_response, "/login");
+    assert_is_redirect_to(&tags_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn tagged_subscribers_are_counted_in_the_segment_preview() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let tag = unique_tag();
+    app.create_confirmed_subscriber("ursula_le_guin_segments1@gmail.com")
+        .await;
+
+    // Act - Part 1 - Tag the subscriber
+    let response = app
+        .post_subscriber_tags(
+            "ursula_le_guin_segments1@gmail.com",
+            &format!("vip, {}", tag),
+        )
+        .await;
+    assert_is_redirect_to(
+        &response,
+        "/admin/subscribers/attributes?email=ursula_le_guin_segments1%40gmail.com",
+    );
+
+    // Act - Part 2 - Preview segments
+    let expression = format!("status:confirmed and tag:{} and not bounced", tag);
+    let matching_html = app.get_segments_html(Some(&expression)).await;
+    let excluding_html = app
+        .get_segments_html(Some(&format!("tag:{} and bounced", tag)))
+        .await;
+
+    // Assert
+    app.cleanup_subscriptinos("ursula_le_guin_segments1@gmail.com".into())
+        .await;
+    assert!(matching_html.contains(&format!(
+        "<p>1 subscribers match <code>{}</code>.</p>",
+        expression
+    )));
+    assert!(excluding_html.contains("<p>0 subscribers match"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn invalid_segments_are_reported_in_the_preview() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_segments_html(Some("tag:beta and opened:7d")).await;
+
+    // Assert
+    assert!(html_page.contains("<p><i>`opened` is not a known segment predicate.</i></p>"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn segments_can_be_saved_by_name() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let name = format!("Beta testers {}", uuid::Uuid::new_v4());
+
+    // Act - Part 1 - Save the segment
+    let response = app
+        .post_save_segment(&name, "tag:beta and subscribed_within:90d")
+        .await;
+    assert_is_redirect_to(&response, "/admin/segments");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_segments_html(None).await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The {} segment has been saved.</i></p>",
+        name
+    )));
+    assert!(html_page.contains(&format!(
+        "<li><strong>{}</strong>: <code>tag:beta and subscribed_within:90d</code>",
+        name
+    )));
+
+    // Act - Part 3 - Pick it when publishing
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(">{}</option>", name)));
+
+    sqlx::query!("DELETE FROM segments WHERE name = $1", name)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn invalid_segments_are_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let name = format!("Broken {}", uuid::Uuid::new_v4());
+
+    // Act
+    let response = app.post_save_segment(&name, "tag:beta and").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/segments?expression=tag%3Abeta%20and");
+    let html_page = app.get_segments_html(None).await;
+    assert!(html_page
+        .contains("<p><i>The segment could not be saved: The segment ends unexpectedly.</i></p>"));
+    let saved = sqlx::query!("SELECT name FROM segments WHERE name = $1", name)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn newsletters_sent_to_a_segment_only_reach_its_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let tag = unique_tag();
+    let name = format!("Segment {}", tag);
+    app.create_confirmed_subscriber("ursula_le_guin_segments2@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("ursula_le_guin_segments3@gmail.com")
+        .await;
+    app.post_subscriber_tags("ursula_le_guin_segments3@gmail.com", &tag)
+        .await;
+    app.post_save_segment(&name, &format!("tag:{}", tag)).await;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "segment": name,
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Recipients"][0]["email"],
+        "ursula_le_guin_segments3@gmail.com"
+    );
+
+    app.cleanup_subscriptinos("ursula_le_guin_segments2@gmail.com".into())
+        .await;
+    app.cleanup_subscriptinos("ursula_le_guin_segments3@gmail.com".into())
+        .await;
+    sqlx::query!("DELETE FROM segments WHERE name = $1", name)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "segment": "No such segment",
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>There is no segment called No such segment.</i></p>"));
+    app.cleanup_user().await;
+}
@@ -0,0 +1,300 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, body_string_contains};
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAILS: [&str; 3] = [
+    "ursula_le_guin_staged1@gmail.com",
+    "ursula_le_guin_staged2@gmail.com",
+    "ursula_le_guin_staged3@gmail.com",
+];
+
+async fn import_subscribers(app: &TestApp) {
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": format!("email,name\n{}\n", EMAILS.map(|e| format!("{},Ursula Le Guin", e)).join("\n")),
+        "mode": "confirmed",
+        "consent_source": "Staged rollout",
+        "action": "import",
+    }))
+    .await;
+}
+
+async fn publish_staged_issue(app: &TestApp) -> Uuid {
+    let title = format!("Staged issue {}", Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "rollout": "staged",
+    }))
+    .await;
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn a_staged_send_cannot_test_subjects_too() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "subject_b": "Another title",
+            "test_metric": "opens",
+            "rollout": "staged",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html = app.get_publish_newsletter_html().await;
+    assert!(html.contains(
+        "<p><i>A subject test already sends the issue to a share of the audience first: it cannot be sent in stages too.</i></p>"
+    ));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn a_staged_send_reaches_the_audience_one_stage_at_a_time() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_staged_issue(&app).await;
+
+    // Act - Part 1 - Only the first stage is sent
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+    assert_eq!(app.issue_progress(issue_id).await["queued"], 2);
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains(
+        r#"<tr data-stage="1"><td>1</td><td>1%</td><td>0</td><td>1</td><td>0</td><td>0</td><td>0.0%</td></tr>"#
+    ));
+    assert!(html.contains("Stage 1 of 3 is being sent."));
+
+    // Act - Part 2 - Each check releases the next stage
+    for sent in [2, 3] {
+        app.advance_rollouts().await;
+        app.dispatch_all_pending_emails().await;
+        assert_eq!(
+            app.email_server.received_requests().await.unwrap().len(),
+            sent
+        );
+    }
+
+    // Assert - Part 2
+    assert_eq!(app.issue_progress(issue_id).await["queued"], 0);
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains("Every stage has been released."));
+
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn a_stage_with_too_many_failures_pauses_the_send_and_tells_the_admins() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE id = $2",
+        "rollout_admin@example.com",
+        app.test_user.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    import_subscribers(&app).await;
+    Mock::given(body_string_contains("ursula_le_guin_staged"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(body_string_contains("was paused"))
+        .and(body_string_contains("rollout_admin@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_staged_issue(&app).await;
+
+    // Act - Part 1 - The first stage fails
+    app.dispatch_all_pending_emails().await;
+    app.advance_rollouts().await;
+
+    // Assert - Part 1
+    let progress = app.issue_progress(issue_id).await;
+    assert_eq!(progress["status"], "paused");
+    assert_eq!(progress["queued"], 2);
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains(
+        "Sending was paused automatically: 100.0% of the emails of stage 1 failed or bounced, over the limit of 5%."
+    ));
+
+    // Act - Part 2 - Resuming moves on to the next stage
+    app.post_issue_action(issue_id, "resume").await;
+    app.advance_rollouts().await;
+
+    // Assert - Part 2
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains("Stage 2 of 3 is being sent."));
+
+    app.post_issue_action(issue_id, "cancel").await;
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn a_stage_waits_for_bounces_and_pauses_if_too_many_bounced() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_staged_issue(&app).await;
+    sqlx::query!(
+        "UPDATE issue_rollouts SET settle_minutes = 60 WHERE newsletter_issue_id = $1",
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Part 1 - The stage is not checked before it has settled
+    app.advance_rollouts().await;
+
+    // Assert - Part 1
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains("Stage 1 of 3 is being sent. The next one starts 60 minutes after it is"));
+
+    // Act - Part 2 - The email of the stage bounces, then the stage settles
+    let delivered = sqlx::query!(
+        "SELECT subscriber_email FROM issue_deliveries WHERE newsletter_issue_id = $1",
+        issue_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .subscriber_email;
+    app.post_email_events(&serde_json::json!({
+        "event": "bounce",
+        "time": 1670000000,
+        "email": delivered,
+        "hard_bounce": true,
+    }))
+    .await;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries SET attempted_at = now() - interval '2 hours'
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.advance_rollouts().await;
+
+    // Assert - Part 2
+    assert_eq!(app.issue_progress(issue_id).await["status"], "paused");
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains(
+        r#"<tr data-stage="1"><td>1</td><td>1%</td><td>0</td><td>1</td><td>0</td><td>1</td><td>100.0%</td></tr>"#
+    ));
+    assert!(html.contains(
+        "Sending was paused automatically: 100.0% of the emails of stage 1 failed or bounced"
+    ));
+
+    app.post_issue_action(issue_id, "cancel").await;
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn drafts_and_scheduled_issues_keep_their_staged_rollout() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+    let draft_body = serde_json::json!({
+        "title": "Staged draft",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "rollout": "staged",
+    });
+    let published_id = app.create_draft(&draft_body).await;
+    let scheduled_id = app.create_draft(&draft_body).await;
+
+    // Act - Part 1 - The rollout is shown with the draft
+    let html = app.get_edit_draft_html(published_id).await;
+
+    // Assert - Part 1
+    assert!(html.contains(r#"<option value="staged" selected>"#));
+
+    // Act - Part 2 - Publish one draft and schedule the other
+    app.post_draft_action(
+        published_id,
+        "publish",
+        &serde_json::json!({"idempotency_key": Uuid::new_v4().to_string()}),
+    )
+    .await;
+    app.post_draft_action(
+        scheduled_id,
+        "schedule",
+        &serde_json::json!({"scheduled_for": "2099-01-01T09:00", "timezone": "UTC"}),
+    )
+    .await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() WHERE newsletter_issue_id = $1",
+        scheduled_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.publish_due_issues().await;
+
+    // Assert - Part 2 - Both are sent in stages
+    for issue_id in [published_id, scheduled_id] {
+        let html = app.get_issue_details_html(issue_id).await;
+        assert!(html.contains("Stage 1 of 3 is being sent."));
+        app.post_issue_action(issue_id, "cancel").await;
+    }
+
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
@@ -0,0 +1,268 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAILS: [&str; 4] = [
+    "ursula_le_guin_subjects1@gmail.com",
+    "ursula_le_guin_subjects2@gmail.com",
+    "ursula_le_guin_subjects3@gmail.com",
+    "ursula_le_guin_subjects4@gmail.com",
+];
+
+fn sent_emails(requests: &[wiremock::Request]) -> Vec<serde_json::Value> {
+    requests
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_details_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issue_details(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn invalid_subject_tests_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({"subject_b": "Another title", "test_share": "30"}),
+            "Each subject can be tested on 1% to 25% of the audience.",
+        ),
+        (
+            serde_json::json!({
+                "subject_b": "Title B",
+                "subject_c": "Title C",
+                "subject_d": "Title D",
+                "test_share": "25",
+            }),
+            "4 subjects tested on 25% of the audience each leave nobody for the winner.",
+        ),
+    ];
+
+    for (subject_test, error_message) in test_cases {
+        // Act
+        let mut body = serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "test_metric": "opens",
+        });
+        body.as_object_mut()
+            .unwrap()
+            .extend(subject_test.as_object().unwrap().clone());
+        let response = app.post_publish_newsletter(&body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html = app.get_publish_newsletter_html().await;
+        assert!(
+            html.contains(&format!("<p><i>{}</i></p>", error_message)),
+            "{}",
+            error_message
+        );
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn the_subject_with_the_most_clicks_is_sent_to_the_rest_of_the_audience() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": format!("email,name\n{}\n", EMAILS.map(|e| format!("{},Ursula Le Guin", e)).join("\n")),
+        "mode": "confirmed",
+        "consent_source": "Subject test",
+        "action": "import",
+    }))
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+    let title = format!("Subject A {}", Uuid::new_v4());
+    let subject_b = format!("Subject B {}", Uuid::new_v4());
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": r#"<p><a href="https://example.com/read">Read more</a></p>"#,
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "subject_b": subject_b,
+        "test_share": "25",
+        "test_wait_hours": "2",
+        "test_metric": "clicks",
+    }))
+    .await;
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act - Part 1 - Only the test share gets the issue
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    let emails = sent_emails(&app.email_server.received_requests().await.unwrap());
+    let mut subjects: Vec<_> = emails.iter().map(|e| e["Subject"].clone()).collect();
+    subjects.sort_by_key(|s| s.to_string());
+    assert_eq!(subjects, [title.as_str(), subject_b.as_str()]);
+    let progress = app.issue_progress(issue_id).await;
+    assert_eq!(progress["queued"], 2);
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains("Each subject is sent to 25% of the audience."));
+    assert!(html.contains("The subject with the highest click rate is picked after"));
+
+    // Act - Part 2 - The recipient of subject B follows the link
+    let email_b = emails.iter().find(|e| e["Subject"] == subject_b).unwrap();
+    let html_part = email_b["Html-part"].as_str().unwrap();
+    assert!(html_part.contains(&format!("{}/issues/{}/opened?", app.base_url, issue_id)));
+    let start = html_part
+        .find(&format!("{}/issues/{}/clicked?", app.base_url, issue_id))
+        .unwrap();
+    let end = start + html_part[start..].find('"').unwrap();
+    let click_url = htmlescape::decode_html(&html_part[start..end])
+        .unwrap()
+        .replace(&app.base_url, &app.address);
+    let response = app.api_client.get(&click_url).send().await.unwrap();
+
+    // Assert - Part 2
+    assert_is_redirect_to(&response, "https://example.com/read");
+    let response = app
+        .api_client
+        .get(click_url.replace("example.com", "example.org"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Act - Part 3 - The winner is only picked once the wait is over
+    app.pick_subject_test_winners().await;
+    let progress = app.issue_progress(issue_id).await;
+    assert_eq!(progress["queued"], 2);
+    sqlx::query!(
+        "UPDATE subject_tests SET decide_at = now() WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.pick_subject_test_winners().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 3
+    let emails = sent_emails(&app.email_server.received_requests().await.unwrap());
+    assert_eq!(emails.len(), 4);
+    assert!(emails[2..].iter().all(|e| e["Subject"] == subject_b));
+    let html = app.get_issue_details_html(issue_id).await;
+    assert!(html.contains(&format!(
+        r#"<tr data-variant="1"><td>{} (winner)</td><td>1</td><td>1</td><td>1</td><td>100.0%</td><td>1</td><td>100.0%</td></tr>"#,
+        subject_b
+    )));
+    assert!(html.contains("The winning subject is being sent to the rest of the audience."));
+
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn drafts_and_scheduled_issues_keep_their_subject_test() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": format!("email,name\n{}\n", EMAILS.map(|e| format!("{},Ursula Le Guin", e)).join("\n")),
+        "mode": "confirmed",
+        "consent_source": "Subject test",
+        "action": "import",
+    }))
+    .await;
+    let draft_body = serde_json::json!({
+        "title": "Subject A",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "subject_b": "Alternative",
+        "test_share": "25",
+        "test_wait_hours": "2",
+        "test_metric": "clicks",
+    });
+    let published_id = app.create_draft(&draft_body).await;
+    let scheduled_id = app.create_draft(&draft_body).await;
+
+    // Act - Part 1 - The test is shown with the draft
+    let html = app.get_edit_draft_html(published_id).await;
+
+    // Assert - Part 1
+    assert!(html.contains(r#"<input type="text" name="subject_b" value="Alternative">"#));
+    assert!(html.contains(r#"<option value="clicks" selected>"#));
+
+    // Act - Part 2 - Publish one draft and schedule the other
+    app.post_draft_action(
+        published_id,
+        "publish",
+        &serde_json::json!({"idempotency_key": Uuid::new_v4().to_string()}),
+    )
+    .await;
+    app.post_draft_action(
+        scheduled_id,
+        "schedule",
+        &serde_json::json!({"scheduled_for": "2099-01-01T09:00", "timezone": "UTC"}),
+    )
+    .await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() WHERE newsletter_issue_id = $1",
+        scheduled_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.publish_due_issues().await;
+
+    // Assert - Part 2 - Both test their subjects on 25% of the audience each
+    for issue_id in [published_id, scheduled_id] {
+        let test = sqlx::query!(
+            r#"
+            SELECT t.variant_share, t.metric, count(q.variant) AS "n_testing!"
+            FROM subject_tests t
+            JOIN issue_delivery_queue q USING (newsletter_issue_id)
+            WHERE t.newsletter_issue_id = $1
+            GROUP BY t.newsletter_issue_id
+            "#,
+            issue_id,
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(test.variant_share, 25);
+        assert_eq!(test.metric, "clicks");
+        assert_eq!(test.n_testing, 2);
+        app.post_issue_action(issue_id, "cancel").await;
+    }
+
+    for email in EMAILS {
+        app.cleanup_subscriptinos(email.into()).await;
+    }
+    app.cleanup_user().await;
+}
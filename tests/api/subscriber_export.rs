@@ -0,0 +1,196 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, unique_tag, TestApp};
+
+const EMAILS: [&str; 2] = [
+    "ursula_le_guin_export1@gmail.com",
+    "octavia_butler_export1@gmail.com",
+];
+
+/// Import both subscribers as confirmed, tagging only the first one.
+async fn create_tagged_subscribers(app: &TestApp, tag: &str) {
+    let csv = format!(
+        "email,name\n{},Ursula Le Guin\n{},\"Butler, Octavia\"\n",
+        EMAILS[0], EMAILS[1]
+    );
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": csv,
+        "mode": "confirmed",
+        "consent_source": "Export test",
+        "action": "import",
+    }))
+    .await;
+    app.post_subscriber_tags(EMAILS[0], &format!("vip, {}", tag))
+        .await;
+    app.post_subscriber_tags(EMAILS[1], tag).await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let form_response = app.get_export_subscribers().await;
+    let download_response = app
+        .get_export_subscribers_download(&[("format", "csv")])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&form_response, "/login");
+    assert_is_redirect_to(&download_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn subscribers_are_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let tag = unique_tag();
+    create_tagged_subscribers(&app, &tag).await;
+
+    // Act
+    let response = app
+        .get_export_subscribers_download(&[
+            ("format", "csv"),
+            ("status", "confirmed"),
+            ("list", "newsletter"),
+            ("tag", &tag),
+        ])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        r#"attachment; filename="subscribers.csv""#
+    );
+    let body = response.text().await.unwrap();
+    app.cleanup(&EMAILS).await;
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec![
+            "email",
+            "name",
+            "status",
+            "subscribed_at",
+            "lists",
+            "tags",
+            "attributes"
+        ]
+    );
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 2);
+    let ursula = records.iter().find(|r| &r[0] == EMAILS[0]).unwrap();
+    assert_eq!(&ursula[2], "confirmed");
+    assert_eq!(&ursula[4], "newsletter");
+    assert_eq!(&ursula[5], format!("{};vip", tag));
+    let octavia = records.iter().find(|r| &r[0] == EMAILS[1]).unwrap();
+    assert_eq!(&octavia[1], "Butler, Octavia");
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn subscribers_are_exported_as_ndjson() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let tag = unique_tag();
+    create_tagged_subscribers(&app, &tag).await;
+
+    // Act
+    let response = app
+        .get_export_subscribers_download(&[("format", "ndjson"), ("tag", &tag)])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    app.cleanup(&EMAILS).await;
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows
+        .iter()
+        .all(|r| r["lists"] == serde_json::json!(["newsletter"])));
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn export_filters_are_applied() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let tag = unique_tag();
+    create_tagged_subscribers(&app, &tag).await;
+
+    // Act
+    let pending = app
+        .get_export_subscribers_download(&[
+            ("format", "ndjson"),
+            ("status", "pending_confirmation"),
+            ("tag", &tag),
+        ])
+        .await
+        .text()
+        .await
+        .unwrap();
+    let future = app
+        .get_export_subscribers_download(&[
+            ("format", "ndjson"),
+            ("tag", &tag),
+            ("subscribed_since", "2999-01-01"),
+        ])
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    app.cleanup(&EMAILS).await;
+    assert_eq!(pending, "");
+    assert_eq!(future, "");
+}
+
+#[tokio::test]
+async fn invalid_export_filters_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (vec![("format", "xml")], "unknown format"),
+        (
+            vec![("format", "csv"), ("status", "deleted")],
+            "unknown status",
+        ),
+        (
+            vec![("format", "csv"), ("status", "unsubscribed")],
+            "unsubscribed without a list",
+        ),
+        (
+            vec![("format", "csv"), ("tag", "not a tag!")],
+            "invalid tag",
+        ),
+        (
+            vec![("format", "csv"), ("subscribed_before", "yesterday")],
+            "invalid date",
+        ),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = app.get_export_subscribers_download(&query).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The export did not fail with 400 Bad Request when the query had an {}.",
+            description
+        );
+    }
+    app.cleanup_user().await;
+}
@@ -0,0 +1,290 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn list_statuses(app: &TestApp, emails: &[&str]) -> Vec<(String, String, Option<String>)> {
+    let emails: Vec<String> = emails.iter().map(|e| e.to_string()).collect();
+    sqlx::query!(
+        r#"
+        SELECT s.email, m.status, m.consent_source
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = ANY($1)
+        ORDER BY s.email
+        "#,
+        &emails[..]
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.email, r.status, r.consent_source))
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let form_response = app.get_import_subscribers().await;
+    let import_response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": "email\nursula_le_guin@gmail.com\n",
+            "action": "import",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&form_response, "/login");
+    assert_is_redirect_to(&import_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn a_dry_run_reports_valid_invalid_and_duplicate_rows_without_importing() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csv = "Email Address,First Name,Last Name\n\
+        ursula_le_guin_import1@gmail.com,Ursula,Le Guin\n\
+        not-an-email,Octavia,Butler\n\
+        Ursula_Le_Guin_Import1@gmail.com,Ursula,Le Guin\n";
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": csv,
+            "mode": "pending",
+            "action": "dry_run",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(
+        "<p>Detected format: Mailchimp export. 1 valid, 1 invalid and 1 duplicate rows.</p>"
+    ));
+    assert!(html_page.contains("<li>Line 3: not-an-email - "));
+    assert!(html_page.contains(
+        "<li>Line 4: Ursula_Le_Guin_Import1@gmail.com - The same address appears on line 2.</li>"
+    ));
+    // The CSV is kept so the import can follow the dry run.
+    assert!(html_page.contains("ursula_le_guin_import1@gmail.com,Ursula,Le Guin"));
+    assert!(list_statuses(&app, &["ursula_le_guin_import1@gmail.com"])
+        .await
+        .is_empty());
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn confirmed_imports_require_a_consent_source() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": "email\nursula_le_guin_import2@gmail.com\n",
+            "mode": "confirmed",
+            "consent_source": " ",
+            "action": "import",
+        }))
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(
+        "<p><i>Record where consent was collected to import subscribers as confirmed.</i></p>"
+    ));
+    assert!(list_statuses(&app, &["ursula_le_guin_import2@gmail.com"])
+        .await
+        .is_empty());
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn confirmed_imports_record_the_consent_source_and_send_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let emails = [
+        "ursula_le_guin_import3@gmail.com",
+        "ursula_le_guin_import4@gmail.com",
+    ];
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Import
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": format!("email,name\n{},Ursula\n{},\n", emails[0], emails[1]),
+            "mode": "confirmed",
+            "consent_source": "Mailchimp double opt-in",
+            "action": "import",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains(
+        "<p><i>Imported 2 subscribers as confirmed. \
+        0 invalid and 0 duplicate rows were skipped.</i></p>"
+    ));
+
+    // Assert
+    let statuses = list_statuses(&app, &emails).await;
+    app.cleanup(&emails).await;
+    for (i, (email, status, consent_source)) in statuses.into_iter().enumerate() {
+        assert_eq!(email, emails[i]);
+        assert_eq!(status, "confirmed");
+        assert_eq!(consent_source.as_deref(), Some("Mailchimp double opt-in"));
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn pending_imports_queue_confirmation_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = "ursula_le_guin_import5@gmail.com";
+
+    // Act - Part 1 - Import, without sending anything yet
+    let scoped_mock = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": format!("email\n{}\n", email),
+            "mode": "pending",
+            "action": "import",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    drop(scoped_mock);
+
+    // Act - Part 2 - Send the queued confirmation emails
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_confirmation_emails().await;
+
+    // Act - Part 3 - Confirm
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let pending = list_statuses(&app, &[email]).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let confirmed = list_statuses(&app, &[email]).await;
+    app.cleanup(&[email]).await;
+    assert_eq!(pending[0].1, "pending_confirmation");
+    assert_eq!(confirmed[0].1, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribers_already_on_the_list_are_reported_as_duplicates() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = "ursula_le_guin_import6@gmail.com";
+    app.post_import_subscribers(&serde_json::json!({
+        "csv_file": format!("email\n{}\n", email),
+        "mode": "confirmed",
+        "consent_source": "Substack",
+        "action": "import",
+    }))
+    .await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": format!("email\n{}\n", email),
+            "mode": "confirmed",
+            "consent_source": "Substack",
+            "action": "dry_run",
+        }))
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    app.cleanup(&[email]).await;
+    assert!(html_page.contains(&format!(
+        "<li>Line 2: {} - The subscriber is already on this list.</li>",
+        email
+    )));
+}
+
+#[tokio::test]
+async fn the_csv_kept_after_a_dry_run_can_be_imported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = "ursula_le_guin_import7@gmail.com";
+
+    // Act - No file is uploaded the second time: the text field is used
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": "",
+            "csv": format!("email\n{}\n", email),
+            "mode": "confirmed",
+            "consent_source": "Substack",
+            "action": "import",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let statuses = list_statuses(&app, &[email]).await;
+    app.cleanup(&[email]).await;
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].1, "confirmed");
+}
+
+#[tokio::test]
+async fn files_over_the_size_limit_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let row = "ursula_le_guin_import8@gmail.com\n";
+    let csv = format!("email\n{}", row.repeat(11 * 1024 * 1024 / row.len()));
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv_file": csv,
+            "mode": "pending",
+            "action": "dry_run",
+        }))
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page
+        .contains("<p><i>The CSV is larger than 10 MB; split it into smaller files.</i></p>"));
+    app.cleanup_user().await;
+}
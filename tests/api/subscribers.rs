@@ -0,0 +1,232 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Import `email` with the given mode, returning the subscriber id.
+async fn import_subscriber(app: &TestApp, email: &str, name: &str, mode: &str) -> Uuid {
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": format!("email,name\n{},{}\n", email, name),
+        "mode": mode,
+        "consent_source": "Subscribers test",
+        "action": "import",
+    }))
+    .await;
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the imported subscriber.")
+        .id
+}
+
+async fn statuses(app: &TestApp, subscriber_id: Uuid) -> (String, Vec<String>) {
+    let address = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status;
+    let memberships = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.status)
+    .collect();
+    (address, memberships)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+
+    // Act
+    let list_response = app.get_subscribers(&[]).await;
+    let details_response = app.get_subscriber_details(subscriber_id).await;
+    let action_response = app.post_subscriber_action(subscriber_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&list_response, "/login");
+    assert_is_redirect_to(&details_response, "/login");
+    assert_is_redirect_to(&action_response, "/login");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let ursula = "ursula_le_guin_admin1@gmail.com";
+    let octavia = "octavia_butler_admin1@gmail.com";
+    let ursula_id = import_subscriber(&app, ursula, "Ursula Le Guin", "confirmed").await;
+    import_subscriber(&app, octavia, "Octavia Butler", "pending").await;
+
+    // Act
+    let by_email_html = app.get_subscribers_html(&[("search", "_ADMIN1@")]).await;
+    let by_name_html = app
+        .get_subscribers_html(&[("search", "le guin"), ("status", "confirmed")])
+        .await;
+    let pending_html = app
+        .get_subscribers_html(&[("search", "_admin1"), ("status", "pending_confirmation")])
+        .await;
+    let wildcard_html = app.get_subscribers_html(&[("search", "%")]).await;
+    let invalid_status = app.get_subscribers(&[("status", "deleted")]).await;
+
+    // Assert
+    app.cleanup_subscriptinos(ursula.into()).await;
+    app.cleanup_subscriptinos(octavia.into()).await;
+    assert!(by_email_html.contains("Showing 1-2 of 2 subscribers."));
+    assert!(by_name_html.contains(&format!(
+        r#"<a href="/admin/subscribers/{}">{}</a>"#,
+        ursula_id, ursula
+    )));
+    assert!(!by_name_html.contains(octavia));
+    assert!(pending_html.contains(octavia));
+    assert!(!pending_html.contains(ursula));
+    assert!(wildcard_html.contains("of 0 subscribers."));
+    assert_eq!(invalid_status.status().as_u16(), 400);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn admins_can_confirm_a_pending_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = "ursula_le_guin_admin2@gmail.com";
+    let subscriber_id = import_subscriber(&app, email, "Ursula Le Guin", "pending").await;
+
+    // Act - Part 1 - Confirm
+    let response = app.post_subscriber_action(subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+
+    // Act - Part 3 - Confirm again
+    app.post_subscriber_action(subscriber_id, "confirm").await;
+    let second_html_page = app.get_subscriber_details_html(subscriber_id).await;
+
+    // Assert
+    let (address, memberships) = statuses(&app, subscriber_id).await;
+    app.cleanup_subscriptinos(email.into()).await;
+    assert_eq!(address, "confirmed");
+    assert_eq!(memberships, vec!["confirmed"]);
+    assert!(html_page.contains(&format!("<p><i>{} has been confirmed.</i></p>", email)));
+    assert!(html_page.contains(&format!("Confirmed by {}", app.test_user.username)));
+    assert!(second_html_page.contains("has nothing pending confirmation"));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn admins_can_resend_the_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = "ursula_le_guin_admin3@gmail.com";
+    let subscriber_id = import_subscriber(&app, email, "Ursula Le Guin", "pending").await;
+
+    // Act
+    let response = app
+        .post_subscriber_action(subscriber_id, "resend_confirmation")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let n_queued = sqlx::query!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM confirmation_email_queue q
+        JOIN subscription_tokens t ON t.subscription_token = q.subscription_token
+        WHERE t.subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    app.cleanup_subscriptinos(email.into()).await;
+    // One from the import, one resent.
+    assert_eq!(n_queued, 2);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn admins_can_unsubscribe_and_delete_a_subscriber_and_the_actions_are_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = "ursula_le_guin_admin4@gmail.com";
+    let subscriber_id = import_subscriber(&app, email, "Ursula Le Guin", "confirmed").await;
+    // An issue is still queued for the subscriber when they are deleted.
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Act - Part 1 - Unsubscribe
+    app.post_subscriber_action(subscriber_id, "unsubscribe")
+        .await;
+    let (_, memberships) = statuses(&app, subscriber_id).await;
+    let unsubscribed_html = app
+        .get_subscribers_html(&[("search", email), ("status", "unsubscribed")])
+        .await;
+    let n_queued_before = sqlx::query!(
+        "SELECT count(*) AS n FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+
+    // Act - Part 2 - Delete
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let details_response = app.get_subscriber_details(subscriber_id).await;
+
+    // Assert
+    assert_eq!(memberships, vec!["unsubscribed"]);
+    assert_eq!(n_queued_before, Some(1));
+    assert!(unsubscribed_html.contains("Showing 1-1 of 1 subscribers."));
+    assert_eq!(details_response.status().as_u16(), 404);
+    let n_queued = sqlx::query!(
+        "SELECT count(*) AS n FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_queued, Some(0));
+    let audit_log = sqlx::query!(
+        r#"
+        SELECT action, subscriber_email FROM subscriber_audit_log
+        WHERE subscriber_id = $1 AND performed_by = $2
+        ORDER BY performed_at
+        "#,
+        subscriber_id,
+        app.test_user.id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let actions: Vec<&str> = audit_log.iter().map(|r| r.action.as_str()).collect();
+    assert_eq!(actions, vec!["unsubscribe", "delete"]);
+    assert!(audit_log.iter().all(|r| r.subscriber_email == email));
+    app.cleanup_user().await;
+}
@@ -1,6 +1,9 @@
 use crate::helpers::spawn_app;
+use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::EmailPolicy;
+use zero2prod::normalised_emails::backfill_normalised_emails;
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_request() {
@@ -169,9 +172,270 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // Get the first intercepted request
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // The two links should be identical
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
     app.cleanup_user().await;
 }
+
+#[tokio::test]
+async fn subscribe_persists_the_display_and_normalised_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin5", "email":" Ursula_Le_Guin5+news@GMail.com " }
+    "#;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT email, normalised_email FROM subscriptions WHERE normalised_email = $1",
+        "Ursula_Le_Guin5@gmail.com"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    app.cleanup_subscriptinos("Ursula_Le_Guin5+news@GMail.com".into())
+        .await;
+
+    assert_eq!(saved.email, "Ursula_Le_Guin5+news@GMail.com");
+    assert_eq!(saved.normalised_email, "Ursula_Le_Guin5@gmail.com");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribe_does_not_store_the_same_normalised_email_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_body = r#"
+        { "name":"le guin6", "email":"ursula_le_guin6@gmail.com" }
+    "#;
+    let second_body = r#"
+        { "name":"le guin6", "email":"URSULA_LE_GUIN6+again@Gmail.com" }
+    "#;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(first_body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+    let response = app.post_subscriptions(second_body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE lower(normalised_email) = $1",
+        "ursula_le_guin6@gmail.com"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscriptions.");
+    app.cleanup_subscriptinos("ursula_le_guin6@gmail.com".into())
+        .await;
+
+    assert_ne!(200, response.status().as_u16());
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula_le_guin6@gmail.com");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribe_suggests_a_correction_for_a_misspelt_domain() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin7", "email":"ursula_le_guin7@gmial.com" }
+    "#;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(422, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["suggestion"], "ursula_le_guin7@gmail.com");
+    let saved = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE email = $1",
+        "ursula_le_guin7@gmial.com"
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert!(saved.is_none());
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribe_stores_a_misspelt_domain_when_asked_to_keep_it() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin8", "email":"ursula_le_guin8@gmial.com", "keep_as_typed": true }
+    "#;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    app.cleanup_subscriptinos("ursula_le_guin8@gmial.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribe_rejects_non_ascii_addresses_when_the_transport_lacks_smtputf8() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin9", "email":"ürsula_le_guin9@bücher.de" }
+    "#;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        response.text().await.unwrap(),
+        "ürsula_le_guin9@bücher.de contains non-ASCII characters, \
+        which our email provider cannot deliver to."
+    );
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribe_persists_the_subscriber_attributes() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin10", "email":"ursula_le_guin10@gmail.com", "attributes": { "company": "Acme" } }
+    "#;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT attributes FROM subscriptions WHERE email = $1",
+        "ursula_le_guin10@gmail.com"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    app.cleanup_subscriptinos("ursula_le_guin10@gmail.com".into())
+        .await;
+
+    assert_eq!(saved.attributes, serde_json::json!({ "company": "Acme" }));
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_attributes_are_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = r#"
+        { "name":"le guin11", "email":"ursula_le_guin11@gmail.com", "attributes": ["Acme"] }
+    "#;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn the_backfill_merges_idn_subscriptions_that_were_normalised_in_sql() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // The SQL backfill only lowercased the domain of historical entries.
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, normalised_email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, 'le guin idn', now() - interval '1 day', 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        "ursula_le_guin_idn@Bücher.de",
+        "ursula_le_guin_idn@bücher.de",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let body = r#"
+        { "name":"le guin idn", "email":"ursula_le_guin_idn@bücher.de" }
+    "#;
+    app.post_subscriptions(body.into()).await;
+
+    // Act
+    backfill_normalised_emails(&app.db_pool, &EmailPolicy::default())
+        .await
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT email, normalised_email, status FROM subscriptions WHERE email ILIKE $1",
+        "ursula_le_guin_idn@%"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscriptions.");
+    app.post_subscriptions(body.into()).await;
+    let n_saved = sqlx::query!(
+        "SELECT count(*) AS \"count!\" FROM subscriptions WHERE email ILIKE $1",
+        "ursula_le_guin_idn@%"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    app.cleanup_subscriptinos("ursula_le_guin_idn@Bücher.de".into())
+        .await;
+
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula_le_guin_idn@Bücher.de");
+    assert_eq!(
+        saved[0].normalised_email,
+        "ursula_le_guin_idn@xn--bcher-kva.de"
+    );
+    assert_eq!(saved[0].status, "confirmed");
+    assert_eq!(n_saved, 1);
+    app.cleanup_user().await;
+}
@@ -0,0 +1,135 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::routes::unsubscribe_url;
+
+/// Subscribe `email` and return the link to leave the list it joined.
+async fn subscribe(app: &TestApp, email: &str) -> (Uuid, Uuid, String) {
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(serde_json::json!({ "name": "le guin", "email": email }).to_string())
+        .await;
+    let membership = sqlx::query!(
+        r#"
+        SELECT m.subscriber_id, m.list_id
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    let link = unsubscribe_url(
+        &app.address,
+        &app.hmac_secret,
+        membership.subscriber_id,
+        membership.list_id,
+    );
+    (membership.subscriber_id, membership.list_id, link)
+}
+
+async fn membership_status(app: &TestApp, subscriber_id: Uuid, list_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM list_memberships WHERE subscriber_id = $1 AND list_id = $2",
+        subscriber_id,
+        list_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+#[tokio::test]
+async fn unsubscribe_links_with_an_invalid_token_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&list_id={}&token=deadbeef",
+        app.address,
+        Uuid::new_v4(),
+        Uuid::new_v4()
+    );
+
+    // Act
+    let get_response = reqwest::get(&link).await.unwrap();
+    let post_response = app.api_client.post(&link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn following_the_unsubscribe_link_only_asks_for_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula_le_guin_unsubscribe@gmail.com";
+    let (subscriber_id, list_id, link) = subscribe(&app, email).await;
+
+    // Act
+    let response = reqwest::get(&link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    let status = membership_status(&app, subscriber_id, list_id).await;
+    app.cleanup_subscriptinos(email.into()).await;
+    assert!(html.contains(&format!(
+        r#"<form action="/subscriptions/unsubscribe?subscriber_id={}&amp;list_id={}&amp;token="#,
+        subscriber_id, list_id
+    )));
+    assert!(html.contains(r#"method="post""#));
+    assert_eq!(status, "pending_confirmation");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn confirming_on_the_unsubscribe_page_unsubscribes_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula_le_guin_unsubscribe2@gmail.com";
+    let (subscriber_id, list_id, link) = subscribe(&app, email).await;
+
+    // Act
+    let response = app.api_client.post(&link).send().await.unwrap();
+
+    // Assert
+    let status = membership_status(&app, subscriber_id, list_id).await;
+    app.cleanup_subscriptinos(email.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(status, "unsubscribed");
+    app.cleanup_user().await;
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_requests_unsubscribe_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula_le_guin_unsubscribe3@gmail.com";
+    let (subscriber_id, list_id, link) = subscribe(&app, email).await;
+
+    // Act - As sent by mail clients, see RFC 8058
+    let response = app
+        .api_client
+        .post(&link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let status = membership_status(&app, subscriber_id, list_id).await;
+    app.cleanup_subscriptinos(email.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(status, "unsubscribed");
+    app.cleanup_user().await;
+}